@@ -0,0 +1,456 @@
+/* Approval testing: a response approved on review (`--approve` writes it to
+    `approved/<case_id>.json`) is the one the later runs must match.  A test case with an
+    approved file fails on any difference, reported as a unified diff.  The responses are
+    normalized before being written or compared, as set with `approvals` in the config:
+    dir:          directory of the approved files (`approved` by default).
+    sort_keys:    sort the keys of the objects (true by default).
+    ignore_paths: volatile values dropped from the body, as JSONPaths with wildcards and
+                  recursive descent, ex: `$.items[*].createdAt`, `$..id`.
+    headers:      approve the response headers too (false by default), but the volatile ones
+                  (`Date`, ..) and the `ignore_headers`.
+*/
+
+use serde::Deserialize;
+use serde_json::Value;
+use similar::TextDiff;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApprovalConfig {
+    pub dir: Option<String>,
+    #[serde(default = "default_sort_keys")]
+    pub sort_keys: bool,
+    #[serde(default)]
+    pub ignore_paths: Vec<String>,
+    #[serde(default)]
+    pub headers: bool,
+    #[serde(default)]
+    pub ignore_headers: Vec<String>,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        ApprovalConfig {
+            dir: None,
+            sort_keys: default_sort_keys(),
+            ignore_paths: vec![],
+            headers: false,
+            ignore_headers: vec![],
+        }
+    }
+}
+
+fn default_sort_keys() -> bool {
+    true
+}
+
+// Headers which change from one response to the next, or with the connection.
+const VOLATILE_HEADERS: [&str; 12] = [
+    "date",
+    "age",
+    "expires",
+    "last-modified",
+    "set-cookie",
+    "content-length",
+    "transfer-encoding",
+    "connection",
+    "keep-alive",
+    "traceparent",
+    "x-request-id",
+    "x-correlation-id",
+];
+
+#[derive(Debug, Clone)]
+pub struct Approvals {
+    dir: PathBuf,
+    approve: bool, // Write the responses, instead of comparing them.
+    sort_keys: bool,
+    ignore_paths: Vec<String>,
+    headers: bool,
+    ignore_headers: Vec<String>, // Lowercased.
+}
+
+impl Approvals {
+    pub fn new(config: Option<&ApprovalConfig>, approve: bool) -> Self {
+        let config = config.cloned().unwrap_or_default();
+        Approvals {
+            dir: PathBuf::from(config.dir.as_deref().unwrap_or("approved")),
+            approve,
+            sort_keys: config.sort_keys,
+            ignore_paths: config.ignore_paths,
+            headers: config.headers,
+            ignore_headers: config
+                .ignore_headers
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+        }
+    }
+
+    fn path(&self, id: u32) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    // True if the response of the test case is to be approved, or checked against an approved one.
+    pub fn applies_to(&self, id: u32) -> bool {
+        self.approve || self.path(id).exists()
+    }
+
+    // Approves the response (`{status, headers, body}`) of the test case, or compares it with the
+    // approved one. Returns the unified diff (approved to received) on a mismatch.
+    pub fn check(&self, id: u32, response: &Value) -> Result<(), String> {
+        let path = self.path(id);
+        let received = self.normalize(response);
+        if self.approve {
+            fs::create_dir_all(&self.dir)
+                .and_then(|_| fs::write(&path, to_pretty(&received) + "\n"))
+                .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))?;
+            println!("Response approved to: {}", path.display());
+            return Ok(());
+        }
+
+        let approved = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+        let approved: Value = serde_json::from_str(&approved)
+            .map_err(|e| format!("Invalid approved file '{}': {}", path.display(), e))?;
+        // The approved one is normalized again, for the rules added since it was approved.
+        let (approved, received) = (to_pretty(&self.normalize(&approved)), to_pretty(&received));
+        if approved == received {
+            return Ok(());
+        }
+        Err(TextDiff::from_lines(&approved, &received)
+            .unified_diff()
+            .header(&path.display().to_string(), "received")
+            .to_string())
+    }
+
+    fn normalize(&self, response: &Value) -> Value {
+        let mut response = response.clone();
+        if let Some(response) = response.as_object_mut() {
+            match response.get_mut("headers").and_then(Value::as_object_mut) {
+                Some(headers) if self.headers => headers.retain(|name, _| {
+                    let name = name.to_lowercase();
+                    !VOLATILE_HEADERS.contains(&name.as_str())
+                        && !self.ignore_headers.contains(&name)
+                }),
+                _ => {
+                    response.shift_remove("headers");
+                }
+            }
+        }
+        if let Some(body) = response.get_mut("body") {
+            for path in &self.ignore_paths {
+                drop_path(body, &parse_path(path));
+            }
+        }
+        if self.sort_keys {
+            sort_keys(&mut response);
+        }
+        response
+    }
+}
+
+fn to_pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+// A step of a path: a key, an index or `*`, of the value itself (`.key`, `[0]`, `[*]`), or of
+// the value and all its descendants (`..key`).
+#[derive(Debug, PartialEq)]
+enum Step<'a> {
+    Child(&'a str),
+    Descendant(&'a str),
+}
+
+// Parses a JSONPath like `$.items[*].createdAt` or `$..id` into its steps.
+fn parse_path(path: &str) -> Vec<Step<'_>> {
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+    let mut steps = vec![];
+    while !rest.is_empty() {
+        let descendant = rest.starts_with("..");
+        rest = rest
+            .strip_prefix(if descendant { ".." } else { "." })
+            .unwrap_or(rest);
+        let (selector, tail) = match rest.strip_prefix('[') {
+            Some(inner) => {
+                let end = inner.find(']').unwrap_or(inner.len());
+                let selector = inner[..end].trim_matches(['\'', '"']);
+                (selector, inner.get(end + 1..).unwrap_or_default())
+            }
+            None => rest.split_at(rest.find(['.', '[']).unwrap_or(rest.len())),
+        };
+        if !selector.is_empty() {
+            steps.push(match descendant {
+                true => Step::Descendant(selector),
+                false => Step::Child(selector),
+            });
+        }
+        rest = tail;
+    }
+    steps
+}
+
+// Removes the value(s) at the path.
+fn drop_path(value: &mut Value, path: &[Step]) {
+    match path.split_first() {
+        None => {}
+        Some((Step::Child(selector), rest)) => drop_selected(value, selector, rest),
+        Some((Step::Descendant(selector), rest)) => {
+            drop_selected(value, selector, rest);
+            match value {
+                Value::Object(map) => map.values_mut().for_each(|v| drop_path(v, path)),
+                Value::Array(items) => items.iter_mut().for_each(|v| drop_path(v, path)),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Removes the children matching the selector (a key, an index or `*`), or the values at the
+// rest of the path under them.
+fn drop_selected(value: &mut Value, selector: &str, rest: &[Step]) {
+    if !rest.is_empty() {
+        match value {
+            Value::Object(map) if selector == "*" => {
+                map.values_mut().for_each(|v| drop_path(v, rest))
+            }
+            Value::Object(map) => map
+                .get_mut(selector)
+                .into_iter()
+                .for_each(|v| drop_path(v, rest)),
+            Value::Array(items) if selector == "*" => {
+                items.iter_mut().for_each(|v| drop_path(v, rest))
+            }
+            Value::Array(items) => {
+                let item = selector
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| items.get_mut(i));
+                item.into_iter().for_each(|v| drop_path(v, rest))
+            }
+            _ => {}
+        }
+        return;
+    }
+    match value {
+        Value::Object(map) if selector == "*" => map.clear(),
+        Value::Object(map) => {
+            map.shift_remove(selector);
+        }
+        Value::Array(items) if selector == "*" => items.clear(),
+        Value::Array(items) => {
+            if let Some(index) = selector.parse::<usize>().ok().filter(|i| *i < items.len()) {
+                items.remove(index);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.sort_keys();
+            map.values_mut().for_each(sort_keys);
+        }
+        Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn approvals(name: &str, approve: bool) -> Approvals {
+        let dir = std::env::temp_dir().join(format!("approved_{}_{}", name, std::process::id()));
+        let config = ApprovalConfig {
+            dir: Some(dir.to_string_lossy().to_string()),
+            ignore_paths: vec!["$.id".to_string(), "$.items[*].createdAt".to_string()],
+            ..ApprovalConfig::default()
+        };
+        Approvals::new(Some(&config), approve)
+    }
+
+    fn with_headers(response: Value, cache_control: &str) -> Value {
+        let mut response = response;
+        response["headers"] = json!({
+            "date": "Mon, 01 Jan 2024 10:00:00 GMT",
+            "cache-control": cache_control,
+            "access-control-allow-origin": "*",
+            "x-served-by": "cache-1",
+        });
+        response
+    }
+
+    fn response(id: &str, total: u32) -> Value {
+        json!({
+            "status": 200,
+            "body": {
+                "total": total,
+                "id": id,
+                "items": [{ "name": "pen", "createdAt": id }],
+            },
+        })
+    }
+
+    #[test]
+    fn test_approve_then_match() {
+        let approvals = approvals("match", true);
+        // Nothing to compare with, until approved.
+        assert!(!approvals.path(1).exists());
+        approvals.check(1, &response("a1", 3)).unwrap();
+
+        // The approved file is normalized: sorted keys, without the volatile values.
+        let approved: Value =
+            serde_json::from_str(&fs::read_to_string(approvals.path(1)).unwrap()).unwrap();
+        assert_eq!(
+            approved,
+            json!({ "body": { "items": [{ "name": "pen" }], "total": 3 }, "status": 200 })
+        );
+        let keys: Vec<&String> = approved["body"].as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["items", "total"]);
+
+        // A later run matches, whatever the volatile values.
+        let compare = Approvals {
+            approve: false,
+            ..approvals.clone()
+        };
+        assert!(compare.applies_to(1));
+        assert!(!compare.applies_to(2));
+        assert_eq!(compare.check(1, &response("b2", 3)), Ok(()));
+        fs::remove_dir_all(&approvals.dir).unwrap();
+    }
+
+    #[test]
+    fn test_mismatch_diff() {
+        let approvals = approvals("mismatch", true);
+        approvals.check(1, &response("a1", 3)).unwrap();
+
+        let compare = Approvals {
+            approve: false,
+            ..approvals.clone()
+        };
+        let diff = compare.check(1, &response("a1", 4)).unwrap_err();
+        assert!(diff.contains("+++ received"), "{}", diff);
+        assert!(diff.contains("-    \"total\": 3"), "{}", diff);
+        assert!(diff.contains("+    \"total\": 4"), "{}", diff);
+        fs::remove_dir_all(&approvals.dir).unwrap();
+    }
+
+    #[test]
+    fn test_drop_path() {
+        let mut value =
+            json!({ "a": { "b": 1, "c": 2 }, "list": [{ "x": 1 }, { "x": 2, "y": 3 }] });
+        drop_path(&mut value, &parse_path("$.a.b"));
+        drop_path(&mut value, &parse_path("$.list[1].y"));
+        drop_path(&mut value, &parse_path("$.list[*].x"));
+        assert_eq!(value, json!({ "a": { "c": 2 }, "list": [{}, {}] }));
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("$.items[*].createdAt"),
+            vec![
+                Step::Child("items"),
+                Step::Child("*"),
+                Step::Child("createdAt")
+            ]
+        );
+        assert_eq!(parse_path("$..id"), vec![Step::Descendant("id")]);
+        assert_eq!(
+            parse_path("$['meta'].list[0]..['trace-id']"),
+            vec![
+                Step::Child("meta"),
+                Step::Child("list"),
+                Step::Child("0"),
+                Step::Descendant("trace-id")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drop_from_every_item() {
+        let mut value = json!({ "items": [
+            { "name": "pen", "createdAt": "2024-01-01" },
+            { "name": "ink", "createdAt": "2024-01-02" },
+        ] });
+        drop_path(&mut value, &parse_path("$.items[*].createdAt"));
+        assert_eq!(
+            value,
+            json!({ "items": [{ "name": "pen" }, { "name": "ink" }] })
+        );
+    }
+
+    #[test]
+    fn test_drop_recursively() {
+        let mut value = json!({
+            "id": 1,
+            "order": { "id": 2, "lines": [{ "id": 3, "sku": "a" }, { "sku": "b" }] },
+            "tags": ["id"],
+        });
+        drop_path(&mut value, &parse_path("$..id"));
+        assert_eq!(
+            value,
+            json!({ "order": { "lines": [{ "sku": "a" }, { "sku": "b" }] }, "tags": ["id"] })
+        );
+    }
+
+    #[test]
+    fn test_approve_headers() {
+        let config = ApprovalConfig {
+            dir: Some(
+                std::env::temp_dir()
+                    .join(format!("approved_headers_{}", std::process::id()))
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+            headers: true,
+            ignore_headers: vec!["X-Served-By".to_string()],
+            ..ApprovalConfig::default()
+        };
+        let approvals = Approvals::new(Some(&config), true);
+        approvals
+            .check(1, &with_headers(response("a1", 3), "max-age=60"))
+            .unwrap();
+
+        // Only the stable headers are approved.
+        let approved: Value =
+            serde_json::from_str(&fs::read_to_string(approvals.path(1)).unwrap()).unwrap();
+        assert_eq!(
+            approved["headers"],
+            json!({ "access-control-allow-origin": "*", "cache-control": "max-age=60" })
+        );
+
+        let compare = Approvals::new(Some(&config), false);
+        let mut later = with_headers(response("a1", 3), "max-age=60");
+        later["headers"]["date"] = json!("Tue, 02 Jan 2024 11:00:00 GMT");
+        assert_eq!(compare.check(1, &later), Ok(()));
+
+        let diff = compare
+            .check(1, &with_headers(response("a1", 3), "no-store"))
+            .unwrap_err();
+        assert!(
+            diff.contains("-    \"cache-control\": \"max-age=60\""),
+            "{}",
+            diff
+        );
+        assert!(
+            diff.contains("+    \"cache-control\": \"no-store\""),
+            "{}",
+            diff
+        );
+
+        // Without `headers`, they are left out.
+        let body_only = Approvals::new(None, false);
+        assert_eq!(
+            body_only.normalize(&with_headers(response("a1", 3), "no-store"))["headers"],
+            Value::Null
+        );
+        fs::remove_dir_all(&approvals.dir).unwrap();
+    }
+}
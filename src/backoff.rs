@@ -0,0 +1,94 @@
+/* Strategies for the wait between the attempts of a test case (ex: while polling), set with
+    `backoff` in the config:
+    fixed:       the base interval every time.
+    linear:      the base interval, growing by `step_ms` with each attempt.
+    exponential: the base interval, multiplied by `factor` with each attempt, up to `max_ms`.
+*/
+
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BackoffStrategy {
+    #[default]
+    Fixed,
+    Linear {
+        step_ms: u64,
+    },
+    Exponential {
+        factor: f64,
+        max_ms: u64,
+    },
+}
+
+impl BackoffStrategy {
+    // Returns the wait after the given attempt (1-based), for the given base interval.
+    pub fn delay_for(&self, attempt: u32, base: Duration) -> Duration {
+        let n = attempt.saturating_sub(1);
+        match self {
+            BackoffStrategy::Fixed => base,
+            BackoffStrategy::Linear { step_ms } => {
+                base + Duration::from_millis(step_ms.saturating_mul(n as u64))
+            }
+            BackoffStrategy::Exponential { factor, max_ms } => {
+                let ms = base.as_millis() as f64 * factor.powi(n as i32);
+                Duration::from_millis(ms.min(*max_ms as f64) as u64)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delays(strategy: &BackoffStrategy, base_ms: u64) -> Vec<u64> {
+        (1..=5)
+            .map(|attempt| {
+                strategy
+                    .delay_for(attempt, Duration::from_millis(base_ms))
+                    .as_millis() as u64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fixed() {
+        assert_eq!(
+            delays(&BackoffStrategy::Fixed, 500),
+            vec![500, 500, 500, 500, 500]
+        );
+    }
+
+    #[test]
+    fn test_linear() {
+        let strategy = BackoffStrategy::Linear { step_ms: 250 };
+        assert_eq!(delays(&strategy, 500), vec![500, 750, 1000, 1250, 1500]);
+    }
+
+    #[test]
+    fn test_exponential_capped() {
+        let strategy = BackoffStrategy::Exponential {
+            factor: 2.0,
+            max_ms: 5000,
+        };
+        assert_eq!(delays(&strategy, 500), vec![500, 1000, 2000, 4000, 5000]);
+    }
+
+    #[test]
+    fn test_from_yaml() {
+        let strategy: BackoffStrategy =
+            serde_yaml::from_str("type: exponential\nfactor: 1.5\nmax_ms: 3000\n").unwrap();
+        assert_eq!(
+            strategy,
+            BackoffStrategy::Exponential {
+                factor: 1.5,
+                max_ms: 3000
+            }
+        );
+        let strategy: BackoffStrategy =
+            serde_yaml::from_str("type: linear\nstep_ms: 100\n").unwrap();
+        assert_eq!(strategy, BackoffStrategy::Linear { step_ms: 100 });
+    }
+}
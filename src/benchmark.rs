@@ -0,0 +1,146 @@
+/* Benchmark mode: sends the request of a single test case a given no. of times, with a given
+    no. of them in flight, and reports the latency percentiles and the error rate. The
+    individual failures are only counted, they are not reported as test case failures.
+*/
+
+use crate::config::Config;
+use crate::test_case::TestCase;
+use crate::test_context::TestCtx;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct BenchmarkReport {
+    pub name: String,
+    pub count: usize,
+    pub concurrency: usize,
+    pub errors: usize,
+    pub elapsed: Duration,
+    latencies: Vec<Duration>, // Sorted, fastest first.
+}
+
+impl BenchmarkReport {
+    pub fn new(
+        name: &str,
+        concurrency: usize,
+        errors: usize,
+        elapsed: Duration,
+        mut latencies: Vec<Duration>,
+    ) -> Self {
+        latencies.sort();
+        BenchmarkReport {
+            name: name.to_string(),
+            count: latencies.len(),
+            concurrency,
+            errors,
+            elapsed,
+            latencies,
+        }
+    }
+
+    // Latency at the given percentile (0-100), by the nearest rank.
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (p / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.errors as f64 * 100.0 / self.count as f64
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        writeln!(f, "Benchmark: {}", self.name)?;
+        writeln!(
+            f,
+            "  count: {}, concurrency: {}, errors: {} ({:.1}%)",
+            self.count,
+            self.concurrency,
+            self.errors,
+            self.error_rate()
+        )?;
+        writeln!(
+            f,
+            "  latency (ms): min: {:.1}, p50: {:.1}, p90: {:.1}, p99: {:.1}, max: {:.1}",
+            ms(self.percentile(0.0)),
+            ms(self.percentile(50.0)),
+            ms(self.percentile(90.0)),
+            ms(self.percentile(99.0)),
+            ms(self.percentile(100.0))
+        )?;
+        write!(
+            f,
+            "  throughput: {:.1} req/s",
+            self.count as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+        )
+    }
+}
+
+// Sends the request of the test case `count` times, with up to `concurrency` in flight. The
+// requests are prepared (and the responses checked) on this thread, like the regular runs.
+pub fn run(
+    tc: &mut TestCase,
+    ts_ctx: &mut TestCtx,
+    count: usize,
+    concurrency: usize,
+    config: &Config,
+) -> BenchmarkReport {
+    let requests: Vec<_> = (0..count as u32)
+        .map(|iteration| tc.prepare_benchmark(ts_ctx, config, iteration))
+        .collect();
+
+    let start = Instant::now();
+    let mut latencies = Vec::with_capacity(count);
+    let mut errors = 0;
+    ts_ctx.send_each(requests, concurrency, |ctx, _, response, latency| {
+        latencies.push(latency);
+        if tc.is_benchmark_error(ctx, response, config) {
+            errors += 1;
+        }
+    });
+    BenchmarkReport::new(&tc.name, concurrency, errors, start.elapsed(), latencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::concurrency_server;
+
+    #[test]
+    fn test_benchmark_report() {
+        let (url, _) = concurrency_server(Duration::from_millis(20));
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let mut tc = TestCase::dummy();
+        tc.url = format!("{}/health", url);
+
+        let report = run(&mut tc, &mut ts_ctx, 6, 3, &config);
+        assert_eq!(report.count, 6);
+        assert_eq!(report.errors, 0);
+        assert!(report.percentile(50.0) >= Duration::from_millis(20));
+
+        let printed = report.to_string();
+        assert!(printed.contains("count: 6"), "{}", printed);
+        assert!(printed.contains("errors: 0"), "{}", printed);
+        assert!(printed.contains("p50: "), "{}", printed);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let latencies = (1..=10).map(Duration::from_millis).collect();
+        let report = BenchmarkReport::new("t", 1, 1, Duration::from_secs(1), latencies);
+        assert_eq!(report.percentile(50.0), Duration::from_millis(5));
+        assert_eq!(report.percentile(90.0), Duration::from_millis(9));
+        assert_eq!(report.percentile(100.0), Duration::from_millis(10));
+        assert_eq!(report.percentile(0.0), Duration::from_millis(1));
+        assert_eq!(report.error_rate(), 10.0);
+    }
+}
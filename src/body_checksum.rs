@@ -0,0 +1,91 @@
+/* A checksum of the request body, sent as a header (ex: `Content-MD5`, or the
+    `x-amz-content-sha256` of S3), set with `body_checksum` in the config, or `bodyChecksum`
+    in the config of a test case:
+    header:    name of the header.
+    algorithm: md5 or sha256.
+    encoding:  hex (default) or base64.
+*/
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::Md5;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha256,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BodyChecksum {
+    pub header: String,
+    pub algorithm: ChecksumAlgorithm,
+    #[serde(default)]
+    pub encoding: ChecksumEncoding,
+}
+
+impl BodyChecksum {
+    // The value of the header, for the given (final, as sent) body.
+    pub fn value_for(&self, body: &[u8]) -> String {
+        let digest = match self.algorithm {
+            ChecksumAlgorithm::Md5 => Md5::digest(body).to_vec(),
+            ChecksumAlgorithm::Sha256 => Sha256::digest(body).to_vec(),
+        };
+        match self.encoding {
+            ChecksumEncoding::Hex => digest.iter().map(|b| format!("{:02x}", b)).collect(),
+            ChecksumEncoding::Base64 => STANDARD.encode(digest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum(algorithm: ChecksumAlgorithm, encoding: ChecksumEncoding) -> BodyChecksum {
+        BodyChecksum {
+            header: "X-Checksum".to_string(),
+            algorithm,
+            encoding,
+        }
+    }
+
+    #[test]
+    fn test_known_values() {
+        let md5 = checksum(ChecksumAlgorithm::Md5, ChecksumEncoding::Hex);
+        assert_eq!(md5.value_for(b"hello"), "5d41402abc4b2a76b9719d911017c592");
+        let md5 = checksum(ChecksumAlgorithm::Md5, ChecksumEncoding::Base64);
+        assert_eq!(md5.value_for(b"hello"), "XUFAKrxLKna5cZ2REBfFkg==");
+
+        let sha256 = checksum(ChecksumAlgorithm::Sha256, ChecksumEncoding::Hex);
+        assert_eq!(
+            sha256.value_for(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_from_yaml() {
+        let checksum: BodyChecksum =
+            serde_yaml::from_str("header: Content-MD5\nalgorithm: md5\nencoding: base64\n")
+                .unwrap();
+        assert_eq!(checksum.algorithm, ChecksumAlgorithm::Md5);
+        assert_eq!(checksum.encoding, ChecksumEncoding::Base64);
+
+        // Hex by default.
+        let checksum: BodyChecksum =
+            serde_yaml::from_str("header: x-amz-content-sha256\nalgorithm: sha256\n").unwrap();
+        assert_eq!(checksum.encoding, ChecksumEncoding::Hex);
+    }
+}
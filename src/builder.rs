@@ -0,0 +1,268 @@
+/* Builds a suite in code, instead of loading it from an excel file, ex: for generated tests:
+
+    let summary = SuiteBuilder::new()
+        .base_url("http://localhost:3000")
+        .group("Login")
+        .case(TestCaseData { id: 1, url: "/login".into(), ..TestCaseData::default() })
+        .run()?;
+
+    The test cases are turned into the rows of a worksheet, so they run through the same
+    pipeline as the ones read from excel (groups, authorizers, templates, events, ..).
+*/
+
+use crate::config::Config;
+use crate::run_summary::RunSummary;
+use crate::test_suite::TestSuite;
+use crate::transport::Transport;
+use calamine::Data;
+use serde_json::Value;
+use std::error::Error;
+use std::sync::mpsc::channel;
+
+// No. of columns of a test case row (up to `accept_variants`).
+const ROW_LEN: usize = 19;
+
+// The cells of a test case, as in the columns of a worksheet.
+#[derive(Debug, Clone)]
+pub struct TestCaseData {
+    pub id: u32,
+    pub name: String,
+    pub given: String,
+    pub when: String,
+    pub then: String,
+    pub url: String, // Absolute, or relative to the base url.
+    pub method: String,
+    pub headers: Vec<(String, String)>,
+    pub payload: String,
+    pub config: Option<Value>, // Test case config, ex: `{"authType": "authorizer"}`.
+    pub pre_test_script: Option<String>,
+    pub post_test_script: Option<String>,
+    pub expected_sha256: Option<String>,
+    pub notes: Option<String>,
+    pub final_script: Option<String>,
+    pub expected_json: Option<Value>,
+    pub expected_error: Option<String>, // A text in the body, or a `{"path": ..}` json.
+    pub accept_variants: Vec<String>,   // Media types the case is run with, one run per type.
+}
+
+impl Default for TestCaseData {
+    fn default() -> Self {
+        TestCaseData {
+            id: 0,
+            name: String::new(),
+            given: String::new(),
+            when: String::new(),
+            then: String::new(),
+            url: String::new(),
+            method: "GET".to_string(),
+            headers: Vec::new(),
+            payload: String::new(),
+            config: None,
+            pre_test_script: None,
+            post_test_script: None,
+            expected_sha256: None,
+            notes: None,
+            final_script: None,
+            expected_json: None,
+            expected_error: None,
+            accept_variants: Vec::new(),
+        }
+    }
+}
+
+impl TestCaseData {
+    // The worksheet row of the test case.
+    fn to_row(&self) -> Vec<Data> {
+        let text = |s: &str| match s {
+            "" => Data::Empty,
+            s => Data::String(s.to_string()),
+        };
+        let optional = |s: &Option<String>| s.as_deref().map_or(Data::Empty, text);
+        let json = |v: &Option<Value>| v.as_ref().map_or(Data::Empty, |v| text(&v.to_string()));
+        let headers = self
+            .headers
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut row = vec![
+            Data::Float(self.id as f64),
+            // The descriptions are always set, an empty cell is an invalid row.
+            Data::String(self.name.clone()),
+            Data::String(self.given.clone()),
+            Data::String(self.when.clone()),
+            Data::String(self.then.clone()),
+            text(&self.url),
+            text(&self.method),
+            text(&headers),
+            text(&self.payload),
+            json(&self.config),
+            optional(&self.pre_test_script),
+            optional(&self.post_test_script),
+            optional(&self.expected_sha256),
+            optional(&self.notes),
+            optional(&self.final_script),
+            json(&self.expected_json),
+        ];
+        row.resize(ROW_LEN, Data::Empty);
+        row[17] = optional(&self.expected_error);
+        row[18] = text(&self.accept_variants.join(", "));
+        row
+    }
+}
+
+pub struct SuiteBuilder {
+    name: String,
+    config: Config,
+    rows: Vec<Vec<Data>>, // Starting with the (empty) header row.
+}
+
+impl SuiteBuilder {
+    pub fn new() -> Self {
+        SuiteBuilder {
+            name: "Suite".to_string(),
+            config: Config::default(),
+            rows: vec![vec![Data::Empty; ROW_LEN]],
+        }
+    }
+
+    // Name of the suite, as the worksheet name in the reports.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    // Replaces the config the suite is run with (its base url is kept, if set earlier).
+    pub fn config(mut self, config: Config) -> Self {
+        let base_url = self.config.base_url.take();
+        self.config = config;
+        if self.config.base_url.is_none() {
+            self.config.base_url = base_url;
+        }
+        self
+    }
+
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.config.base_url = Some(base_url.to_string());
+        self
+    }
+
+    // Starts a group, the following test cases belong to it.
+    pub fn group(mut self, name: &str) -> Self {
+        let mut row = vec![Data::String(format!("Group: {}", name))];
+        row.resize(ROW_LEN, Data::Empty);
+        self.rows.push(row);
+        self
+    }
+
+    // Adds a test case to the current group (a "Default" group, if none was started).
+    pub fn case(mut self, case: TestCaseData) -> Self {
+        if self.rows.len() == 1 {
+            self = self.group("Default");
+        }
+        self.rows.push(case.to_row());
+        self
+    }
+
+    // Runs the suite, and returns the tally of its test cases. A failed test case is only
+    // counted in the summary, the error is returned if the suite could not be run.
+    pub fn run(self) -> Result<RunSummary, Box<dyn Error>> {
+        let (tx, rx) = channel();
+        let mut ts = TestSuite::with_transport(Transport::from_config(&self.config)?);
+        let rows: Vec<&[Data]> = self.rows.iter().map(Vec::as_slice).collect();
+        let result = ts.exec_sheet(&rows, &self.name, &self.config, &tx);
+
+        let mut summary = RunSummary::default();
+        for event in rx.try_iter() {
+            summary.on_event(&event);
+        }
+        match result {
+            Err(e) if summary.failed == 0 => Err(e),
+            _ => Ok(summary),
+        }
+    }
+}
+
+impl Default for SuiteBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::sequence_server;
+
+    #[test]
+    fn test_two_case_group() {
+        let (url, requests) = sequence_server(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"token\":\"t-123\"}",
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 11\r\nConnection: close\r\n\r\n{\"id\":\"u1\"}",
+        ]);
+        let config = Config {
+            token_key: Some("token".to_string()),
+            ..Config::default()
+        };
+
+        let summary = SuiteBuilder::new()
+            .config(config)
+            .base_url(&url)
+            .group("Login")
+            .case(TestCaseData {
+                id: 1,
+                name: "Login".to_string(),
+                url: "/login".to_string(),
+                method: "POST".to_string(),
+                payload: r#"{"user": "ann"}"#.to_string(),
+                config: Some(serde_json::json!({ "authType": "authorizer" })),
+                post_test_script: Some(
+                    "SAT.tester('200', () => SAT.response.status === 200)".to_string(),
+                ),
+                ..TestCaseData::default()
+            })
+            .case(TestCaseData {
+                id: 2,
+                name: "Get profile".to_string(),
+                url: "/profile".to_string(),
+                config: Some(serde_json::json!({ "authType": "authorized" })),
+                expected_json: Some(serde_json::json!({ "id": "u1" })),
+                ..TestCaseData::default()
+            })
+            .run()
+            .unwrap();
+
+        assert_eq!(summary.passed, 2);
+        assert_eq!(summary.failed, 0);
+        assert_eq!(summary.invalid, 0);
+
+        // The token captured by the first case is sent with the second one.
+        let _login = requests.recv().unwrap();
+        let profile = requests.recv().unwrap().to_lowercase();
+        assert!(
+            profile.contains("authorization: bearer t-123"),
+            "{}",
+            profile
+        );
+    }
+
+    #[test]
+    fn test_case_row() {
+        let case = TestCaseData {
+            id: 3,
+            name: "Create order".to_string(),
+            url: "/orders".to_string(),
+            method: "POST".to_string(),
+            headers: vec![("X-Tenant".to_string(), "acme".to_string())],
+            expected_error: Some("out of stock".to_string()),
+            ..TestCaseData::default()
+        };
+        let row = case.to_row();
+        assert_eq!(row.len(), ROW_LEN);
+        assert_eq!(row[0], Data::Float(3.0));
+        assert_eq!(row[7], Data::String("X-Tenant: acme".to_string()));
+        assert_eq!(row[9], Data::Empty);
+        assert_eq!(row[17], Data::String("out of stock".to_string()));
+    }
+}
@@ -1,9 +1,97 @@
+use crate::approval::ApprovalConfig;
+use crate::backoff::BackoffStrategy;
+use crate::body_checksum::BodyChecksum;
 use getopts::Options;
-use serde::Deserialize;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Deserializer};
 use serde_yaml;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::OnceLock;
 use std::{env, error::Error, fs};
 
+// Environment specific settings, which override the top level ones when the
+// environment is selected (ex: --env prod).
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct EnvConfig {
+    pub base_url: Option<String>,
+    pub token_key: Option<String>,
+    pub token_header: Option<String>,
+}
+
+// A run profile (--profile ci.yaml): the selection and the report options of a run, kept in
+// one reviewable file instead of many flags. Overrides the config file, the flags override it.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RunProfile {
+    pub test_file: Option<String>,
+    pub worksheet: Option<String>,
+    pub groups: Option<Vec<String>>, // As with -g: [worksheet_name.]group_name
+    pub env: Option<String>,
+    pub base_url: Option<String>,
+    pub vars: Option<HashMap<String, String>>,
+    pub report_traceability: Option<String>,
+    pub infer_schemas: Option<String>,
+    pub report_json: Option<String>,
+    #[serde(default)]
+    pub report_globals: bool,
+    pub metrics: Option<String>,
+    pub write_failed: Option<String>,
+    #[serde(default)]
+    pub quiet: bool,
+    #[serde(default)]
+    pub group_fail_fast: bool,
+    #[serde(default)]
+    pub strict: bool,
+}
+
+impl RunProfile {
+    // Reads a YAML (or JSON) profile.
+    fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read profile '{}': {}", path, e))?;
+        let profile = serde_yaml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse profile '{}': {}", path, e))?;
+        Ok(profile)
+    }
+}
+
+// Credentials of the test cases with `authType: "ntlm"`, for the endpoints behind NTLM (or
+// Negotiate, with an NTLM token). Used by a build with the `ntlm` feature.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct NtlmConfig {
+    pub user: String,
+    #[serde(default)]
+    pub password: String,
+    pub password_env: Option<String>, // Environment variable holding the password, instead.
+    pub domain: Option<String>,
+    pub workstation: Option<String>,
+    pub scheme: Option<String>, // Authorization scheme: NTLM (default) or Negotiate.
+}
+
+impl NtlmConfig {
+    pub fn password(&self) -> Result<String, Box<dyn Error>> {
+        match &self.password_env {
+            Some(var) => env::var(var).map_err(|_| {
+                format!(
+                    "Environment variable '{}' (ntlm.password_env) is not set",
+                    var
+                )
+                .into()
+            }),
+            None => Ok(self.password.clone()),
+        }
+    }
+
+    pub fn scheme(&self) -> &str {
+        self.scheme.as_deref().unwrap_or("NTLM")
+    }
+}
+
+const DEFAULT_CONFIG_FILE: &str = "config.yaml";
+
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub start_row: Option<usize>,
@@ -11,9 +99,106 @@ pub struct Config {
     pub base_url: Option<String>,
     pub test_file: Option<String>, // Add this line
     pub worksheet: Option<String>,
+    #[serde(default)]
     pub verbose: bool,
     pub token_key: Option<String>,
-    pub groups: Option<Vec<(Option<String>, String)>>,
+    pub token_header: Option<String>, // Header carrying the token (defaults to Authorization).
+    #[serde(default, deserialize_with = "deserialize_groups")]
+    pub groups: Option<Vec<(Option<String>, String)>>, // As [worksheet_name.]group_name, or [worksheet_name, group_name].
+    pub js_prelude_file: Option<String>, // JS file evaluated in every runtime after the globals.
+    #[serde(default)]
+    pub shuffle: bool, // Randomize the order of test cases within each group.
+    pub seed: Option<u64>,               // Seed for the randomization, to reproduce a given order.
+    pub env: Option<String>,             // Name of the selected environment.
+    pub environments: Option<HashMap<String, EnvConfig>>,
+    pub report_traceability: Option<String>, // Path of the traceability matrix (.csv or .md).
+    #[serde(default)]
+    pub http2_prior_knowledge: bool, // Force HTTP/2 without negotiation (ex: h2c endpoints).
+    #[serde(default)]
+    pub number_tolerance: f64, // Max difference for numbers to be treated equal in comparisons.
+    #[serde(default)]
+    pub lint: bool,     // Only parse the test cases and report the invalid rows.
+    #[serde(default)]
+    pub ascii: bool,    // Print plain text markers ([PASS], [FAIL], ..) instead of emoji.
+    #[serde(default)]
+    pub no_color: bool, // Print without colors.
+    #[serde(default)]
+    pub quiet: bool,    // Print a single line per test case, instead of its details.
+    pub vars: Option<HashMap<String, String>>, // Values seeded into SAT.globals (--var key=value).
+    #[serde(default)]
+    pub group_fail_fast: bool, // On a failure, skip the rest of the group and move on to the next one.
+    #[serde(default)]
+    pub inject_trace_id: bool, // Tag every request with a fresh W3C `traceparent` header.
+    pub write_failed: Option<String>, // Path of the workbook the failed test cases are written to.
+    #[serde(default)]
+    pub strict: bool, // Turn the warnings about the config (ex: unmatched groups) into errors.
+    pub default_content_type: Option<String>, // Payload encoding of the test cases without a content-type header.
+    pub jwt_token: Option<String>, // Token used by the authorized test cases, until an authorizer replaces it.
+    pub jwt_token_env: Option<String>, // Environment variable holding that token.
+    pub jwt_token_file: Option<String>, // File holding that token.
+    #[serde(default)]
+    pub error_empty_groups: bool, // Fail the run if a group has no test cases.
+    pub response_print_limit: Option<usize>, // Max. lines of a body printed in verbose mode.
+    #[serde(default)]
+    pub http1_title_case_headers: bool, // Send the header names in Title-Case over HTTP/1 (ex: X-Custom-Header).
+    pub infer_schemas: Option<String>, // Directory the JSON schemas inferred from the responses are written to.
+    pub record: Option<String>, // Cassette file every request / response exchange is recorded to.
+    pub replay: Option<String>, // Cassette file the responses are replayed from, instead of sending.
+    #[serde(default)]
+    pub auto_authorize_group: bool, // Once an authorizer ran, send its token with every later test case of the group.
+    pub dump_on_failure_dir: Option<String>, // Directory the context of the failed test cases is dumped to.
+    #[serde(default)]
+    pub backoff: BackoffStrategy, // Growth of the wait between the polling attempts (fixed by default).
+    pub global_post_script: Option<String>, // Script checking every response, after its own post test script.
+    pub ntlm: Option<NtlmConfig>,           // Credentials of the `authType: "ntlm"` test cases.
+    pub body_checksum: Option<BodyChecksum>, // Header carrying a checksum of every request body.
+    pub approvals: Option<ApprovalConfig>, // Directory and normalization of the approved responses.
+    pub report_json: Option<String>, // Path of the JSON report of the groups and their test cases.
+    #[serde(default)]
+    pub report_globals: bool, // Add the final SAT.globals of each group to the JSON report, redacted.
+    pub metrics: Option<String>, // Path of the metrics of the run, in the Prometheus text format.
+    pub redact_keys: Option<Vec<String>>, // Parts of the names of the fields masked in the reports.
+    pub local_address: Option<IpAddr>, // Local address the requests are sent from (multi-homed hosts).
+    pub pool_max_idle_per_host: Option<usize>, // Max. idle connections kept open per host (no limit by default).
+    pub pool_idle_timeout_secs: Option<u64>, // Idle connections are closed after this long (90 by default).
+    pub soft_error_detector: Option<String>, // JS expression flagging a response as an error, whatever its status.
+    pub max_redirects: Option<usize>, // Redirects followed per request (10 by default), 0 to get the 3xx itself.
+    #[serde(default)]
+    pub auto_reauth: bool, // On a 401, re-run the authorizer of the group, and retry the request once.
+    pub tag_header: Option<String>, // Header carrying the run id on every request (ex: X-Test-Run), for the server logs.
+    // Resolve the `{{cmd:...}}` placeholders by running them. Only the command line can enable it.
+    #[serde(skip)]
+    pub allow_cmd_placeholders: bool,
+    // Only run the test case of this id (--case), after the authorizers of its group.
+    #[serde(skip)]
+    pub case: Option<u32>,
+    // Benchmark mode (--benchmark <case_id>): the case, the no. of requests and how many in flight.
+    #[serde(skip)]
+    pub benchmark: Option<u32>,
+    #[serde(skip)]
+    pub benchmark_count: Option<usize>,
+    #[serde(skip)]
+    pub benchmark_concurrency: Option<usize>,
+    // Pause after each test case (--step), if the input is a terminal.
+    #[serde(skip)]
+    pub step: bool,
+    // Write the responses as the approved ones (--approve), instead of comparing with them.
+    #[serde(skip)]
+    pub approve: bool,
+    // Only check the header row of the worksheets (--validate-template).
+    #[serde(skip)]
+    pub validate_template: bool,
+    // Id of the run (--run-id), instead of a fresh UUID, seeded into the globals as `runId`.
+    #[serde(skip)]
+    pub run_id: Option<String>,
+    // Directory the relative paths of the test cases (ex: the uploaded files) are resolved
+    // against (--base-dir), instead of the directory of the test file.
+    #[serde(skip)]
+    pub base_dir: Option<String>,
+
+    // Contents of the `js_prelude_file`, loaded while building the config.
+    #[serde(skip)]
+    pub js_prelude: Option<String>,
 }
 
 impl Config {
@@ -26,19 +211,235 @@ impl Config {
             worksheet: None,
             verbose: false,
             token_key: None,
+            token_header: None,
             groups: None,
+            js_prelude_file: None,
+            shuffle: false,
+            seed: None,
+            env: None,
+            environments: None,
+            report_traceability: None,
+            http2_prior_knowledge: false,
+            number_tolerance: 0.0,
+            lint: false,
+            ascii: false,
+            no_color: false,
+            quiet: false,
+            vars: None,
+            group_fail_fast: false,
+            inject_trace_id: false,
+            write_failed: None,
+            strict: false,
+            default_content_type: None,
+            jwt_token: None,
+            jwt_token_env: None,
+            jwt_token_file: None,
+            error_empty_groups: false,
+            response_print_limit: None,
+            http1_title_case_headers: false,
+            infer_schemas: None,
+            record: None,
+            replay: None,
+            auto_authorize_group: false,
+            dump_on_failure_dir: None,
+            backoff: BackoffStrategy::Fixed,
+            global_post_script: None,
+            ntlm: None,
+            body_checksum: None,
+            approvals: None,
+            report_json: None,
+            metrics: None,
+            report_globals: false,
+            redact_keys: None,
+            local_address: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout_secs: None,
+            max_redirects: None,
+            tag_header: None,
+            auto_reauth: false,
+            soft_error_detector: None,
+            allow_cmd_placeholders: false,
+            case: None,
+            benchmark: None,
+            benchmark_count: None,
+            benchmark_concurrency: None,
+            step: false,
+            approve: false,
+            validate_template: false,
+            run_id: None,
+            base_dir: None,
+            js_prelude: None,
         }
     }
     pub fn build_config() -> Result<Self, Box<dyn Error>> {
         let args: Vec<String> = env::args().collect();
+        Self::from_args(&args, DEFAULT_CONFIG_FILE)
+    }
 
+    // Builds the config from the given command line arguments, layered on top of the config file.
+    // The config file is optional, unless it is explicitly passed with --config.
+    fn from_args(args: &[String], default_config_file: &str) -> Result<Self, Box<dyn Error>> {
         let mut opts = Options::new();
+        opts.optopt("c", "config", "Set the config file", "CONFIG");
         opts.optopt("s", "start_row", "Set the start row", "START_ROW");
         opts.optopt("e", "end_row", "Set the end row", "END_ROW");
         opts.optopt("b", "base_url", "Set the base URL", "BASE_URL");
         opts.optopt("t", "test_file", "Set the test file", "TEST_FILE");
         opts.optopt("w", "worksheet", "Set the worksheet", "WORKSHEET");
         opts.optmulti("g", "groups", "Set the test groups", "GROUPS");
+        opts.optopt("", "env", "Select the environment from config", "ENV");
+        opts.optmulti(
+            "",
+            "var",
+            "Set a global variable, or override a config key (ex: base_url)",
+            "KEY=VALUE",
+        );
+        opts.optflag(
+            "",
+            "shuffle",
+            "Randomize the order of test cases within each group",
+        );
+        opts.optopt("", "seed", "Set the seed for shuffling test cases", "SEED");
+        opts.optopt(
+            "",
+            "report-traceability",
+            "Write a given/when/then traceability matrix (.csv or .md)",
+            "PATH",
+        );
+        opts.optopt(
+            "",
+            "write-failed",
+            "Write the failed test cases to a new workbook, to rerun them",
+            "PATH",
+        );
+        opts.optflag(
+            "",
+            "lint",
+            "Validate the test case rows without executing them",
+        );
+        opts.optflag(
+            "",
+            "validate-template",
+            "Check that the header row of the worksheets has the expected columns, in order",
+        );
+        opts.optflag(
+            "",
+            "ascii",
+            "Print [PASS] / [FAIL] / [SKIP] instead of emoji",
+        );
+        opts.optflag("", "no-color", "Print without colors");
+        opts.optflag(
+            "",
+            "quiet",
+            "Print a single line per test case, with its result, status and duration",
+        );
+        opts.optflag(
+            "",
+            "strict",
+            "Fail if a group selector matches no group, instead of warning",
+        );
+        opts.optflag(
+            "",
+            "group-fail-fast",
+            "On a failure, skip the rest of the group and continue with the next group",
+        );
+        opts.optflag(
+            "",
+            "error-empty-groups",
+            "Fail if a group has no test cases",
+        );
+        opts.optopt(
+            "",
+            "infer-schemas",
+            "Write a JSON schema inferred from the response of each test case",
+            "DIR",
+        );
+        opts.optopt(
+            "",
+            "report-json",
+            "Write a JSON report of the groups and their test cases",
+            "PATH",
+        );
+        opts.optopt(
+            "",
+            "metrics",
+            "Write the counts of the test cases and the latencies in the Prometheus text format",
+            "PATH",
+        );
+        opts.optflag(
+            "",
+            "report-globals",
+            "Add the final SAT.globals of each group to the JSON report, with the secrets masked",
+        );
+        opts.optopt(
+            "",
+            "record",
+            "Record every request / response exchange to a cassette file",
+            "CASSETTE",
+        );
+        opts.optopt(
+            "",
+            "replay",
+            "Replay the responses from a cassette file, instead of sending the requests",
+            "CASSETTE",
+        );
+        opts.optflag(
+            "",
+            "allow-cmd-placeholders",
+            "Resolve the {{cmd:...}} placeholders by running them as shell commands",
+        );
+        opts.optopt(
+            "",
+            "case",
+            "Only run the test case of the given id, after the authorizers of its group",
+            "CASE_ID",
+        );
+        opts.optopt(
+            "",
+            "benchmark",
+            "Only send the test case of the given id repeatedly, and report its latencies",
+            "CASE_ID",
+        );
+        opts.optopt(
+            "",
+            "count",
+            "No. of requests sent in benchmark mode (default: 100)",
+            "N",
+        );
+        opts.optopt(
+            "",
+            "concurrency",
+            "No. of requests in flight in benchmark mode (default: 1)",
+            "C",
+        );
+        opts.optflag(
+            "",
+            "step",
+            "Pause after each test case: Enter runs the next one, r opens a JS console, q quits",
+        );
+        opts.optflag(
+            "",
+            "approve",
+            "Write the responses as the approved ones, for the later runs to match",
+        );
+        opts.optopt(
+            "",
+            "run-id",
+            "Set the id of the run, seeded into the globals as runId (a fresh UUID by default)",
+            "RUN_ID",
+        );
+        opts.optopt(
+            "",
+            "base-dir",
+            "Resolve the relative file paths of the test cases against this directory (the one of the test file by default)",
+            "BASE_DIR",
+        );
+        opts.optopt(
+            "",
+            "profile",
+            "Read the worksheet, groups, env, vars and reports of the run from a YAML/JSON file",
+            "PROFILE",
+        );
         opts.optflag("h", "help", "Print this help menu");
         opts.optflag("v", "verbose", "Print verbose information");
 
@@ -53,11 +454,59 @@ impl Config {
         }
         let verbose = matches.opt_present("v");
 
+        // The flags win over the profile, which wins over the config file.
+        let profile = match matches.opt_str("profile") {
+            Some(path) => RunProfile::load(&path)?,
+            None => RunProfile::default(),
+        };
+
         let start_row = matches.opt_str("s").map(|s| s.parse::<usize>().unwrap());
         let end_row = matches.opt_str("e").map(|e| e.parse::<usize>().unwrap());
-        let base_url = matches.opt_str("b");
-        let test_file = matches.opt_str("t");
-        let worksheet = matches.opt_str("w");
+        let base_url = matches.opt_str("b").or(profile.base_url);
+        let test_file = matches.opt_str("t").or(profile.test_file);
+        let worksheet = matches.opt_str("w").or(profile.worksheet);
+        let shuffle = matches.opt_present("shuffle");
+        let seed = parse_opt::<u64>(&matches, "seed")?;
+        let env_name = matches.opt_str("env").or(profile.env);
+        let mut vars = profile.vars.unwrap_or_default();
+        vars.extend(
+            matches
+                .opt_strs("var")
+                .iter()
+                .map(|var| match var.split_once('=') {
+                    Some((key, value)) => Ok((key.trim().to_string(), value.to_string())),
+                    None => Err(format!("Invalid var format: {}. Expected KEY=VALUE", var)),
+                })
+                .collect::<Result<HashMap<String, String>, String>>()?,
+        );
+        let report_traceability = matches
+            .opt_str("report-traceability")
+            .or(profile.report_traceability);
+        let infer_schemas = matches.opt_str("infer-schemas").or(profile.infer_schemas);
+        let report_json = matches.opt_str("report-json").or(profile.report_json);
+        let report_globals = matches.opt_present("report-globals") || profile.report_globals;
+        let metrics = matches.opt_str("metrics").or(profile.metrics);
+        let record = matches.opt_str("record");
+        let replay = matches.opt_str("replay");
+        let write_failed = matches.opt_str("write-failed").or(profile.write_failed);
+        let config_path = matches.opt_str("c");
+        let lint = matches.opt_present("lint");
+        let validate_template = matches.opt_present("validate-template");
+        let ascii = matches.opt_present("ascii");
+        let no_color = matches.opt_present("no-color");
+        let quiet = matches.opt_present("quiet") || profile.quiet;
+        let group_fail_fast = matches.opt_present("group-fail-fast") || profile.group_fail_fast;
+        let strict = matches.opt_present("strict") || profile.strict;
+        let error_empty_groups = matches.opt_present("error-empty-groups");
+        let allow_cmd_placeholders = matches.opt_present("allow-cmd-placeholders");
+        let case = parse_opt::<u32>(&matches, "case")?;
+        let benchmark = parse_opt::<u32>(&matches, "benchmark")?;
+        let benchmark_count = parse_opt::<usize>(&matches, "count")?;
+        let benchmark_concurrency = parse_opt::<usize>(&matches, "concurrency")?;
+        let step = matches.opt_present("step");
+        let approve = matches.opt_present("approve");
+        let run_id = matches.opt_str("run-id");
+        let base_dir = matches.opt_str("base-dir");
 
         // If conflicting arguments bail out.
         if (start_row.is_some() || end_row.is_some()) && worksheet.is_none() {
@@ -65,29 +514,55 @@ impl Config {
             exit(1);
         }
 
-        let groups: Vec<(Option<String>, String)> = matches
-            .opt_strs("g")
-            .into_iter()
-            .map(|g| {
-                let split: Vec<&str> = g.split(|c| c == '.' || c == ':').collect();
-                match split.len() {
-                    1 => (None, split[0].to_string()),
-                    2 => (Some(split[0].to_string()), split[1].to_string()),
-                    _ => panic!(
-                        "Invalid group format: {}. Expected [worksheet_name.]group_name",
-                        g
-                    ),
-                }
-            })
-            .collect();
+        let mut groups = matches.opt_strs("g");
+        if groups.is_empty() {
+            groups = profile.groups.unwrap_or_default();
+        }
+        let groups = groups
+            .iter()
+            .map(|g| parse_group(g))
+            .collect::<Result<Vec<_>, String>>()?;
 
         // Read from config.yaml
         // Get and print the current working directory for debugging
         let current_dir = env::current_dir()?;
         println!("Current working directory: {}", current_dir.display());
 
-        let config_file = fs::read_to_string("config.yaml")?;
-        let mut config: Config = serde_yaml::from_str(&config_file)?;
+        // The globals files are looked up next to the config file, if any.
+        let (mut config, config_dir) = match config_path {
+            Some(path) => (
+                Self::load_file(&path)?,
+                Path::new(&path).parent().map(Path::to_path_buf),
+            ),
+            None => match find_config_file(default_config_file, test_file.as_deref()) {
+                Some(path) => (
+                    Self::load_file(&path.to_string_lossy())?,
+                    path.parent().map(Path::to_path_buf),
+                ),
+                None => {
+                    println!(
+                        "No {} found, continuing with command line arguments.",
+                        default_config_file
+                    );
+                    (Config::default(), None)
+                }
+            },
+        };
+
+        // Merge the selected environment first, so that command line arguments still win.
+        let env_name = env_name.or(config.env.clone());
+        if let Some(env_name) = &env_name {
+            config.apply_env(env_name)?;
+        }
+
+        // Globals cascade as: config vars < globals.common.json < globals.<env>.json < --var
+        let globals_dir = config_dir.unwrap_or(current_dir);
+        config.apply_globals_files(&globals_dir, env_name.as_deref())?;
+
+        // Command line vars are merged into the ones from the config file.
+        if !vars.is_empty() {
+            config.apply_vars(vars);
+        }
 
         // Override with command line arguments if provided
         if let Some(start_row) = start_row {
@@ -107,11 +582,298 @@ impl Config {
         if !groups.is_empty() {
             config.groups = Some(groups);
         }
+        if let Some(report_traceability) = report_traceability {
+            config.report_traceability = Some(report_traceability);
+        }
+        if let Some(infer_schemas) = infer_schemas {
+            config.infer_schemas = Some(infer_schemas);
+        }
+        if let Some(report_json) = report_json {
+            config.report_json = Some(report_json);
+        }
+        if let Some(metrics) = metrics {
+            config.metrics = Some(metrics);
+        }
+        if let Some(record) = record {
+            config.record = Some(record);
+        }
+        if let Some(replay) = replay {
+            config.replay = Some(replay);
+        }
+        if let Some(write_failed) = write_failed {
+            config.write_failed = Some(write_failed);
+        }
 
         config.verbose = verbose;
 
+        if shuffle {
+            config.shuffle = true;
+        }
+        if lint {
+            config.lint = true;
+        }
+        if ascii {
+            config.ascii = true;
+        }
+        if no_color {
+            config.no_color = true;
+        }
+        if quiet {
+            config.quiet = true;
+        }
+        if group_fail_fast {
+            config.group_fail_fast = true;
+        }
+        if strict {
+            config.strict = true;
+        }
+        if error_empty_groups {
+            config.error_empty_groups = true;
+        }
+        if report_globals {
+            config.report_globals = true;
+        }
+        // Running commands from a test file is never enabled by the config file.
+        config.allow_cmd_placeholders = allow_cmd_placeholders;
+        config.case = case;
+        config.benchmark = benchmark;
+        config.benchmark_count = benchmark_count;
+        config.benchmark_concurrency = benchmark_concurrency;
+        config.step = step;
+        config.approve = approve;
+        config.validate_template = validate_template;
+        config.run_id = run_id;
+        config.base_dir = base_dir;
+        if let Some(seed) = seed {
+            config.seed = Some(seed);
+        }
+
+        if config.test_file.is_none() {
+            return Err("Test file not provided, use -t or set test_file in config".into());
+        }
+        if config.base_url.is_none() {
+            println!("Warning: base_url is not set, test cases must use absolute URLs.");
+        }
+
+        // Load the JS prelude upfront, so that a missing file aborts the run before any test executes.
+        if let Some(prelude_file) = &config.js_prelude_file {
+            let prelude = fs::read_to_string(prelude_file)
+                .map_err(|e| format!("Failed to read js_prelude_file '{}': {}", prelude_file, e))?;
+            config.js_prelude = Some(prelude);
+        }
+
+        Ok(config)
+    }
+
+    // True if the debug info (ex: the raw responses) is to be printed. It may hold secrets, so
+    // it is only printed in verbose mode, and never in quiet mode.
+    pub fn print_debug(&self) -> bool {
+        self.verbose && !self.quiet
+    }
+
+    // Adds the vars to the globals, and applies the ones named after config keys as overrides.
+    pub fn apply_vars(&mut self, vars: HashMap<String, String>) {
+        for (key, value) in &vars {
+            match key.as_str() {
+                "base_url" => self.base_url = Some(value.clone()),
+                "token_key" => self.token_key = Some(value.clone()),
+                "token_header" => self.token_header = Some(value.clone()),
+                _ => {}
+            }
+        }
+        self.vars.get_or_insert_with(HashMap::new).extend(vars);
+    }
+
+    // Overlays the vars of `globals.common.json`, and then of `globals.<env>.json`,
+    // found in the given directory.
+    fn apply_globals_files(
+        &mut self,
+        dir: &Path,
+        env_name: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut files = vec!["globals.common.json".to_string()];
+        if let Some(env_name) = env_name {
+            files.push(format!("globals.{}.json", env_name));
+        }
+        for path in files.iter().map(|file| dir.join(file)) {
+            if path.is_file() {
+                self.apply_vars(load_globals_file(&path)?);
+                println!("Loaded globals from: {}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    // The token given up front (ex: by an external login), from `jwt_token`, the
+    // `jwt_token_env` variable or the `jwt_token_file`, in that order.
+    pub fn preset_jwt_token(&self) -> Result<Option<String>, Box<dyn Error>> {
+        if let Some(token) = &self.jwt_token {
+            return Ok(Some(token.clone()));
+        }
+        if let Some(var) = &self.jwt_token_env {
+            let token = env::var(var).map_err(|_| {
+                format!("Environment variable '{}' (jwt_token_env) is not set", var)
+            })?;
+            return Ok(Some(token.trim().to_string()));
+        }
+        if let Some(path) = &self.jwt_token_file {
+            let token = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read jwt_token_file '{}': {}", path, e))?;
+            return Ok(Some(token.trim().to_string()));
+        }
+        Ok(None)
+    }
+
+    // Reads and parses the given config file.
+    fn load_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let config_file = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file '{}': {}", path, e))?;
+        let parse_error =
+            |e: serde_yaml::Error| format!("Failed to parse config file '{}': {}", path, e);
+        let mut value: serde_yaml::Value =
+            serde_yaml::from_str(&config_file).map_err(parse_error)?;
+        expand_env_vars(&mut value);
+        let config = serde_yaml::from_value(value).map_err(parse_error)?;
         Ok(config)
     }
+
+    // Overrides the top level settings with the ones defined for the given environment.
+    pub fn apply_env(&mut self, env_name: &str) -> Result<(), Box<dyn Error>> {
+        let env_config = self
+            .environments
+            .as_ref()
+            .and_then(|envs| envs.get(env_name))
+            .cloned()
+            .ok_or_else(|| format!("Environment '{}' is not defined in config", env_name))?;
+
+        if env_config.base_url.is_some() {
+            self.base_url = env_config.base_url;
+        }
+        if env_config.token_key.is_some() {
+            self.token_key = env_config.token_key;
+        }
+        if env_config.token_header.is_some() {
+            self.token_header = env_config.token_header;
+        }
+        self.env = Some(env_name.to_string());
+        Ok(())
+    }
+}
+
+// Replaces the `${NAME}` references in the string values of the parsed config file with the
+// value of the environment variable (ex: `base_url: ${API_HOST}`), so that the value is never
+// read as YAML (ex: a `#` in a password). An unset variable is left as-is, with a warning.
+fn expand_env_vars(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::String(text) => *text = expand_env_refs(text),
+        serde_yaml::Value::Sequence(items) => items.iter_mut().for_each(expand_env_vars),
+        serde_yaml::Value::Mapping(fields) => fields.values_mut().for_each(expand_env_vars),
+        serde_yaml::Value::Tagged(tagged) => expand_env_vars(&mut tagged.value),
+        _ => {}
+    }
+}
+
+fn expand_env_refs(text: &str) -> String {
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    let reference =
+        REFERENCE.get_or_init(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+    reference
+        .replace_all(text, |caps: &Captures| match env::var(&caps[1]) {
+            Ok(value) => value,
+            Err(_) => {
+                eprintln!(
+                    "Warning: environment variable '{}' is not set, '{}' is left as-is in the config",
+                    &caps[1], &caps[0]
+                );
+                caps[0].to_string()
+            }
+        })
+        .into_owned()
+}
+
+// Parses the value of a numeric option, if given (ex: `--count 100`).
+fn parse_opt<T: std::str::FromStr>(
+    matches: &getopts::Matches,
+    name: &str,
+) -> Result<Option<T>, String> {
+    matches
+        .opt_str(name)
+        .map(|value| {
+            value.parse::<T>().map_err(|_| {
+                format!(
+                    "Invalid value for --{}: '{}', expected a number",
+                    name, value
+                )
+            })
+        })
+        .transpose()
+}
+
+// Splits a group selector, as `[worksheet_name.]group_name` (or with a `:`).
+fn parse_group(group: &str) -> Result<(Option<String>, String), String> {
+    let split: Vec<&str> = group.split(|c| c == '.' || c == ':').collect();
+    match split.len() {
+        1 => Ok((None, split[0].to_string())),
+        2 => Ok((Some(split[0].to_string()), split[1].to_string())),
+        _ => Err(format!(
+            "Invalid group format: {}. Expected [worksheet_name.]group_name",
+            group
+        )),
+    }
+}
+
+// The selected groups, as (worksheet_name, group_name) pairs (with no worksheet for any).
+type Groups = Option<Vec<(Option<String>, String)>>;
+
+// The `groups` of config.yaml, each as a selector string (ex: "Sheet1.smoke"), as with -g, or
+// as a [worksheet_name, group_name] pair (with a null worksheet for any).
+fn deserialize_groups<'de, D>(deserializer: D) -> Result<Groups, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Group {
+        Selector(String),
+        Pair(Option<String>, String),
+    }
+
+    let Some(groups) = Option::<Vec<Group>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    groups
+        .into_iter()
+        .map(|group| match group {
+            Group::Selector(selector) => parse_group(&selector).map_err(serde::de::Error::custom),
+            Group::Pair(worksheet, name) => Ok((worksheet, name)),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+// Reads a globals file, a json object whose values are used as strings.
+fn load_globals_file(path: &Path) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read globals file '{}': {}", path.display(), e))?;
+    let globals: HashMap<String, serde_json::Value> = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse globals file '{}': {}", path.display(), e))?;
+    Ok(globals
+        .into_iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(s) => (key, s),
+            other => (key, other.to_string()),
+        })
+        .collect())
+}
+
+// Looks for the config file in the current directory, and then alongside the test file.
+fn find_config_file(default_config_file: &str, test_file: Option<&str>) -> Option<PathBuf> {
+    let mut candidates = vec![PathBuf::from(default_config_file)];
+    if let Some(test_dir) = test_file.and_then(|f| Path::new(f).parent()) {
+        let file_name = Path::new(default_config_file).file_name()?;
+        candidates.push(test_dir.join(file_name));
+    }
+    candidates.into_iter().find(|path| path.is_file())
 }
 
 fn print_usage(program: &str, opts: Options) {
@@ -125,3 +887,312 @@ fn print_usage(program: &str, opts: Options) {
 
     print!("{}", opts.usage(&brief));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_config_from_flags_without_yaml() {
+        let args = args(&[
+            "satyanaash",
+            "-t",
+            "data/mock-tests.xlsx",
+            "-b",
+            "http://localhost:3000/api",
+        ]);
+        let config = Config::from_args(&args, "no-such-dir/config.yaml").unwrap();
+        assert_eq!(config.test_file.as_deref(), Some("data/mock-tests.xlsx"));
+        assert_eq!(
+            config.base_url.as_deref(),
+            Some("http://localhost:3000/api")
+        );
+        assert_eq!(config.token_key, None);
+    }
+
+    #[test]
+    fn test_debug_prints_only_in_verbose_mode() {
+        let config_with = |flags: &[&str]| {
+            let mut list = vec!["satyanaash", "-t", "data/mock-tests.xlsx"];
+            list.extend_from_slice(flags);
+            Config::from_args(&args(&list), "no-such-dir/config.yaml").unwrap()
+        };
+        assert!(!config_with(&[]).print_debug());
+        assert!(config_with(&["-v"]).print_debug());
+        assert!(!config_with(&["-v", "--quiet"]).print_debug());
+    }
+
+    #[test]
+    fn test_config_without_test_file() {
+        let args = args(&["satyanaash", "-b", "http://localhost:3000/api"]);
+        assert!(Config::from_args(&args, "no-such-dir/config.yaml").is_err());
+    }
+
+    // Writes the given yaml into a fresh temp directory and returns the directory.
+    fn temp_config_dir(name: &str, yaml: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("satyanaash-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.yaml"), yaml).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_config_from_non_default_path() {
+        let dir = temp_config_dir(
+            "explicit",
+            "base_url: http://staging:8080\ntoken_key: auth.token\n",
+        );
+        let config_path = dir.join("config.yaml").to_string_lossy().to_string();
+        let args = args(&["satyanaash", "-t", "tests.xlsx", "--config", &config_path]);
+
+        let config = Config::from_args(&args, DEFAULT_CONFIG_FILE).unwrap();
+        assert_eq!(config.base_url.as_deref(), Some("http://staging:8080"));
+        assert_eq!(config.token_key.as_deref(), Some("auth.token"));
+    }
+
+    #[test]
+    fn test_config_with_env_vars() {
+        env::set_var("SATYANAASH_TEST_API_HOST", "api.staging:8080");
+        let dir = temp_config_dir(
+            "env-vars",
+            "base_url: http://${SATYANAASH_TEST_API_HOST}/api\ntoken_key: ${SATYANAASH_TEST_UNSET}\n",
+        );
+        let config_path = dir.join("config.yaml").to_string_lossy().to_string();
+        let args = args(&["satyanaash", "-t", "tests.xlsx", "--config", &config_path]);
+
+        let config = Config::from_args(&args, DEFAULT_CONFIG_FILE).unwrap();
+        assert_eq!(
+            config.base_url.as_deref(),
+            Some("http://api.staging:8080/api")
+        );
+        // An unset variable is left as-is.
+        assert_eq!(
+            config.token_key.as_deref(),
+            Some("${SATYANAASH_TEST_UNSET}")
+        );
+    }
+
+    #[test]
+    fn test_config_with_env_vars_read_as_text() {
+        env::set_var("SATYANAASH_TEST_TOKEN_HEADER", "X-Token #1: primary");
+        let dir = temp_config_dir(
+            "env-vars-text",
+            "token_header: ${SATYANAASH_TEST_TOKEN_HEADER}\nvars:\n  header: \"${SATYANAASH_TEST_TOKEN_HEADER}\"\n",
+        );
+        let config_path = dir.join("config.yaml").to_string_lossy().to_string();
+        let args = args(&["satyanaash", "-t", "tests.xlsx", "--config", &config_path]);
+
+        let config = Config::from_args(&args, DEFAULT_CONFIG_FILE).unwrap();
+        assert_eq!(config.token_header.as_deref(), Some("X-Token #1: primary"));
+        assert_eq!(config.vars.unwrap()["header"], "X-Token #1: primary");
+    }
+
+    #[test]
+    fn test_config_alongside_test_file() {
+        let dir = temp_config_dir("alongside", "base_url: http://qa:8080\n");
+        let test_file = dir.join("tests.xlsx").to_string_lossy().to_string();
+        let args = args(&["satyanaash", "-t", &test_file]);
+
+        let config = Config::from_args(&args, "no-such-dir/config.yaml").unwrap();
+        assert_eq!(config.base_url.as_deref(), Some("http://qa:8080"));
+    }
+
+    #[test]
+    fn test_config_with_vars() {
+        let args = args(&[
+            "satyanaash",
+            "-t",
+            "tests.xlsx",
+            "--var",
+            "base_url=http://localhost:4000",
+            "--var",
+            "tenant=acme",
+        ]);
+        let config = Config::from_args(&args, "no-such-dir/config.yaml").unwrap();
+        assert_eq!(config.base_url.as_deref(), Some("http://localhost:4000"));
+        let vars = config.vars.unwrap();
+        assert_eq!(vars.get("tenant").map(String::as_str), Some("acme"));
+    }
+
+    #[test]
+    fn test_cascading_globals() {
+        let dir = temp_config_dir(
+            "globals",
+            "environments:\n  qa:\n    base_url: http://qa:8080\n",
+        );
+        fs::write(
+            dir.join("globals.common.json"),
+            r#"{"tenant": "common", "region": "eu", "plan": "free", "retries": 3}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("globals.qa.json"),
+            r#"{"tenant": "qa", "region": "us"}"#,
+        )
+        .unwrap();
+        let config_path = dir.join("config.yaml").to_string_lossy().to_string();
+        let args = args(&[
+            "satyanaash",
+            "-t",
+            "tests.xlsx",
+            "-c",
+            &config_path,
+            "--env",
+            "qa",
+            "--var",
+            "tenant=cli",
+        ]);
+
+        let config = Config::from_args(&args, DEFAULT_CONFIG_FILE).unwrap();
+        let vars = config.vars.unwrap();
+        assert_eq!(vars.get("plan").map(String::as_str), Some("free"));
+        assert_eq!(vars.get("retries").map(String::as_str), Some("3"));
+        assert_eq!(vars.get("region").map(String::as_str), Some("us"));
+        assert_eq!(vars.get("tenant").map(String::as_str), Some("cli"));
+    }
+
+    #[test]
+    fn test_preset_jwt_token_from_file() {
+        let dir = temp_config_dir("jwt", "");
+        let token_file = dir.join("token.txt");
+        fs::write(&token_file, "eyJhbGciOi.token\n").unwrap();
+
+        let config = Config {
+            jwt_token_file: Some(token_file.to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.preset_jwt_token().unwrap().as_deref(),
+            Some("eyJhbGciOi.token")
+        );
+
+        let config = Config {
+            jwt_token_env: Some("SATYANAASH_NO_SUCH_TOKEN".to_string()),
+            ..Config::default()
+        };
+        assert!(config.preset_jwt_token().is_err());
+    }
+
+    #[test]
+    fn test_missing_explicit_config_file() {
+        let args = args(&[
+            "satyanaash",
+            "-t",
+            "tests.xlsx",
+            "-c",
+            "no-such-dir/sat.yaml",
+        ]);
+        let err = Config::from_args(&args, DEFAULT_CONFIG_FILE).unwrap_err();
+        assert!(err.to_string().contains("no-such-dir/sat.yaml"));
+    }
+
+    #[test]
+    fn test_invalid_benchmark_options() {
+        let from = |flags: &[&str]| {
+            let mut list = vec!["satyanaash", "-t", "tests.xlsx"];
+            list.extend_from_slice(flags);
+            Config::from_args(&args(&list), "no-such-dir/config.yaml")
+        };
+        let config = from(&["--benchmark", "7", "--count", "50", "--concurrency", "4"]).unwrap();
+        assert_eq!(config.benchmark, Some(7));
+        assert_eq!(config.benchmark_count, Some(50));
+        assert_eq!(config.benchmark_concurrency, Some(4));
+
+        let error = from(&["--benchmark", "7", "--count", "5O"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for --count: '5O', expected a number"
+        );
+        assert!(from(&["--benchmark", "seven"]).is_err());
+        assert!(from(&["--benchmark", "7", "--concurrency", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_case() {
+        let from = |case: &str| {
+            Config::from_args(
+                &args(&["satyanaash", "-t", "tests.xlsx", "--case", case]),
+                "no-such-dir/config.yaml",
+            )
+        };
+        assert_eq!(from("12").unwrap().case, Some(12));
+        let error = from("login").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for --case: 'login', expected a number"
+        );
+    }
+
+    #[test]
+    fn test_groups_in_config() {
+        let selectors: Config =
+            serde_yaml::from_str("groups: [\"Sheet1.smoke\", \"Sheet2:auth\", regression]\n")
+                .unwrap();
+        let pairs: Config =
+            serde_yaml::from_str("groups: [[Sheet1, smoke], [Sheet2, auth], [null, regression]]\n")
+                .unwrap();
+        let expected = vec![
+            (Some("Sheet1".to_string()), "smoke".to_string()),
+            (Some("Sheet2".to_string()), "auth".to_string()),
+            (None, "regression".to_string()),
+        ];
+        assert_eq!(selectors.groups, Some(expected.clone()));
+        assert_eq!(pairs.groups, Some(expected));
+
+        let none: Config = serde_yaml::from_str("base_url: http://qa:8080\n").unwrap();
+        assert_eq!(none.groups, None);
+        assert!(serde_yaml::from_str::<Config>("groups: [\"a.b.c\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_run_profile() {
+        let path = std::env::temp_dir().join(format!("profile_{}.yaml", std::process::id()));
+        fs::write(
+            &path,
+            "test_file: data/mock-tests.xlsx\nworksheet: Orders\ngroups: [\"Orders.Create\", Refunds]\nreport_traceability: reports/trace.md\nvars: { tenant: acme }\n",
+        )
+        .unwrap();
+        let profile = path.to_string_lossy().to_string();
+
+        let config = Config::from_args(
+            &args(&["satyanaash", "--profile", &profile]),
+            "no-such-dir/config.yaml",
+        )
+        .unwrap();
+        assert_eq!(config.test_file.as_deref(), Some("data/mock-tests.xlsx"));
+        assert_eq!(
+            config.groups,
+            Some(vec![
+                (Some("Orders".to_string()), "Create".to_string()),
+                (None, "Refunds".to_string()),
+            ])
+        );
+        assert_eq!(
+            config.report_traceability.as_deref(),
+            Some("reports/trace.md")
+        );
+        assert_eq!(config.vars.unwrap()["tenant"], "acme");
+
+        // The flags still override the profile.
+        let config = Config::from_args(
+            &args(&[
+                "satyanaash",
+                "--profile",
+                &profile,
+                "-w",
+                "Users",
+                "-g",
+                "Login",
+            ]),
+            "no-such-dir/config.yaml",
+        )
+        .unwrap();
+        assert_eq!(config.worksheet.as_deref(), Some("Users"));
+        assert_eq!(config.groups, Some(vec![(None, "Login".to_string())]));
+        fs::remove_file(path).unwrap();
+    }
+}
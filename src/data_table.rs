@@ -0,0 +1,124 @@
+/* Shared lookup data of the data-driven tests, kept in other worksheets of the test file
+    (ex: a `Users` sheet) and referred to by the `{{table:Users.admin.email}}` placeholders.
+    The header row of a table names its fields, and the first column holds the keys of its
+    rows.  A worksheet is read once, at its first lookup.
+*/
+
+use calamine::{open_workbook, Data, DataType, Reader, Xlsx};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// The rows of a table by their key, and their cells by the field name.
+type Table = HashMap<String, HashMap<String, String>>;
+
+#[derive(Debug, Default)]
+pub struct DataTables {
+    path: Option<PathBuf>, // The workbook holding the tables.
+    tables: HashMap<String, Result<Table, String>>, // Read tables (or why they can't be), by their sheet.
+}
+
+impl DataTables {
+    pub fn new(path: Option<&Path>) -> Self {
+        DataTables {
+            path: path.map(Path::to_path_buf),
+            tables: HashMap::new(),
+        }
+    }
+
+    // Resolves a reference of the form `Sheet.key.field` (the key may contain dots, ex: an
+    // email), and returns the cell of the row with the key, in the column of the field.
+    pub fn lookup(&mut self, reference: &str) -> Result<String, Box<dyn Error>> {
+        let (sheet, rest) = reference
+            .split_once('.')
+            .ok_or_else(|| format!("'{}' is not of the form Sheet.key.field", reference))?;
+        let (key, field) = rest
+            .rsplit_once('.')
+            .ok_or_else(|| format!("'{}' is not of the form Sheet.key.field", reference))?;
+
+        if !self.tables.contains_key(sheet) {
+            let table = self.read(sheet).map_err(|e| e.to_string());
+            self.tables.insert(sheet.to_string(), table);
+        }
+        let table = self.tables[sheet].as_ref().map_err(|e| e.clone())?;
+        let row = table
+            .get(key)
+            .ok_or_else(|| format!("No row with the key '{}' in the table '{}'", key, sheet))?;
+        let cell = row
+            .get(field)
+            .ok_or_else(|| format!("No field '{}' in the table '{}'", field, sheet))?;
+        Ok(cell.clone())
+    }
+
+    fn read(&self, sheet: &str) -> Result<Table, Box<dyn Error>> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or("No test file to read the tables from")?;
+        let mut excel: Xlsx<_> = open_workbook(path)?;
+        let range = excel
+            .worksheet_range(sheet)
+            .map_err(|e| format!("Failed to read the table '{}': {}", sheet, e))?;
+        let mut rows = range.rows();
+        let fields: Vec<String> = rows.next().unwrap_or(&[]).iter().map(cell_text).collect();
+        Ok(rows
+            .filter(|row| !row.first().map(cell_text).unwrap_or_default().is_empty())
+            .map(|row| {
+                let cells = fields
+                    .iter()
+                    .zip(row.iter().map(cell_text))
+                    .filter(|(field, _)| !field.is_empty())
+                    .map(|(field, cell)| (field.clone(), cell))
+                    .collect();
+                (cell_text(&row[0]), cells)
+            })
+            .collect())
+    }
+}
+
+fn cell_text(cell: &Data) -> String {
+    match cell.get_string() {
+        Some(s) => s.trim().to_string(),
+        None if cell.is_empty() => String::new(),
+        None => cell.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let path = std::env::temp_dir().join(format!("tables_{}.xlsx", std::process::id()));
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let users = workbook.add_worksheet().set_name("Users").unwrap();
+        for (row, cells) in [
+            ["key", "email", "role"],
+            ["admin", "admin@acme.io", "owner"],
+            ["ann@acme.io", "ann@acme.io", "viewer"],
+        ]
+        .iter()
+        .enumerate()
+        {
+            for (col, cell) in cells.iter().enumerate() {
+                users.write_string(row as u32, col as u16, *cell).unwrap();
+            }
+        }
+        users.write_string(3, 0, "guest").unwrap();
+        users.write_number(3, 2, 7).unwrap();
+        workbook.save(&path).unwrap();
+
+        let mut tables = DataTables::new(Some(&path));
+        assert_eq!(tables.lookup("Users.admin.email").unwrap(), "admin@acme.io");
+        assert_eq!(tables.lookup("Users.ann@acme.io.role").unwrap(), "viewer");
+        assert_eq!(tables.lookup("Users.guest.role").unwrap(), "7");
+        assert_eq!(tables.lookup("Users.guest.email").unwrap(), "");
+
+        assert!(tables.lookup("Users.nobody.email").is_err());
+        assert!(tables.lookup("Users.admin.phone").is_err());
+        assert!(tables.lookup("Orders.1.total").is_err());
+        assert!(tables.lookup("Users.admin").is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,19 +1,46 @@
 // In lib.rs
+pub mod approval;
+pub mod backoff;
+pub mod benchmark;
+pub mod body_checksum;
+pub mod builder;
 pub mod config;
+mod data_table;
+#[cfg(feature = "ntlm")]
+mod ntlm;
+mod redact;
+pub mod reporters;
+mod reruns;
+pub mod run_summary;
+mod step;
+mod template;
 mod test_case;
 mod test_context;
-mod test_events;
+pub mod test_events;
 mod test_group;
 mod test_suite; // Import the test_suite module
+#[cfg(test)]
+mod test_utils;
+mod transport;
 pub mod v8engine;
 
+use crate::benchmark::BenchmarkReport;
 use crate::config::Config;
+use crate::data_table::DataTables;
+use crate::test_context::TestCtx;
 use crate::test_suite::TestSuite;
+use crate::transport::Transport;
 use calamine::{open_workbook, Reader, Xlsx};
 use std::error::Error; // Import the TestSuite struct
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
 use test_events::TestEvent;
 
+pub use builder::{SuiteBuilder, TestCaseData};
+pub use test_case::{TestCase, TestResult};
+pub use test_suite::LoadedGroup;
+
 // Define a struct TSat that contains a channel transmitter
 pub struct TSat {
     tx: Sender<test_events::TestEvent>,
@@ -31,10 +58,19 @@ impl TSat {
     pub fn exec(&self, filename: &str, config: &Config) -> Result<(), Box<dyn Error>> {
         // Open the excel file.
         let mut excel: Xlsx<_> = open_workbook(filename)?;
-        let mut ts = TestSuite::new();
+        let mut ts = TestSuite::with_transport(Transport::from_config(config)?);
+        ts.set_base_dir(base_dir(filename, config));
+        ts.set_test_file(Path::new(filename));
+        println!("Run id: {}", ts.run_id(config));
+        if config.shuffle {
+            println!(
+                "Shuffling test cases with seed: {}",
+                ts.shuffle_seed(config)
+            );
+        }
 
         // If a worksheet is specified in the config, only construct and run the TestSuite for that worksheet.
-        if let Some(worksheet) = &config.worksheet {
+        let result = if let Some(worksheet) = &config.worksheet {
             println!("Constructing test suite for sheet: {}", worksheet);
 
             /*
@@ -42,14 +78,59 @@ impl TSat {
                 .send(TestEvent::EvtTestSuiteBegin(ts.get_start_evt_data()))
                 .unwrap();
             */
-            let _ = ts.exec(&mut excel, worksheet, config, &self.tx)?;
+            ts.exec(&mut excel, worksheet, config, &self.tx).map(|_| ())
         } else {
             // If no worksheet is specified, construct and run the TestSuite for all worksheets.
-            for sheet_name in excel.sheet_names() {
-                println!("Constructing test suite for sheet: {}", sheet_name);
-                //let mut ts = TestSuite::new();
-                let _ = ts.exec(&mut excel, &sheet_name, config, &self.tx)?;
+            // A worksheet which can't be read is skipped, and reported once the others ran.
+            let mut unreadable = vec![];
+            excel
+                .sheet_names()
+                .iter()
+                .try_for_each(|sheet_name| {
+                    println!("Constructing test suite for sheet: {}", sheet_name);
+                    let range = match excel.worksheet_range(sheet_name) {
+                        Ok(range) => range,
+                        Err(e) => {
+                            eprintln!(
+                                "Skipping sheet '{}', it could not be read: {}",
+                                sheet_name, e
+                            );
+                            unreadable.push(format!("{} ({})", sheet_name, e));
+                            return Ok(());
+                        }
+                    };
+                    let rows: Vec<_> = range.rows().collect();
+                    ts.exec_sheet(&rows, sheet_name, config, &self.tx)
+                        .map(|_| ())
+                })
+                .and_then(|_| match unreadable.is_empty() {
+                    true => Ok(()),
+                    false => Err(format!(
+                        "Failed to read the worksheet(s): {}",
+                        unreadable.join(", ")
+                    )
+                    .into()),
+                })
+        };
+
+        // The failed test cases, and the recording, are written even if the run was aborted by a failure.
+        if let Some(path) = &config.write_failed {
+            ts.write_failed(path)?;
+        }
+        ts.save_recording()?;
+
+        // A typo in a group selector would otherwise silently run nothing.
+        let unmatched = ts.unmatched_groups(config);
+        if !unmatched.is_empty() {
+            let msg = format!("No group matched the selector(s): {}", unmatched.join(", "));
+            if config.strict {
+                return Err(msg.into());
             }
+            eprintln!("Warning: {}", msg);
+        }
+        result?;
+        if let Some(case_id) = config.case.filter(|_| !ts.case_found()) {
+            return Err(format!("No test case with id {} in the selected groups", case_id).into());
         }
         /*
         // Fire an event to indicate that the test suite is finished.
@@ -61,4 +142,367 @@ impl TSat {
 
         Ok(())
     }
+
+    // Parses the targeted worksheet(s) without executing, and returns the no. of invalid rows.
+    pub fn lint(&self, filename: &str, config: &Config) -> Result<usize, Box<dyn Error>> {
+        let mut excel: Xlsx<_> = open_workbook(filename)?;
+        let ts = TestSuite::new();
+
+        let sheet_names = match &config.worksheet {
+            Some(worksheet) => vec![worksheet.clone()],
+            None => excel.sheet_names(),
+        };
+        let mut invalid_rows = 0;
+        for sheet_name in sheet_names {
+            invalid_rows += ts.lint(&mut excel, &sheet_name, config)?;
+        }
+        Ok(invalid_rows)
+    }
+
+    // Checks the header row of the targeted worksheet(s) against the expected columns, and
+    // returns the no. of problems found.
+    pub fn validate_template(filename: &str, config: &Config) -> Result<usize, Box<dyn Error>> {
+        let mut excel: Xlsx<_> = open_workbook(filename)?;
+        let sheet_names = match &config.worksheet {
+            Some(worksheet) => vec![worksheet.clone()],
+            None => excel.sheet_names(),
+        };
+        let mut problems = 0;
+        for sheet_name in sheet_names {
+            let range = excel.worksheet_range(&sheet_name)?;
+            let sheet_problems = template::validate_header(range.rows().next().unwrap_or(&[]));
+            for problem in &sheet_problems {
+                println!("{}: {}", sheet_name, problem);
+            }
+            println!(
+                "Validated the template of sheet: {}, problems: {}",
+                sheet_name,
+                sheet_problems.len()
+            );
+            problems += sheet_problems.len();
+        }
+        Ok(problems)
+    }
+
+    // Parses the targeted worksheet(s) into their groups of test cases, without executing
+    // them (ex: to inspect or validate a suite from another tool).
+    pub fn load(filename: &str, config: &Config) -> Result<Vec<LoadedGroup>, Box<dyn Error>> {
+        let mut excel: Xlsx<_> = open_workbook(filename)?;
+        let ts = TestSuite::new();
+
+        let sheet_names = match &config.worksheet {
+            Some(worksheet) => vec![worksheet.clone()],
+            None => excel.sheet_names(),
+        };
+        let mut groups = vec![];
+        for sheet_name in sheet_names {
+            groups.extend(ts.load(&mut excel, &sheet_name, config)?);
+        }
+        Ok(groups)
+    }
+
+    // Sends the request of the test case with the given id repeatedly (`--benchmark`), and
+    // returns the latencies and the error rate. The authorizers of its group are run first,
+    // so that an authorized test case gets the token.
+    pub fn benchmark(
+        filename: &str,
+        case_id: u32,
+        config: &Config,
+    ) -> Result<BenchmarkReport, Box<dyn Error>> {
+        let (_, mut cases) = Self::load(filename, config)?
+            .into_iter()
+            .find(|(_, cases)| cases.iter().any(|tc| tc.id == case_id))
+            .ok_or_else(|| format!("No test case with id {}", case_id))?;
+        let index = cases.iter().position(|tc| tc.id == case_id).unwrap();
+        if !cases[index].errors.is_empty() {
+            return Err(format!(
+                "Test case {} is invalid: {:?}",
+                case_id, cases[index].errors
+            )
+            .into());
+        }
+
+        let transport = Arc::new(Transport::from_config(config)?);
+        let mut ts_ctx = TestCtx::with_transport(config, transport)?;
+        ts_ctx.data_tables = DataTables::new(Some(Path::new(filename)));
+        ts_ctx.base_dir = base_dir(filename, config);
+        let (tx, _rx) = channel();
+        for tc in cases
+            .iter_mut()
+            .filter(|tc| tc.id != case_id && tc.is_authorizer())
+        {
+            tc.run(&mut ts_ctx, config, &tx);
+        }
+
+        let count = config.benchmark_count.unwrap_or(100);
+        let concurrency = config.benchmark_concurrency.unwrap_or(1);
+        Ok(benchmark::run(
+            &mut cases[index],
+            &mut ts_ctx,
+            count,
+            concurrency,
+            config,
+        ))
+    }
+}
+
+// The directory the relative paths of the test cases are resolved against: the `--base-dir`,
+// or else the directory of the test file.
+fn base_dir(filename: &str, config: &Config) -> PathBuf {
+    match &config.base_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(filename)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_summary::{RunSummary, EXIT_INVALID};
+    use crate::test_utils::{capture_server, request_line_server};
+    use std::io::{Cursor, Read, Write};
+
+    // Writes a workbook with a "Broken" worksheet, whose xml is cut short, and a "Good" one
+    // with a test case sending a GET to the url.
+    fn write_broken_workbook(path: &Path, url: &str) {
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let broken = workbook.add_worksheet().set_name("Broken").unwrap();
+        broken.write_string(0, 0, "id").unwrap();
+        let good = workbook.add_worksheet().set_name("Good").unwrap();
+        good.write_string(0, 0, "id").unwrap();
+        good.write_string(1, 0, "Group: Health").unwrap();
+        good.write_number(2, 0, 1).unwrap();
+        let cells = [
+            "Health check",
+            "a running server",
+            "its health is checked",
+            "it is up",
+            url,
+            "GET",
+        ];
+        for (col, cell) in (1..).zip(cells) {
+            good.write_string(2, col, cell).unwrap();
+        }
+        good.write_string(2, 11, "SAT.tester('up', () => SAT.response.status === 200)")
+            .unwrap();
+        let buffer = workbook.save_to_buffer().unwrap();
+
+        let mut source = zip::ZipArchive::new(Cursor::new(buffer)).unwrap();
+        let mut target = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        for i in 0..source.len() {
+            let mut file = source.by_index(i).unwrap();
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).unwrap();
+            if file.name() == "xl/worksheets/sheet1.xml" {
+                contents = b"<worksheet><sheetData><row r=\"1\"><c r=\"A1\"><v>".to_vec();
+            }
+            target
+                .start_file(file.name(), zip::write::FileOptions::default())
+                .unwrap();
+            target.write_all(&contents).unwrap();
+        }
+        target.finish().unwrap();
+    }
+
+    #[test]
+    fn test_unreadable_sheet_does_not_stop_the_others() {
+        let (url, requests) = request_line_server(1);
+        let path = std::env::temp_dir().join(format!("broken_{}.xlsx", std::process::id()));
+        write_broken_workbook(&path, &format!("{}/health", url));
+
+        let (tsat, _events) = TSat::new();
+        let result = tsat.exec(&path.to_string_lossy(), &Config::default());
+
+        // The good sheet still ran, and the broken one is reported at the end.
+        assert_eq!(requests.recv().unwrap(), "GET /health HTTP/1.1");
+        let error = result.unwrap_err().to_string();
+        assert!(
+            error.contains("Failed to read the worksheet(s): Broken"),
+            "{}",
+            error
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_load_without_executing() {
+        let groups = TSat::load("data/mock-tests.xlsx", &Config::default()).unwrap();
+        let counts: Vec<(&str, usize)> = groups
+            .iter()
+            .map(|(name, cases)| (name.as_str(), cases.len()))
+            .collect();
+        assert_eq!(counts, vec![("One", 2), ("Two", 2)]);
+
+        // Only the selected groups are loaded.
+        let config = Config {
+            groups: Some(vec![(None, "Two".to_string())]),
+            worksheet: Some("suite1".to_string()),
+            ..Config::default()
+        };
+        let groups = TSat::load("data/mock-tests.xlsx", &config).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].0, "Two");
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert_eq!(
+            TSat::validate_template("data/mock-tests.xlsx", &Config::default()).unwrap(),
+            0
+        );
+        // The older layout, with the status and auth type columns in place of the config, which
+        // shifts the scripts by one column.
+        assert_eq!(
+            TSat::validate_template("data/data.xlsx", &Config::default()).unwrap(),
+            4
+        );
+    }
+
+    // Writes a workbook with a test case uploading the file at the (relative) path to the url.
+    fn write_upload_workbook(path: &Path, url: &str, upload: &str) {
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let sheet = workbook.add_worksheet();
+        sheet.write_string(0, 0, "id").unwrap();
+        sheet.write_string(1, 0, "Group: Uploads").unwrap();
+        sheet.write_number(2, 0, 1).unwrap();
+        let payload = format!(
+            r#"{{"form-data": {{"files": [{{"fieldname": "doc", "filepath": "{}"}}]}}}}"#,
+            upload
+        );
+        let cells = [
+            "Upload a document",
+            "a document",
+            "it is uploaded",
+            "it is stored",
+            url,
+            "POST",
+            "Content-Type: multipart/form-data",
+            &payload,
+        ];
+        for (col, cell) in (1..).zip(cells) {
+            sheet.write_string(2, col, cell).unwrap();
+        }
+        sheet
+            .write_string(
+                2,
+                11,
+                "SAT.tester('stored', () => SAT.response.status === 200)",
+            )
+            .unwrap();
+        workbook.save(path).unwrap();
+    }
+
+    #[test]
+    fn test_exit_code_of_an_invalid_row() {
+        let test_file = std::env::temp_dir().join(format!("invalid_{}.xlsx", std::process::id()));
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let sheet = workbook.add_worksheet();
+        sheet.write_string(0, 0, "id").unwrap();
+        sheet.write_string(1, 0, "Group: Orders").unwrap();
+        sheet.write_number(2, 0, 1).unwrap();
+        let cells = [
+            "List the orders",
+            "some orders",
+            "they are listed",
+            "a list is returned",
+            "http://127.0.0.1:1/orders",
+            "FETCH",
+        ];
+        for (col, cell) in (1..).zip(cells) {
+            sheet.write_string(2, col, cell).unwrap();
+        }
+        sheet
+            .write_string(2, 11, "SAT.tester('listed', () => true)")
+            .unwrap();
+        workbook.save(&test_file).unwrap();
+
+        // The skipped row aborts the run, which still exits as invalid rather than failed.
+        let (tsat, events) = TSat::new();
+        let result = tsat.exec(&test_file.to_string_lossy(), &Config::default());
+        assert!(result.is_err());
+        let mut summary = RunSummary::default();
+        for event in events.try_iter() {
+            summary.on_event(&event);
+        }
+        assert_eq!((summary.invalid, summary.failed), (1, 0));
+        assert_eq!(summary.exit_code_of_run(&result), EXIT_INVALID);
+        std::fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_table_of_the_test_file() {
+        let test_file = std::env::temp_dir().join(format!("tables_{}.xlsx", std::process::id()));
+        let (url, rx) = capture_server();
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let sheet = workbook.add_worksheet().set_name("Sheet1").unwrap();
+        sheet.write_string(0, 0, "id").unwrap();
+        sheet.write_string(1, 0, "Group: Users").unwrap();
+        sheet.write_number(2, 0, 1).unwrap();
+        let cells = [
+            "Invite the admin",
+            "an admin",
+            "it is invited",
+            "an invite is sent",
+            &url,
+            "POST",
+            "Content-Type: application/json",
+            r#"{"email": "{{table:Users.admin.email}}"}"#,
+        ];
+        for (col, cell) in (1..).zip(cells) {
+            sheet.write_string(2, col, cell).unwrap();
+        }
+        sheet
+            .write_string(2, 11, "SAT.tester('sent', () => true)")
+            .unwrap();
+        let users = workbook.add_worksheet().set_name("Users").unwrap();
+        users.write_row(0, 0, ["key", "email"]).unwrap();
+        users.write_row(1, 0, ["admin", "admin@acme.io"]).unwrap();
+        workbook.save(&test_file).unwrap();
+
+        // The test file is not the one of the config.
+        let (tsat, _events) = TSat::new();
+        let config = Config {
+            worksheet: Some("Sheet1".to_string()),
+            ..Config::default()
+        };
+        tsat.exec(&test_file.to_string_lossy(), &config).unwrap();
+        let body = String::from_utf8(rx.recv().unwrap().1).unwrap();
+        assert!(body.contains(r#""email": "admin@acme.io""#), "{}", body);
+        std::fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_upload_relative_to_the_test_file() {
+        // The test file and the uploaded file are in a directory other than the current one.
+        let dir = std::env::temp_dir().join(format!("uploads_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("files")).unwrap();
+        std::fs::write(dir.join("files/doc.txt"), "next to the test file").unwrap();
+        let test_file = dir.join("tests.xlsx");
+        let (tsat, _events) = TSat::new();
+
+        let (url, rx) = capture_server();
+        write_upload_workbook(&test_file, &url, "files/doc.txt");
+        tsat.exec(&test_file.to_string_lossy(), &Config::default())
+            .unwrap();
+        let body = String::from_utf8(rx.recv().unwrap().1).unwrap();
+        assert!(body.contains("next to the test file"), "{}", body);
+
+        // Or against the `--base-dir`.
+        let base_dir = dir.join("fixtures");
+        std::fs::create_dir_all(base_dir.join("files")).unwrap();
+        std::fs::write(base_dir.join("files/doc.txt"), "in the base dir").unwrap();
+        let (url, rx) = capture_server();
+        write_upload_workbook(&test_file, &url, "files/doc.txt");
+        let config = Config {
+            base_dir: Some(base_dir.to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        tsat.exec(&test_file.to_string_lossy(), &config).unwrap();
+        let body = String::from_utf8(rx.recv().unwrap().1).unwrap();
+        assert!(body.contains("in the base dir"), "{}", body);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -1,6 +1,7 @@
 use std::process;
 
 use satyanaash::config::Config; // Import the TestOptions struct
+use satyanaash::run_summary::{RunSummary, EXIT_INVALID};
 
 fn main() {
     // Open banner file, if existing and print its contents to screen...
@@ -12,6 +13,10 @@ fn main() {
         process::exit(1);
     });
 
+    if config.no_color {
+        colored::control::set_override(false);
+    }
+
     // extract the test file from the config
     let test_file = config.test_file.clone().unwrap_or_else(|| {
         eprintln!("Test file not provided");
@@ -19,7 +24,52 @@ fn main() {
     });
 
     // Create an instance of test framework..
-    let (sat, _listener) = satyanaash::TSat::new();
+    let (sat, listener) = satyanaash::TSat::new();
+
+    // Only check the columns of the template, and exit.
+    if config.validate_template {
+        match satyanaash::TSat::validate_template(&test_file, &config) {
+            Ok(0) => process::exit(0),
+            Ok(problems) => {
+                eprintln!("Found {} problem(s) in the template", problems);
+                process::exit(EXIT_INVALID);
+            }
+            Err(err) => {
+                eprintln!("Error validating the template: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    // In lint mode, only validate the test cases and exit.
+    if config.lint {
+        match sat.lint(&test_file, &config) {
+            Ok(0) => process::exit(0),
+            Ok(invalid_rows) => {
+                eprintln!("Found {} invalid row(s)", invalid_rows);
+                process::exit(EXIT_INVALID);
+            }
+            Err(err) => {
+                eprintln!("Error linting test cases: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    // In benchmark mode, only hammer the given test case, and print its latencies.
+    if let Some(case_id) = config.benchmark {
+        match satyanaash::TSat::benchmark(&test_file, case_id, &config) {
+            Ok(report) => {
+                println!("{}", report);
+                process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("Error benchmarking test case: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+    let mut reporters = satyanaash::reporters::build_reporters(&config);
 
     /*
     // Get the listener and create a thread for event handling
@@ -31,8 +81,24 @@ fn main() {
     */
 
     //execute test cases..
-    if let Err(err) = sat.exec(&test_file, &config) {
+    let result = sat.exec(&test_file, &config);
+
+    // Feed the events fired during the run to the reporters, and let them write out their reports.
+    let mut summary = RunSummary::default();
+    for event in listener.try_iter() {
+        summary.on_event(&event);
+        for reporter in reporters.iter_mut() {
+            reporter.on_event(&event);
+        }
+    }
+    for reporter in reporters.iter_mut() {
+        if let Err(err) = reporter.finish() {
+            eprintln!("Error writing report: {}", err);
+        }
+    }
+
+    if let Err(err) = &result {
         eprintln!("Error executing test cases: {}", err);
-        process::exit(1);
     }
+    process::exit(summary.exit_code_of_run(&result));
 }
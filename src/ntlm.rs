@@ -0,0 +1,268 @@
+/* NTLM (v2) authentication, for the intranet endpoints behind NTLM or Negotiate (with an
+    NTLM token, Kerberos is not supported). Built with the `ntlm` feature.
+
+    The handshake is made per request, on a kept-alive connection: the request is sent with
+    a negotiate message, the server answers 401 with its challenge, and the request is sent
+    again with the authenticate message computed from the challenge and the credentials.
+*/
+
+use crate::config::NtlmConfig;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+// Unicode, request target, NTLM, always sign, extended session security, 128 and 56 bits.
+const FLAGS: u32 = 0xa208_8205;
+// Seconds between 1601-01-01 (the windows epoch) and 1970-01-01.
+const EPOCH_DIFF_SECS: u64 = 11_644_473_600;
+
+// The challenge of the server, from its type 2 message.
+#[derive(Debug, PartialEq)]
+pub struct Challenge {
+    pub server_challenge: [u8; 8],
+    pub target_info: Vec<u8>,
+}
+
+// The type 1 (negotiate) message, base64 encoded.
+pub fn negotiate_message() -> String {
+    let mut msg = Vec::with_capacity(32);
+    msg.extend_from_slice(SIGNATURE);
+    msg.extend_from_slice(&1u32.to_le_bytes());
+    msg.extend_from_slice(&FLAGS.to_le_bytes());
+    // Empty domain and workstation.
+    msg.extend_from_slice(&[0; 16]);
+    STANDARD.encode(msg)
+}
+
+// Parses the challenge out of the `WWW-Authenticate` header of the 401 response, for the
+// given scheme (NTLM or Negotiate).
+pub fn parse_challenge(header: &str, scheme: &str) -> Result<Challenge, Box<dyn Error>> {
+    let token = header
+        .split(',')
+        .map(str::trim)
+        .find_map(|value| {
+            let (name, token) = value.split_once(' ')?;
+            name.eq_ignore_ascii_case(scheme).then(|| token.trim())
+        })
+        .ok_or_else(|| format!("No {} challenge in: {}", scheme, header))?;
+    let msg = STANDARD.decode(token)?;
+    if msg.len() < 48 || &msg[..8] != SIGNATURE || msg[8..12] != 2u32.to_le_bytes() {
+        return Err("Not an NTLM challenge message".into());
+    }
+    let mut server_challenge = [0; 8];
+    server_challenge.copy_from_slice(&msg[24..32]);
+    let len = u16::from_le_bytes([msg[40], msg[41]]) as usize;
+    let offset = u32::from_le_bytes([msg[44], msg[45], msg[46], msg[47]]) as usize;
+    let target_info = msg
+        .get(offset..offset + len)
+        .ok_or("Invalid target info in the NTLM challenge")?
+        .to_vec();
+    Ok(Challenge {
+        server_challenge,
+        target_info,
+    })
+}
+
+// The type 3 (authenticate) message answering the challenge, base64 encoded.
+pub fn authenticate_message(creds: &NtlmConfig, password: &str, challenge: &Challenge) -> String {
+    let client_challenge: [u8; 8] = rand::random();
+    let timestamp = av_timestamp(&challenge.target_info).unwrap_or_else(now_filetime);
+    authenticate_message_with(creds, password, challenge, client_challenge, timestamp)
+}
+
+fn authenticate_message_with(
+    creds: &NtlmConfig,
+    password: &str,
+    challenge: &Challenge,
+    client_challenge: [u8; 8],
+    timestamp: u64,
+) -> String {
+    let domain = creds.domain.clone().unwrap_or_default();
+    let workstation = creds.workstation.clone().unwrap_or_default();
+    let key = ntowf_v2(&creds.user, password, &domain);
+    let blob = client_blob(client_challenge, timestamp, &challenge.target_info);
+    let nt_response = [
+        nt_proof(&key, &challenge.server_challenge, &blob).as_slice(),
+        &blob,
+    ]
+    .concat();
+    let lm_response = [
+        hmac_md5(&key, &[&challenge.server_challenge, &client_challenge]).as_slice(),
+        &client_challenge,
+    ]
+    .concat();
+
+    let fields = [
+        lm_response,
+        nt_response,
+        utf16le(&domain),
+        utf16le(&creds.user),
+        utf16le(&workstation),
+        vec![], // No session key.
+    ];
+    let mut msg = Vec::new();
+    msg.extend_from_slice(SIGNATURE);
+    msg.extend_from_slice(&3u32.to_le_bytes());
+    let mut offset = 64u32;
+    for field in &fields {
+        msg.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&offset.to_le_bytes());
+        offset += field.len() as u32;
+    }
+    msg.extend_from_slice(&FLAGS.to_le_bytes());
+    for field in &fields {
+        msg.extend_from_slice(field);
+    }
+    STANDARD.encode(msg)
+}
+
+// The NTLMv2 key: HMAC-MD5 of the uppercase user and the domain, keyed by the MD4 of the password.
+fn ntowf_v2(user: &str, password: &str, domain: &str) -> [u8; 16] {
+    let nt_hash = Md4::digest(utf16le(password));
+    let identity = utf16le(&format!("{}{}", user.to_uppercase(), domain));
+    hmac_md5(&nt_hash, &[&identity])
+}
+
+fn nt_proof(key: &[u8; 16], server_challenge: &[u8; 8], blob: &[u8]) -> [u8; 16] {
+    hmac_md5(key, &[server_challenge, blob])
+}
+
+fn client_blob(client_challenge: [u8; 8], timestamp: u64, target_info: &[u8]) -> Vec<u8> {
+    let mut blob = vec![1, 1, 0, 0, 0, 0, 0, 0];
+    blob.extend_from_slice(&timestamp.to_le_bytes());
+    blob.extend_from_slice(&client_challenge);
+    blob.extend_from_slice(&[0; 4]);
+    blob.extend_from_slice(target_info);
+    blob.extend_from_slice(&[0; 4]);
+    blob
+}
+
+fn hmac_md5(key: &[u8], parts: &[&[u8]]) -> [u8; 16] {
+    let mut mac = Hmac::<Md5>::new_from_slice(key).expect("HMAC takes a key of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+// The server's time (MsvAvTimestamp) from the target info, if it is there.
+fn av_timestamp(target_info: &[u8]) -> Option<u64> {
+    let mut rest = target_info;
+    while rest.len() >= 4 {
+        let id = u16::from_le_bytes([rest[0], rest[1]]);
+        let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let value = rest.get(4..4 + len)?;
+        match id {
+            0 => return None, // MsvAvEOL
+            7 if len == 8 => return Some(u64::from_le_bytes(value.try_into().ok()?)),
+            _ => rest = &rest[4 + len..],
+        }
+    }
+    None
+}
+
+fn now_filetime() -> u64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (since_epoch.as_secs() + EPOCH_DIFF_SECS) * 10_000_000 + since_epoch.subsec_nanos() as u64 / 100
+}
+
+fn utf16le(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The NTLMv2 test vectors of the MS-NLMP spec (4.2.4).
+    fn spec_creds() -> NtlmConfig {
+        NtlmConfig {
+            user: "User".to_string(),
+            domain: Some("Domain".to_string()),
+            workstation: Some("COMPUTER".to_string()),
+            ..NtlmConfig::default()
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // MsvAvNbDomainName "Domain", MsvAvNbComputerName "Server", MsvAvEOL.
+    fn spec_target_info() -> Vec<u8> {
+        let mut info = vec![2, 0, 12, 0];
+        info.extend(utf16le("Domain"));
+        info.extend([1, 0, 12, 0]);
+        info.extend(utf16le("Server"));
+        info.extend([0, 0, 0, 0]);
+        info
+    }
+
+    #[test]
+    fn test_ntlmv2_spec_vectors() {
+        let key = ntowf_v2("User", "Password", "Domain");
+        assert_eq!(hex(&key), "0c868a403bfd7a93a3001ef22ef02e3f");
+
+        let server_challenge = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+        let blob = client_blob([0xaa; 8], 0, &spec_target_info());
+        assert_eq!(
+            hex(&nt_proof(&key, &server_challenge, &blob)),
+            "68cd0ab851e51c96aabc927bebef6a1c"
+        );
+    }
+
+    #[test]
+    fn test_authenticate_message() {
+        let challenge = Challenge {
+            server_challenge: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef],
+            target_info: spec_target_info(),
+        };
+        let msg = STANDARD
+            .decode(authenticate_message_with(
+                &spec_creds(),
+                "Password",
+                &challenge,
+                [0xaa; 8],
+                0,
+            ))
+            .unwrap();
+        assert_eq!(&msg[..8], SIGNATURE);
+        assert_eq!(msg[8..12], 3u32.to_le_bytes());
+
+        // The NT response starts with the proof of the spec.
+        let offset = u32::from_le_bytes(msg[24..28].try_into().unwrap()) as usize;
+        assert_eq!(
+            hex(&msg[offset..offset + 16]),
+            "68cd0ab851e51c96aabc927bebef6a1c"
+        );
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let target_info = spec_target_info();
+        let mut msg = Vec::new();
+        msg.extend_from_slice(SIGNATURE);
+        msg.extend_from_slice(&2u32.to_le_bytes());
+        msg.extend_from_slice(&[0; 8]); // Target name.
+        msg.extend_from_slice(&FLAGS.to_le_bytes());
+        msg.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        msg.extend_from_slice(&[0; 8]);
+        msg.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&48u32.to_le_bytes());
+        msg.extend_from_slice(&target_info);
+
+        let header = format!("Basic realm=\"x\", NTLM {}", STANDARD.encode(&msg));
+        let challenge = parse_challenge(&header, "NTLM").unwrap();
+        assert_eq!(challenge.server_challenge, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(challenge.target_info, target_info);
+        assert!(parse_challenge(&header, "Negotiate").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+/* Masks the secrets of a value before it leaves the run (ex: the globals written to a
+    report).  A field is a secret if its name contains one of the `redact_keys` of the config
+    (case-insensitive), or one of the default ones otherwise.
+*/
+
+use crate::config::Config;
+use serde_json::Value;
+
+pub const MASK: &str = "***";
+
+const DEFAULT_KEYS: [&str; 7] = [
+    "password",
+    "secret",
+    "token",
+    "authorization",
+    "api_key",
+    "apikey",
+    "cookie",
+];
+
+// The (lowercased) parts of the field names to be masked.
+pub fn keys(config: &Config) -> Vec<String> {
+    match &config.redact_keys {
+        Some(keys) => keys.iter().map(|key| key.to_lowercase()).collect(),
+        None => DEFAULT_KEYS.iter().map(|key| key.to_string()).collect(),
+    }
+}
+
+// Masks the value of every field, at any depth, whose name contains one of the keys.
+pub fn redact(value: &mut Value, keys: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (name, value) in map.iter_mut() {
+                let name = name.to_lowercase();
+                if keys.iter().any(|key| name.contains(key.as_str())) {
+                    *value = Value::String(MASK.to_string());
+                } else {
+                    redact(value, keys);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| redact(v, keys)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact() {
+        let mut value = json!({
+            "userId": 7,
+            "accessToken": "abc",
+            "client": { "Client_Secret": "s3cr3t", "name": "cli" },
+            "users": [{ "password": "pw", "login": "ann" }],
+        });
+        redact(&mut value, &keys(&Config::default()));
+        assert_eq!(
+            value,
+            json!({
+                "userId": 7,
+                "accessToken": "***",
+                "client": { "Client_Secret": "***", "name": "cli" },
+                "users": [{ "password": "***", "login": "ann" }],
+            })
+        );
+
+        // The configured keys replace the default ones.
+        let config = Config {
+            redact_keys: Some(vec!["Login".to_string()]),
+            ..Config::default()
+        };
+        let mut value = json!({ "login": "ann", "password": "pw" });
+        redact(&mut value, &keys(&config));
+        assert_eq!(value, json!({ "login": "***", "password": "pw" }));
+    }
+}
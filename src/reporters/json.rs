@@ -0,0 +1,72 @@
+/* A JSON report of the run: the groups, with the result, status and duration of
+    their test cases.  With `report_globals`, a group also carries its final
+    `SAT.globals` (with the secrets masked) and the names of its captured tokens,
+    to debug the chained flows.
+*/
+
+use super::Reporter;
+use crate::test_events::TestEvent;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs;
+
+#[derive(Debug, Default)]
+pub struct JsonReporter {
+    path: String,
+    groups: Vec<Value>,
+}
+
+impl JsonReporter {
+    pub fn new(path: &str) -> Self {
+        JsonReporter {
+            path: path.to_string(),
+            groups: vec![],
+        }
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn on_event(&mut self, event: &TestEvent) {
+        match event {
+            TestEvent::EvtTestGroupBegin(begin) => self.groups.push(json!({
+                "name": begin.group_name,
+                "cases": [],
+            })),
+            TestEvent::EvtTestCaseResult(end) => {
+                let Some(cases) = self
+                    .groups
+                    .last_mut()
+                    .and_then(|g| g["cases"].as_array_mut())
+                else {
+                    return;
+                };
+                cases.push(json!({
+                    "id": end.testcase_id,
+                    "result": format!("{:?}", end.result),
+                    "status": end.status,
+                    "duration_ms": end.exec_duration.as_millis() as u64,
+                }));
+            }
+            TestEvent::EvtTestGroupEnd(end) => {
+                if let Some(group) = self.groups.last_mut() {
+                    group["duration_ms"] = json!(end.exec_duration.as_millis() as u64);
+                    if let Some(globals) = &end.globals {
+                        group["globals"] = globals.clone();
+                        group["tokens"] = json!(end.tokens);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        fs::write(
+            &self.path,
+            serde_json::to_string_pretty(&json!({ "groups": self.groups }))? + "\n",
+        )
+        .map_err(|e| format!("Failed to write JSON report '{}': {}", self.path, e))?;
+        println!("JSON report written to: {}", self.path);
+        Ok(())
+    }
+}
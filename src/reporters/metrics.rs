@@ -0,0 +1,177 @@
+/* Metrics of the run in the Prometheus text format (`--metrics`), for the scheduled runs
+    feeding a monitoring system (ex: through the textfile collector of the node exporter):
+    the counts of the test cases by their result, and a histogram of the latencies of
+    their requests.
+*/
+
+use super::Reporter;
+use crate::test_case::TestResult;
+use crate::test_events::TestEvent;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+
+// Upper bounds of the latency buckets, in seconds (the default ones of the Prometheus clients).
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Default)]
+pub struct MetricsReporter {
+    path: String,
+    group: String,
+    results: HashMap<(String, u32), TestResult>, // Result of each test case, by its group and id.
+    latencies: Vec<f64>,                         // Of every request sent, in seconds.
+}
+
+impl MetricsReporter {
+    pub fn new(path: &str) -> Self {
+        MetricsReporter {
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn count(&self, result: TestResult) -> usize {
+        self.results.values().filter(|r| **r == result).count()
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        // Snapshots of this run, so gauges: they don't accumulate across the runs.
+        let gauges = [
+            ("satya_tests_total", "Test cases run.", self.results.len()),
+            (
+                "satya_tests_passed",
+                "Test cases passed.",
+                self.count(TestResult::Passed),
+            ),
+            (
+                "satya_tests_failed",
+                "Test cases failed.",
+                self.count(TestResult::Failed),
+            ),
+            (
+                "satya_tests_skipped",
+                "Test cases skipped.",
+                self.count(TestResult::Skipped),
+            ),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        let name = "satya_request_duration_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Latency of the requests of the test cases.",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for le in BUCKETS {
+            let count = self.latencies.iter().filter(|l| **l <= le).count();
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, count);
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{le=\"+Inf\"}} {}",
+            name,
+            self.latencies.len()
+        );
+        let _ = writeln!(out, "{}_sum {}", name, self.latencies.iter().sum::<f64>());
+        let _ = writeln!(out, "{}_count {}", name, self.latencies.len());
+        out
+    }
+}
+
+impl Reporter for MetricsReporter {
+    fn on_event(&mut self, event: &TestEvent) {
+        match event {
+            TestEvent::EvtTestGroupBegin(begin) => self.group = begin.group_name.clone(),
+            // A repeated test case fires an end event per iteration, each one is a request.
+            TestEvent::EvtTestCaseEnd(end) if end.result != TestResult::Skipped => {
+                self.latencies.push(end.exec_duration.as_secs_f64());
+            }
+            TestEvent::EvtTestCaseResult(end) => {
+                self.results
+                    .insert((self.group.clone(), end.testcase_id), end.result.clone());
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        fs::write(&self.path, self.render())
+            .map_err(|e| format!("Failed to write metrics '{}': {}", self.path, e))?;
+        println!("Metrics written to: {}", self.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_events::{TestCaseEnd, TestGroupBegin};
+    use std::time::{Duration, Instant};
+
+    // The events of a test case: an end event per iteration, then its result.
+    fn case_evts(id: u32, iterations: &[(TestResult, u64)], result: TestResult) -> Vec<TestEvent> {
+        let mut events: Vec<TestEvent> = iterations
+            .iter()
+            .map(|(result, millis)| {
+                TestEvent::EvtTestCaseEnd(TestCaseEnd {
+                    exec_duration: Duration::from_millis(*millis),
+                    ..TestCaseEnd::new(id, result.clone())
+                })
+            })
+            .collect();
+        events.push(TestEvent::EvtTestCaseResult(TestCaseEnd::new(id, result)));
+        events
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut reporter = MetricsReporter::new("metrics.prom");
+        reporter.on_event(&TestEvent::EvtTestGroupBegin(TestGroupBegin {
+            timestamp: Instant::now(),
+            iteration_id: "1".to_string(),
+            group_name: "Orders".to_string(),
+        }));
+        let events = [
+            case_evts(1, &[(TestResult::Passed, 125)], TestResult::Passed),
+            case_evts(2, &[(TestResult::Failed, 250)], TestResult::Failed),
+            // A repeated test case, whose first iteration failed and last one passed.
+            case_evts(
+                3,
+                &[(TestResult::Failed, 500), (TestResult::Passed, 2000)],
+                TestResult::Failed,
+            ),
+            case_evts(4, &[(TestResult::Skipped, 0)], TestResult::Skipped),
+        ];
+        for event in events.iter().flatten() {
+            reporter.on_event(event);
+        }
+
+        let metrics = reporter.render();
+        for line in [
+            "# TYPE satya_tests_total gauge",
+            "satya_tests_total 4",
+            "satya_tests_passed 1",
+            "satya_tests_failed 2",
+            "satya_tests_skipped 1",
+            "# TYPE satya_request_duration_seconds histogram",
+            "satya_request_duration_seconds_bucket{le=\"0.1\"} 0",
+            "satya_request_duration_seconds_bucket{le=\"0.25\"} 2",
+            "satya_request_duration_seconds_bucket{le=\"1\"} 3",
+            "satya_request_duration_seconds_bucket{le=\"2.5\"} 4",
+            "satya_request_duration_seconds_bucket{le=\"+Inf\"} 4",
+            "satya_request_duration_seconds_sum 2.875",
+            "satya_request_duration_seconds_count 4",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "{}\n{}", line, metrics);
+        }
+    }
+}
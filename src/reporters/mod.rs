@@ -0,0 +1,44 @@
+/* Reporters consume the test events fired during a run and produce
+    artifacts (files, summaries, etc.) out of them, once the run is over.
+*/
+
+mod json;
+mod metrics;
+mod schema;
+mod traceability;
+
+use crate::config::Config;
+use crate::test_events::TestEvent;
+use std::error::Error;
+
+pub use json::JsonReporter;
+pub use metrics::MetricsReporter;
+pub use schema::SchemaReporter;
+pub use traceability::TraceabilityReporter;
+
+pub trait Reporter {
+    // Invoked for every event fired by the test framework, in the order of firing.
+    fn on_event(&mut self, event: &TestEvent);
+
+    // Invoked once all the events are consumed, to write out the report.
+    fn finish(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+// Builds the reporters enabled in the config.
+pub fn build_reporters(config: &Config) -> Vec<Box<dyn Reporter>> {
+    let mut reporters: Vec<Box<dyn Reporter>> = vec![];
+
+    if let Some(path) = &config.report_traceability {
+        reporters.push(Box::new(TraceabilityReporter::new(path)));
+    }
+    if let Some(dir) = &config.infer_schemas {
+        reporters.push(Box::new(SchemaReporter::new(dir)));
+    }
+    if let Some(path) = &config.report_json {
+        reporters.push(Box::new(JsonReporter::new(path)));
+    }
+    if let Some(path) = &config.metrics {
+        reporters.push(Box::new(MetricsReporter::new(path)));
+    }
+    reporters
+}
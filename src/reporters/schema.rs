@@ -0,0 +1,108 @@
+/* Infers a JSON Schema (types and required fields) from the json response of
+    every test case, and writes it to `<dir>/<test case id>.schema.json`, as a
+    starting point to be reviewed and tightened by hand.
+*/
+
+use super::Reporter;
+use crate::test_events::TestEvent;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Default)]
+pub struct SchemaReporter {
+    dir: String,
+    schemas: BTreeMap<u32, Value>, // By test case id.
+}
+
+impl SchemaReporter {
+    pub fn new(dir: &str) -> Self {
+        SchemaReporter {
+            dir: dir.to_string(),
+            schemas: BTreeMap::new(),
+        }
+    }
+}
+
+impl Reporter for SchemaReporter {
+    fn on_event(&mut self, event: &TestEvent) {
+        // Of a repeated test case, the response of its last iteration.
+        if let TestEvent::EvtTestCaseResult(end) = event {
+            if let Some(json) = end.response_json.as_ref().filter(|json| !json.is_null()) {
+                let mut schema = infer_schema(json);
+                schema["$schema"] = json!("http://json-schema.org/draft-07/schema#");
+                self.schemas.insert(end.testcase_id, schema);
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create schema directory '{}': {}", self.dir, e))?;
+        for (id, schema) in &self.schemas {
+            let path = Path::new(&self.dir).join(format!("{}.schema.json", id));
+            fs::write(&path, serde_json::to_string_pretty(schema)? + "\n")
+                .map_err(|e| format!("Failed to write schema '{}': {}", path.display(), e))?;
+        }
+        println!(
+            "{} schema(s) inferred into: {}",
+            self.schemas.len(),
+            self.dir
+        );
+        Ok(())
+    }
+}
+
+// The schema of the value. Every field of an object is required, and the items of an array
+// are described by the schema of its first item.
+pub fn infer_schema(value: &Value) -> Value {
+    match value {
+        Value::Null => json!({ "type": "null" }),
+        Value::Bool(_) => json!({ "type": "boolean" }),
+        Value::Number(n) if n.is_f64() => json!({ "type": "number" }),
+        Value::Number(_) => json!({ "type": "integer" }),
+        Value::String(_) => json!({ "type": "string" }),
+        Value::Array(items) => match items.first() {
+            Some(item) => json!({ "type": "array", "items": infer_schema(item) }),
+            None => json!({ "type": "array" }),
+        },
+        Value::Object(fields) => {
+            let properties: Map<String, Value> = fields
+                .iter()
+                .map(|(key, value)| (key.clone(), infer_schema(value)))
+                .collect();
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": fields.keys().collect::<Vec<_>>(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_object_schema() {
+        let response =
+            json!({ "id": 7, "name": "ann", "score": 4.5, "tags": ["a"], "manager": null });
+        assert_eq!(
+            infer_schema(&response),
+            json!({
+                "type": "object",
+                "properties": {
+                    "id": { "type": "integer" },
+                    "name": { "type": "string" },
+                    "score": { "type": "number" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                    "manager": { "type": "null" },
+                },
+                "required": ["id", "name", "score", "tags", "manager"],
+            })
+        );
+    }
+}
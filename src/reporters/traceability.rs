@@ -0,0 +1,170 @@
+/* A requirements traceability matrix, linking the Given / When / Then of
+    every test case to its result.  Written as a markdown table if the
+    report path ends with `.md`, as CSV otherwise.
+*/
+
+use super::Reporter;
+use crate::test_events::TestEvent;
+use std::error::Error;
+use std::fs;
+
+const COLUMNS: [&str; 7] = ["id", "given", "when", "then", "notes", "result", "status"];
+
+#[derive(Debug, Default)]
+pub struct TraceabilityReporter {
+    path: String,
+    rows: Vec<[String; 7]>,
+}
+
+impl TraceabilityReporter {
+    pub fn new(path: &str) -> Self {
+        TraceabilityReporter {
+            path: path.to_string(),
+            rows: vec![],
+        }
+    }
+
+    fn render(&self) -> String {
+        if self.path.ends_with(".md") {
+            self.render_markdown()
+        } else {
+            self.render_csv()
+        }
+    }
+
+    fn render_csv(&self) -> String {
+        let mut lines = vec![COLUMNS.join(",")];
+        for row in &self.rows {
+            let fields: Vec<String> = row.iter().map(|f| csv_escape(f)).collect();
+            lines.push(fields.join(","));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    fn render_markdown(&self) -> String {
+        let mut lines = vec![
+            format!("| {} |", COLUMNS.join(" | ")),
+            format!("|{}", "---|".repeat(COLUMNS.len())),
+        ];
+        for row in &self.rows {
+            let fields: Vec<String> = row.iter().map(|f| markdown_escape(f)).collect();
+            lines.push(format!("| {} |", fields.join(" | ")));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+impl Reporter for TraceabilityReporter {
+    fn on_event(&mut self, event: &TestEvent) {
+        match event {
+            TestEvent::EvtTestCaseBegin(begin) => self.rows.push([
+                begin.testcase_id.to_string(),
+                begin.given.clone(),
+                begin.when.clone(),
+                begin.then.clone(),
+                begin.notes.clone().unwrap_or_default(),
+                String::new(),
+                String::new(),
+            ]),
+            TestEvent::EvtTestCaseResult(end) => {
+                let id = end.testcase_id.to_string();
+                if let Some(row) = self.rows.iter_mut().rev().find(|row| row[0] == id) {
+                    row[5] = format!("{:?}", end.result);
+                    row[6] = end.status.to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        fs::write(&self.path, self.render())
+            .map_err(|e| format!("Failed to write traceability report '{}': {}", self.path, e))?;
+        println!("Traceability report written to: {}", self.path);
+        Ok(())
+    }
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn markdown_escape(field: &str) -> String {
+    field.replace('|', "\\|").replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_case::TestResult;
+    use crate::test_events::{TestCaseBegin, TestCaseEnd};
+    use std::time::Instant;
+
+    fn begin_evt(id: u32, given: &str) -> TestEvent {
+        TestEvent::EvtTestCaseBegin(TestCaseBegin {
+            timestamp: Instant::now(),
+            iteration_id: "1".to_string(),
+            testcase_id: id,
+            testcase_name: "login".to_string(),
+            given: given.to_string(),
+            when: "credentials are posted".to_string(),
+            then: "a token is returned".to_string(),
+            url: "http://localhost:3000/api/login".to_string(),
+            method: "POST".to_string(),
+            headers: vec![],
+            payload: "".to_string(),
+            pre_test_script: None,
+            post_test_script: None,
+            notes: None,
+        })
+    }
+
+    fn result_evt(id: u32, result: TestResult, status: i64) -> TestEvent {
+        TestEvent::EvtTestCaseResult(TestCaseEnd {
+            status,
+            ..TestCaseEnd::new(id, result)
+        })
+    }
+
+    #[test]
+    fn test_csv_matrix() {
+        let mut reporter = TraceabilityReporter::new("matrix.csv");
+        reporter.on_event(&begin_evt(1, "a valid user"));
+        reporter.on_event(&result_evt(1, TestResult::Passed, 200));
+        reporter.on_event(&begin_evt(2, "an invalid user, with a \"bad\" password"));
+        reporter.on_event(&result_evt(2, TestResult::Failed, 401));
+
+        let lines: Vec<String> = reporter.render().lines().map(String::from).collect();
+        assert_eq!(lines[0], "id,given,when,then,notes,result,status");
+        assert_eq!(
+            lines[1],
+            "1,a valid user,credentials are posted,a token is returned,,Passed,200"
+        );
+        assert_eq!(
+            lines[2],
+            "2,\"an invalid user, with a \"\"bad\"\" password\",credentials are posted,a token is returned,,Failed,401"
+        );
+    }
+
+    #[test]
+    fn test_markdown_matrix() {
+        let mut reporter = TraceabilityReporter::new("matrix.md");
+        reporter.on_event(&begin_evt(1, "a valid user"));
+        reporter.on_event(&result_evt(1, TestResult::Skipped, 0));
+
+        let lines: Vec<String> = reporter.render().lines().map(String::from).collect();
+        assert_eq!(
+            lines[0],
+            "| id | given | when | then | notes | result | status |"
+        );
+        assert_eq!(lines[1], "|---|---|---|---|---|---|---|");
+        assert_eq!(
+            lines[2],
+            "| 1 | a valid user | credentials are posted | a token is returned |  | Skipped | 0 |"
+        );
+    }
+}
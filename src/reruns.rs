@@ -0,0 +1,179 @@
+/* Collects the rows of the failed test cases, along with their groups, and writes
+    them to a new workbook (`--write-failed`).  Running that workbook reruns only
+    the failures.
+*/
+
+use calamine::Data;
+use rust_xlsxwriter::{Workbook, Worksheet, XlsxError};
+use std::error::Error;
+
+#[derive(Debug, Default)]
+pub struct Reruns {
+    sheets: Vec<RerunSheet>,
+}
+
+#[derive(Debug)]
+struct RerunSheet {
+    name: String,
+    rows: Vec<Vec<Data>>, // The header row, followed by the group and test case rows.
+    group: Option<String>, // Group of the last recorded row.
+    group_start: usize,   // Index of the row of that group.
+    failed: usize,
+}
+
+impl Reruns {
+    // Starts recording the failures of a worksheet, whose first row is the header row.
+    pub fn start_sheet(&mut self, name: &str, header: &[Data]) {
+        self.sheets.push(RerunSheet {
+            name: name.to_string(),
+            rows: vec![header.to_vec()],
+            group: None,
+            group_start: 0,
+            failed: 0,
+        });
+    }
+
+    // Records the row of a failed test case, preceded by its group row if needed, and by the
+    // authorizers run before it in the group (not yet recorded), for the token it needs.
+    pub fn record(&mut self, group_name: &str, row: &[Data], authorizers: &[&[Data]]) {
+        let Some(sheet) = self.sheets.last_mut() else {
+            return;
+        };
+        if sheet.group.as_deref() != Some(group_name) {
+            let mut group_row = vec![Data::String(format!("Group: {}", group_name))];
+            group_row.resize(row.len(), Data::Empty);
+            sheet.group_start = sheet.rows.len();
+            sheet.rows.push(group_row);
+            sheet.group = Some(group_name.to_string());
+        }
+        for authorizer in authorizers {
+            if !sheet.rows[sheet.group_start..]
+                .iter()
+                .any(|r| r == authorizer)
+            {
+                sheet.rows.push(authorizer.to_vec());
+            }
+        }
+        sheet.rows.push(row.to_vec());
+        sheet.failed += 1;
+    }
+
+    // Writes the worksheets with failures to a new workbook.
+    pub fn write(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let mut workbook = Workbook::new();
+        let mut failed = 0;
+        for sheet in self.sheets.iter().filter(|s| s.group.is_some()) {
+            let worksheet = workbook.add_worksheet();
+            worksheet.set_name(&sheet.name)?;
+            for (r, row) in sheet.rows.iter().enumerate() {
+                for (c, cell) in row.iter().enumerate() {
+                    write_cell(worksheet, r as u32, c as u16, cell)?;
+                }
+            }
+            failed += sheet.failed;
+        }
+        workbook
+            .save(path)
+            .map_err(|e| format!("Failed to write failed test cases to '{}': {}", path, e))?;
+        println!("{} failed test case(s) written to: {}", failed, path);
+        Ok(failed)
+    }
+}
+
+fn write_cell(ws: &mut Worksheet, row: u32, col: u16, cell: &Data) -> Result<(), XlsxError> {
+    match cell {
+        Data::Empty => {}
+        Data::Int(i) => {
+            ws.write_number(row, col, *i as f64)?;
+        }
+        Data::Float(f) => {
+            ws.write_number(row, col, *f)?;
+        }
+        Data::DateTime(dt) => {
+            ws.write_number(row, col, dt.as_f64())?;
+        }
+        Data::Bool(b) => {
+            ws.write_boolean(row, col, *b)?;
+        }
+        other => {
+            ws.write_string(row, col, other.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{open_workbook, DataType, Reader, Xlsx};
+
+    #[test]
+    fn test_write_only_sheets_with_failures() {
+        let header = vec![
+            Data::String("id".to_string()),
+            Data::String("name".to_string()),
+        ];
+        let case = |id: f64| vec![Data::Float(id), Data::String(format!("case {}", id))];
+
+        let mut reruns = Reruns::default();
+        reruns.start_sheet("Passing", &header);
+        reruns.start_sheet("Orders", &header);
+        reruns.record("Create", &case(1.0), &[]);
+        reruns.record("Create", &case(2.0), &[]);
+        reruns.record("Delete", &case(5.0), &[]);
+
+        let path = std::env::temp_dir().join(format!("reruns_{}.xlsx", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert_eq!(reruns.write(path).unwrap(), 3);
+
+        let mut excel: Xlsx<_> = open_workbook(path).unwrap();
+        assert_eq!(excel.sheet_names(), vec!["Orders".to_string()]);
+        let range = excel.worksheet_range("Orders").unwrap();
+        let first_cells: Vec<String> = range
+            .rows()
+            .map(|row| match row[0].get_float() {
+                Some(id) => id.to_string(),
+                None => row[0].get_string().unwrap_or_default().to_string(),
+            })
+            .collect();
+        assert_eq!(
+            first_cells,
+            vec!["id", "Group: Create", "1", "2", "Group: Delete", "5"]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_record_with_authorizers() {
+        let case = |id: f64| vec![Data::Float(id), Data::String(format!("case {}", id))];
+        let (login, login_again) = (case(1.0), case(4.0));
+
+        let mut reruns = Reruns::default();
+        reruns.start_sheet("Orders", &case(0.0));
+        reruns.record("Create", &case(2.0), &[&login]);
+        reruns.record("Create", &case(3.0), &[&login]);
+        reruns.record("Create", &case(5.0), &[&login, &login_again]);
+        // An authorizer which failed itself is recorded once.
+        reruns.record("Delete", &login, &[]);
+        reruns.record("Delete", &case(7.0), &[&login]);
+
+        let sheet = &reruns.sheets[0];
+        let first_cells: Vec<String> = sheet.rows.iter().map(|row| row[0].to_string()).collect();
+        assert_eq!(
+            first_cells,
+            vec![
+                "0",
+                "Group: Create",
+                "1",
+                "2",
+                "3",
+                "4",
+                "5",
+                "Group: Delete",
+                "1",
+                "7"
+            ]
+        );
+        assert_eq!(sheet.failed, 5);
+    }
+}
@@ -0,0 +1,137 @@
+/* Tally of the test case outcomes of a run, and the exit code for it, so that the CI
+    pipelines can branch on the kind of problem:
+    0: every test case passed.
+    1: some test case failed (or the run could not be completed).
+    2: no failures, but some test cases were skipped.
+    3: no failures, but some rows are invalid (ex: a bad method or config json).
+*/
+
+use crate::test_case::TestResult;
+use crate::test_events::TestEvent;
+use std::error::Error;
+
+pub const EXIT_PASSED: i32 = 0;
+pub const EXIT_FAILED: i32 = 1;
+pub const EXIT_SKIPPED: i32 = 2;
+pub const EXIT_INVALID: i32 = 3;
+
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub invalid: usize, // Skipped due to the errors in their rows.
+}
+
+impl RunSummary {
+    pub fn on_event(&mut self, event: &TestEvent) {
+        if let TestEvent::EvtTestCaseResult(end) = event {
+            match end.result {
+                TestResult::Passed => self.passed += 1,
+                TestResult::Failed => self.failed += 1,
+                TestResult::Skipped if !end.errors.is_empty() => self.invalid += 1,
+                TestResult::Skipped => self.skipped += 1,
+                TestResult::NotYetTested => {}
+            }
+        }
+    }
+
+    // Failures take precedence over invalid rows, which take precedence over skips.
+    pub fn exit_code(&self) -> i32 {
+        if self.failed > 0 {
+            EXIT_FAILED
+        } else if self.invalid > 0 {
+            EXIT_INVALID
+        } else if self.skipped > 0 {
+            EXIT_SKIPPED
+        } else {
+            EXIT_PASSED
+        }
+    }
+
+    // The exit code of a run which ended with the result. A skipped test case (or an invalid
+    // row) ends the run with an error too, which is only a failure if nothing failed or was
+    // skipped (ex: the test file could not be read).
+    pub fn exit_code_of_run<T>(&self, result: &Result<T, Box<dyn Error>>) -> i32 {
+        match (result, self.exit_code()) {
+            (Err(_), EXIT_PASSED) => EXIT_FAILED,
+            (_, code) => code,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_events::TestCaseEnd;
+
+    fn result_evt(result: TestResult, errors: Vec<(String, String)>) -> TestEvent {
+        TestEvent::EvtTestCaseResult(TestCaseEnd {
+            errors,
+            ..TestCaseEnd::new(1, result)
+        })
+    }
+
+    fn exit_code(results: Vec<(TestResult, Vec<(String, String)>)>) -> i32 {
+        let mut summary = RunSummary::default();
+        for (result, errors) in results {
+            summary.on_event(&result_evt(result, errors));
+        }
+        summary.exit_code()
+    }
+
+    #[test]
+    fn test_exit_code_of_run() {
+        let mut summary = RunSummary::default();
+        let aborted: Result<(), Box<dyn Error>> = Err("Test Skipped".into());
+        assert_eq!(summary.exit_code_of_run(&Ok(())), EXIT_PASSED);
+        assert_eq!(summary.exit_code_of_run(&aborted), EXIT_FAILED);
+
+        summary.on_event(&result_evt(TestResult::Skipped, vec![]));
+        assert_eq!(summary.exit_code_of_run(&aborted), EXIT_SKIPPED);
+        summary.on_event(&result_evt(TestResult::Failed, vec![]));
+        assert_eq!(summary.exit_code_of_run(&aborted), EXIT_FAILED);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let invalid = || vec![("method".to_string(), "Invalid method".to_string())];
+
+        assert_eq!(exit_code(vec![]), EXIT_PASSED);
+        assert_eq!(
+            exit_code(vec![
+                (TestResult::Passed, vec![]),
+                (TestResult::Passed, vec![])
+            ]),
+            EXIT_PASSED
+        );
+        assert_eq!(
+            exit_code(vec![
+                (TestResult::Passed, vec![]),
+                (TestResult::Failed, vec![])
+            ]),
+            EXIT_FAILED
+        );
+        assert_eq!(
+            exit_code(vec![
+                (TestResult::Skipped, vec![]),
+                (TestResult::Skipped, vec![])
+            ]),
+            EXIT_SKIPPED
+        );
+        assert_eq!(
+            exit_code(vec![
+                (TestResult::Skipped, vec![]),
+                (TestResult::Skipped, invalid())
+            ]),
+            EXIT_INVALID
+        );
+        assert_eq!(
+            exit_code(vec![
+                (TestResult::Failed, vec![]),
+                (TestResult::Skipped, invalid())
+            ]),
+            EXIT_FAILED
+        );
+    }
+}
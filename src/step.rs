@@ -0,0 +1,91 @@
+/* Step mode (`--step`): pauses after each test case, for debugging a flow interactively.
+    Enter runs the next test case, `r` opens a JS console on the context of the group (ex:
+    to inspect `SAT.response` or `SAT.globals`), and `q` stops the run.
+*/
+
+use crate::test_context::TestCtx;
+use std::io::{BufRead, Write};
+
+#[derive(Debug, PartialEq)]
+pub enum StepAction {
+    Continue,
+    Quit,
+}
+
+// Waits for the next command. The end of the input continues, as does Enter.
+pub fn prompt(input: &mut impl BufRead, output: &mut impl Write, ctx: &mut TestCtx) -> StepAction {
+    loop {
+        let _ = write!(output, "[step] Enter: next, r: console, q: quit > ");
+        let _ = output.flush();
+        let mut line = String::new();
+        if input.read_line(&mut line).unwrap_or(0) == 0 {
+            return StepAction::Continue;
+        }
+        match line.trim() {
+            "" => return StepAction::Continue,
+            "q" => return StepAction::Quit,
+            "r" => console(input, output, ctx),
+            other => {
+                let _ = writeln!(output, "Unknown command: {}", other);
+            }
+        }
+    }
+}
+
+// Evaluates each line in the runtime of the group and prints its value, until an empty line.
+fn console(input: &mut impl BufRead, output: &mut impl Write, ctx: &mut TestCtx) {
+    let _ = writeln!(
+        output,
+        "JS console, an empty line returns to the step prompt."
+    );
+    loop {
+        let _ = write!(output, "js> ");
+        let _ = output.flush();
+        let mut line = String::new();
+        if input.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+            return;
+        }
+        match ctx.runtime.eval(line.trim()) {
+            Ok(value) => {
+                let _ = writeln!(output, "{}", value);
+            }
+            Err(e) => {
+                let _ = writeln!(output, "Error: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn prompt_with(input: &str, ctx: &mut TestCtx) -> (StepAction, String) {
+        let mut output = Vec::new();
+        let action = prompt(&mut input.as_bytes(), &mut output, ctx);
+        (action, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_step_prompt() {
+        let mut ctx = TestCtx::new(&Config::default()).unwrap();
+        assert_eq!(prompt_with("\n", &mut ctx).0, StepAction::Continue);
+        assert_eq!(prompt_with("q\n", &mut ctx).0, StepAction::Quit);
+        assert_eq!(prompt_with("", &mut ctx).0, StepAction::Continue);
+
+        // Unknown commands prompt again.
+        let (action, output) = prompt_with("x\nq\n", &mut ctx);
+        assert_eq!(action, StepAction::Quit);
+        assert!(output.contains("Unknown command: x"), "{}", output);
+    }
+
+    #[test]
+    fn test_step_console() {
+        let mut ctx = TestCtx::new(&Config::default()).unwrap();
+        let (action, output) = prompt_with("r\nSAT.globals.answer = 6 * 7\n\n\n", &mut ctx);
+        assert_eq!(action, StepAction::Continue);
+        assert!(output.contains("js> 42"), "{}", output);
+        assert_eq!(ctx.eval_json("SAT.globals.answer"), serde_json::json!(42));
+    }
+}
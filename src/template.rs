@@ -0,0 +1,164 @@
+/* Checks the header row of a worksheet against the expected columns
+    (`--validate-template`).  The rows are parsed by the position of their cells,
+    so a shared template with a missing or moved column would otherwise be
+    silently mis-parsed.
+*/
+
+use calamine::{Data, DataType};
+
+// The expected columns, in order, as (name, accepted header names). The header names are
+// compared case-insensitively, ignoring spaces, dashes and underscores.
+const COLUMNS: [(&str, &[&str]); 19] = [
+    ("id", &["id"]),
+    ("Name", &["name"]),
+    ("Given", &["given"]),
+    ("When", &["when"]),
+    ("Then", &["then"]),
+    ("url", &["url"]),
+    ("method", &["method"]),
+    ("headers", &["headers"]),
+    ("Input Payload", &["inputpayload", "payload", "body"]),
+    ("Config", &["config"]),
+    ("Pre Test Script", &["pretestscript", "prescript"]),
+    ("Post Test Script", &["posttestscript", "postscript"]),
+    ("expected_sha256", &["expectedsha256", "sha256"]),
+    ("notes", &["notes"]),
+    ("final_script", &["finalscript"]),
+    ("expected_json", &["expectedjson"]),
+    ("template", &["template"]),
+    ("expected_error", &["expectederror"]),
+    ("accept_variants", &["acceptvariants"]),
+];
+
+// The columns up to the post test script are required, the later ones are optional.
+const REQUIRED_COLUMNS: usize = 12;
+
+// Returns the problems of the header row: the missing columns, and the ones out of place.
+pub fn validate_header(header: &[Data]) -> Vec<String> {
+    let names: Vec<String> = header
+        .iter()
+        .map(|cell| cell.get_string().unwrap_or_default().trim().to_string())
+        .collect();
+    if names.iter().all(String::is_empty) {
+        return vec!["No header row".to_string()];
+    }
+
+    let mut problems = vec![];
+    for (i, (expected, aliases)) in COLUMNS.iter().enumerate() {
+        let name = names.get(i).map(String::as_str).unwrap_or_default();
+        if name.is_empty() {
+            if i < REQUIRED_COLUMNS {
+                problems.push(format!(
+                    "Column {} is missing, expected '{}'",
+                    column_label(i),
+                    expected
+                ));
+            }
+            continue;
+        }
+        if aliases.contains(&normalize(name).as_str()) {
+            continue;
+        }
+        let problem = match column_of(name) {
+            Some(j) => format!(
+                "Column {} is '{}', expected '{}' ('{}' belongs in column {})",
+                column_label(i),
+                name,
+                expected,
+                name,
+                column_label(j)
+            ),
+            None => format!(
+                "Column {} is '{}', expected '{}'",
+                column_label(i),
+                name,
+                expected
+            ),
+        };
+        problems.push(problem);
+    }
+    problems
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// The index of the expected column with the given header name.
+fn column_of(name: &str) -> Option<usize> {
+    let name = normalize(name);
+    COLUMNS
+        .iter()
+        .position(|(_, aliases)| aliases.contains(&name.as_str()))
+}
+
+// The 1-based number and the letter of the column, ex: "3 (C)".
+fn column_label(index: usize) -> String {
+    format!("{} ({})", index + 1, (b'A' + index as u8) as char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(names: &[&str]) -> Vec<Data> {
+        names.iter().map(|n| Data::String(n.to_string())).collect()
+    }
+
+    const TEMPLATE: [&str; 12] = [
+        "id",
+        "Name",
+        "Given",
+        "When",
+        "Then",
+        "url",
+        "method",
+        "headers",
+        "Input Payload",
+        "Config",
+        "Pre Test Script",
+        "Post Test Script",
+    ];
+
+    #[test]
+    fn test_valid_headers() {
+        assert!(validate_header(&header(&TEMPLATE)).is_empty());
+        // The names are matched loosely, and the optional columns may be there.
+        let mut names = TEMPLATE.to_vec();
+        names[0] = "Id";
+        names[10] = "pre-test script";
+        names.extend(["Expected SHA256", "Notes"]);
+        assert!(validate_header(&header(&names)).is_empty());
+    }
+
+    #[test]
+    fn test_shuffled_columns() {
+        let mut names = TEMPLATE.to_vec();
+        names.swap(2, 3);
+        assert_eq!(
+            validate_header(&header(&names)),
+            vec![
+                "Column 3 (C) is 'When', expected 'Given' ('When' belongs in column 4 (D))",
+                "Column 4 (D) is 'Given', expected 'When' ('Given' belongs in column 3 (C))",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_and_unknown_columns() {
+        let mut names = TEMPLATE.to_vec();
+        names[9] = "status";
+        names.truncate(11);
+        assert_eq!(
+            validate_header(&header(&names)),
+            vec![
+                "Column 10 (J) is 'status', expected 'Config'",
+                "Column 12 (L) is missing, expected 'Post Test Script'",
+            ]
+        );
+        assert_eq!(validate_header(&[]), vec!["No header row"]);
+    }
+}
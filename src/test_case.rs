@@ -1,24 +1,40 @@
 use crate::test_events::{TestCaseBegin, TestCaseEnd, TestEvent};
-use crate::{config::Config, test_context::TestCtx};
+use crate::{
+    body_checksum::BodyChecksum,
+    config::Config,
+    test_context::{truncate_lines, TestCtx, DEFAULT_TOKEN},
+    transport::SendResult,
+};
 //use base64;
 use bharat_cafe as bc;
 use calamine::DataType;
 use colored::Colorize;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use indicatif::ProgressBar;
 use regex::Regex;
 use reqwest::blocking::multipart;
+use reqwest::header::CONTENT_TYPE;
 use reqwest::{Method, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::default;
 use std::env;
 use std::fs::File;
 use std::io::Read;
 use std::io::{self, Write};
 //use std::sync::Arc;
+use std::sync::OnceLock;
 use std::{sync::mpsc::Sender, time::Duration};
 use uuid::Uuid;
 
+// Column naming the case whose cells are inherited by the empty cells of the row.
+const TEMPLATE_COLUMN: usize = 16;
+// First column inherited from a template (the url). The id, name and given / when / then
+// describe the case itself.
+const FIRST_INHERITED_COLUMN: usize = 5;
+
 // Possible test case results.
 #[derive(Debug, Clone, PartialEq)]
 pub enum TestResult {
@@ -35,6 +51,7 @@ enum AuthType {
     None,
     Authorizer,
     Authorized,
+    Ntlm, // Answers the NTLM challenge of the server, with the `ntlm` credentials of the config.
 }
 // Advanced configuration for tweaking the test case behavior
 // for repeated execution, delay between requests, etc.
@@ -49,6 +66,18 @@ struct TestCaseConfig {
     auth_type: AuthType, // Indicates if the test case generates or consumes a JWT
     #[serde(default = "default_delay")]
     delay: u64, // Delay between test case execution (in millis).
+    #[serde(default = "default_max_concurrency")]
+    max_concurrency: usize, // Max. no. of repeated requests in flight at once.
+    #[serde(default)]
+    poll_until_pass: bool, // Re-send the request until the post test script passes.
+    #[serde(default = "default_poll_interval_ms")]
+    poll_interval_ms: u64, // Delay between the polling attempts (in millis).
+    #[serde(default = "default_poll_max_attempts")]
+    poll_max_attempts: u32, // The test case fails if none of these many attempts pass.
+    #[serde(default)]
+    token_name: Option<String>, // Token captured (by an authorizer) or sent, the default one if none.
+    #[serde(default)]
+    body_checksum: Option<BodyChecksum>, // Overrides the `body_checksum` of the config.
 }
 
 impl Default for TestCaseConfig {
@@ -58,6 +87,12 @@ impl Default for TestCaseConfig {
             //data_source: default_data_source(),
             auth_type: default_auth_type(),
             delay: default_delay(),
+            max_concurrency: default_max_concurrency(),
+            poll_until_pass: false,
+            poll_interval_ms: default_poll_interval_ms(),
+            poll_max_attempts: default_poll_max_attempts(),
+            token_name: None,
+            body_checksum: None,
         }
     }
 }
@@ -66,6 +101,18 @@ fn default_repeat_count() -> u32 {
     1
 }
 
+fn default_max_concurrency() -> usize {
+    1
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_poll_max_attempts() -> u32 {
+    10
+}
+
 fn default_data_source() -> String {
     "".to_string()
 }
@@ -78,20 +125,36 @@ fn default_delay() -> u64 {
     0
 }
 
+// Error message a negative test case expects: a text in the body, or the value at a JSONPath
+// of the response json, ex: `{"path": "$.error.message", "contains": "not found"}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedError {
+    pub path: Option<String>, // JSONPath of the message, the whole body if none.
+    pub contains: Option<String>,
+    pub equals: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct TestCase {
-    pub id: u32,                          // test case identifier (typically a number)
-    pub name: String,                     // human readable name for the test case.
-    pub given: String,                    // test case description for the given condition (Given)
-    pub when: String,                     // test case description for the then condition  (When)
-    pub then: String,                     // test case description. for resulting condition. (Then)
-    pub url: String,                      // URL of the request
-    pub method: Method,                   // http method for the request.
-    pub headers: Vec<(String, String)>,   // http headers for the request, if any.
-    pub payload: String,                  // payload to be sent with the request.
-    config: TestCaseConfig,               // advanced configuration for the test case.
-    pub pre_test_script: Option<String>,  // script to be executed before the test case.
+    pub id: u32,                               // test case identifier (typically a number)
+    pub name: String,                          // human readable name for the test case.
+    pub given: String,  // test case description for the given condition (Given)
+    pub when: String,   // test case description for the then condition  (When)
+    pub then: String,   // test case description. for resulting condition. (Then)
+    pub url: String,    // URL of the request
+    pub method: Method, // http method for the request.
+    pub headers: Vec<(String, String)>, // http headers for the request, if any.
+    pub payload: String, // payload to be sent with the request.
+    config: TestCaseConfig, // advanced configuration for the test case.
+    pub pre_test_script: Option<String>, // script to be executed before the test case.
     pub post_test_script: Option<String>, // script to be executed after the test case.
+    pub expected_sha256: Option<String>, // hex sha256 the response body must match, if any.
+    pub notes: Option<String>, // free form notes for the reports, never executed.
+    pub final_script: Option<String>, // script to be executed once, after all the repeats.
+    pub expected_json: Option<Value>, // fragment the response json must contain, if any.
+    pub expected_error: Option<ExpectedError>, // error message the response must carry, if any.
+    pub accept_variants: Vec<String>, // media types the case is run with, one run per type.
 
     pub errors: Vec<(String, String)>, // List of errors found while reading excel data.
 
@@ -99,11 +162,14 @@ pub struct TestCase {
     effective_name: String,
     effective_url: String,
     effective_payload: String,
-    content_type: String, // will be filled by `prepare_payload` method.
+    effective_headers: Vec<(String, String)>,
+    content_type: String,   // will be filled by `prepare_payload` method.
+    accept: Option<String>, // the accept variant being run, if any.
 
     // fields that will be filled after test case is executed..
     //exec_duration: std::time::Duration,
     result: TestResult,
+    pub warnings: usize, // No. of failed warn-only assertions, across the repeats.
 }
 
 impl TestCase {
@@ -121,14 +187,46 @@ impl TestCase {
             config: TestCaseConfig::default(),
             pre_test_script: None,
             post_test_script: None,
+            expected_sha256: None,
+            notes: None,
+            final_script: None,
+            expected_json: None,
+            expected_error: None,
+            accept_variants: Vec::new(),
             errors: Vec::new(),
             effective_name: "".to_string(),
             effective_url: "".to_string(),
             effective_payload: "".to_string(),
+            effective_headers: Vec::new(),
             content_type: "".to_string(),
+            accept: None,
             result: TestResult::NotYetTested,
+            warnings: 0,
+        }
+    }
+    // Returns the id of the test case row, without constructing the test case.
+    pub fn row_id(row: &[calamine::Data]) -> Option<u32> {
+        row.first()?.get_float().map(|f| f as u32)
+    }
+
+    // Id of the template case named in the row, if any.
+    pub fn template_id(row: &[calamine::Data]) -> Option<u32> {
+        match row.get(TEMPLATE_COLUMN)? {
+            calamine::Data::Float(id) => Some(*id as u32),
+            calamine::Data::Int(id) => Some(*id as u32),
+            calamine::Data::String(id) => id.trim().parse().ok(),
+            _ => None,
         }
     }
+
+    // True if the test case row is an authorizer, as per its config column.
+    pub fn is_authorizer_row(row: &[calamine::Data]) -> bool {
+        row.get(9)
+            .and_then(|c| c.get_string())
+            .and_then(|s| serde_json::from_str::<TestCaseConfig>(s).ok())
+            .is_some_and(|c| matches!(c.auth_type, AuthType::Authorizer))
+    }
+
     // Initializes a test case object with a row of data from excel sheet.
     pub fn new(row: &[calamine::Data], config: &Config) -> Self {
         let mut errors = Vec::new();
@@ -283,6 +381,13 @@ impl TestCase {
             },
             None => TestCaseConfig::default(),
         };
+        #[cfg(not(feature = "ntlm"))]
+        if config.auth_type == AuthType::Ntlm {
+            errors.push((
+                "config".to_string(),
+                "authType ntlm needs a build with the `ntlm` feature.".to_string(),
+            ));
+        }
 
         /*
         // This column is read in the beginning. So no need here.
@@ -299,6 +404,76 @@ impl TestCase {
             None => None,
         };
 
+        // Optional columns, which older sheets may not have.
+        let expected_sha256 = row
+            .get(12)
+            .and_then(|c| c.get_string())
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty());
+        let notes = row
+            .get(13)
+            .and_then(|c| c.get_string())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+        let final_script = row
+            .get(14)
+            .and_then(|c| c.get_string())
+            .filter(|s| !s.trim().is_empty())
+            .map(substitute_keywords);
+        let expected_json = match row.get(15).and_then(|c| c.get_string()) {
+            Some(s) if !s.trim().is_empty() => {
+                match serde_json::from_str(&substitute_keywords(s)) {
+                    Ok(json) => Some(json),
+                    Err(_) => {
+                        errors.push(("expected_json".to_string(), "Invalid JSON.".to_string()));
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let expected_error = match row.get(17).and_then(|c| c.get_string()).map(str::trim) {
+            Some(s) if s.starts_with('{') => {
+                match serde_json::from_str::<ExpectedError>(&substitute_keywords(s)) {
+                    Ok(expected) if expected.contains.is_some() || expected.equals.is_some() => {
+                        Some(expected)
+                    }
+                    _ => {
+                        errors.push((
+                            "expected_error".to_string(),
+                            "Invalid JSON, expected a path with contains or equals.".to_string(),
+                        ));
+                        None
+                    }
+                }
+            }
+            Some(s) if !s.is_empty() => Some(ExpectedError {
+                path: None,
+                contains: Some(substitute_keywords(s)),
+                equals: None,
+            }),
+            _ => None,
+        };
+        let accept_variants = row
+            .get(18)
+            .and_then(|c| c.get_string())
+            .map(|s| {
+                s.split([',', '\n'])
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        // The template of a row is resolved (and cleared) along with the sheet, see
+        // `resolve_templates`. A template left here could not be resolved.
+        if row.get(TEMPLATE_COLUMN).is_some_and(|c| !is_empty_cell(c)) {
+            errors.push((
+                "template".to_string(),
+                "No test case with this id, or a cyclic template.".to_string(),
+            ));
+        }
+
         let tc = TestCase {
             id,
             name,
@@ -312,12 +487,21 @@ impl TestCase {
             errors,
             pre_test_script,
             post_test_script,
+            expected_sha256,
+            notes,
+            final_script,
+            expected_json,
+            expected_error,
+            accept_variants,
             result: TestResult::NotYetTested,
             config,
             effective_name: "".to_string(),
             effective_url: "".to_string(),
             effective_payload: "".to_string(),
+            effective_headers: Vec::new(),
             content_type: "".to_string(),
+            accept: None,
+            warnings: 0,
         };
         tc
     }
@@ -333,22 +517,168 @@ impl TestCase {
         // Fire an event indicating that the test case execution has started.
         self.fire_start_evt(tx);
 
-        println!("Running the test case: {}", self.name);
+        if !sys_config.quiet {
+            println!("Running the test case: {}", self.name);
+        }
 
         // Verify if the test case has errors, if so return without executing.
         if self.errors.len() > 0 {
-            println!(
-                "Skipping test case: {} due to errors: {:?}",
-                self.name, self.errors
-            );
+            self.print_skipped(&format!("due to errors: {:?}", self.errors), sys_config);
+            self.result = TestResult::Skipped;
+            self.fire_end_evt(tx, ts_ctx);
+            self.fire_result_evt(tx, ts_ctx, &TestResult::Skipped);
             return TestResult::Skipped;
         }
 
-        let mut overall_result = TestResult::Passed;
-
         // Execute the test case as per the configuration found in the test case.
-        println!("Test case configurations {:?}", self.config);
-        for _ in 0..self.config.repeat_count {
+        if sys_config.print_debug() {
+            println!("Test case configurations {:?}", self.config);
+        }
+        ts_ctx.clear_iteration_times();
+        ts_ctx.clear_request_count();
+        let overall_result = if self.config.poll_until_pass {
+            self.run_polling(ts_ctx, sys_config, tx)
+        } else if !self.accept_variants.is_empty() {
+            self.run_accept_variants(ts_ctx, sys_config, tx)
+        } else if self.config.max_concurrency > 1 && self.config.repeat_count > 1 && !self.is_ntlm()
+        {
+            self.run_concurrently(ts_ctx, sys_config, tx)
+        } else {
+            self.run_sequentially(ts_ctx, sys_config, tx)
+        };
+
+        let result = self.apply_final_script(overall_result, ts_ctx, sys_config);
+        self.fire_result_evt(tx, ts_ctx, &result);
+        result
+    }
+
+    // True if the case can be sent along with the other cases of a `parallel_cases` group:
+    // it sends a single request, and no later case waits for a token captured by it.
+    pub fn is_parallelizable(&self) -> bool {
+        !self.is_authorizer()
+            && !self.config.poll_until_pass
+            && self.config.repeat_count <= 1
+            && self.accept_variants.is_empty()
+            && !self.is_ntlm()
+    }
+
+    // Starts the test case of a `parallel_cases` group, and returns its request to be sent
+    // along with the others, or None if the case is invalid (it is reported as skipped).
+    pub fn start_parallel(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        sys_config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> Option<reqwest::blocking::RequestBuilder> {
+        self.fire_start_evt(tx);
+        if !sys_config.quiet {
+            println!("Running the test case: {}", self.name);
+        }
+        if !self.errors.is_empty() {
+            self.print_skipped(&format!("due to errors: {:?}", self.errors), sys_config);
+            self.result = TestResult::Skipped;
+            self.fire_end_evt(tx, ts_ctx);
+            self.fire_result_evt(tx, ts_ctx, &TestResult::Skipped);
+            return None;
+        }
+        ts_ctx.set_iteration(0);
+        Some(self.pre_run_ops(ts_ctx, sys_config))
+    }
+
+    // Verifies the response of a test case started with `start_parallel`.
+    pub fn finish_parallel(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        response: SendResult,
+        sys_config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> TestResult {
+        // The other cases were prepared since, so restore the request of this one.
+        ts_ctx.set_request(&self.effective_request());
+        ts_ctx.apply_response(response, None, sys_config);
+        self.verify(ts_ctx);
+        if self.result == TestResult::Failed {
+            self.dump_failure(ts_ctx, sys_config);
+        }
+        self.fire_end_evt(tx, ts_ctx);
+        self.print_result(ts_ctx, sys_config);
+        let result = self.result.clone();
+        let result = self.apply_final_script(result, ts_ctx, sys_config);
+        self.fire_result_evt(tx, ts_ctx, &result);
+        result
+    }
+
+    // Prepares the request of a benchmark iteration, without reporting the test case.
+    pub fn prepare_benchmark(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        sys_config: &Config,
+        iteration: u32,
+    ) -> reqwest::blocking::RequestBuilder {
+        ts_ctx.set_iteration(iteration);
+        self.pre_run_ops(ts_ctx, sys_config)
+    }
+
+    // True if the response of a benchmark iteration is an error: the request failed, or its
+    // post test script (or sha256, or expected json) did not pass. Without any, a non 2xx/3xx
+    // status is.
+    pub fn is_benchmark_error(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        response: SendResult,
+        sys_config: &Config,
+    ) -> bool {
+        ts_ctx.apply_response(response, self.authorizer_token(), sys_config);
+        if self.post_test_script.is_some()
+            || self.expected_sha256.is_some()
+            || self.expected_json.is_some()
+            || self.expected_error.is_some()
+        {
+            self.verify(ts_ctx);
+            self.result == TestResult::Failed
+        } else {
+            !(200..400).contains(&self.get_exec_status(ts_ctx))
+        }
+    }
+
+    // The final script checks the state accumulated across the repeats (ex: in SAT.globals).
+    fn apply_final_script(
+        &mut self,
+        overall_result: TestResult,
+        ts_ctx: &mut TestCtx,
+        sys_config: &Config,
+    ) -> TestResult {
+        match &self.final_script {
+            Some(final_script) => {
+                let passed = ts_ctx.verify_result(Some(final_script));
+                if !sys_config.quiet {
+                    println!(
+                        "{:<15}: {}",
+                        "Final script",
+                        if passed { "passed" } else { "failed" }
+                    );
+                }
+                if passed {
+                    overall_result
+                } else {
+                    self.dump_failure(ts_ctx, sys_config);
+                    TestResult::Failed
+                }
+            }
+            None => overall_result,
+        }
+    }
+
+    // Runs the iterations one after the other, until one of them fails.
+    fn run_sequentially(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        sys_config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> TestResult {
+        let mut overall_result = TestResult::Passed;
+        for iteration in 0..self.config.repeat_count {
+            ts_ctx.set_iteration(iteration);
             let req = self.pre_run_ops(ts_ctx, sys_config);
             let spinner = ProgressBar::new_spinner();
             show_progress(&mut self.effective_url, &spinner);
@@ -367,10 +697,135 @@ impl TestCase {
         overall_result
     }
 
+    // Runs the case once per accept variant, with that `Accept` header, and checks that the
+    // response is of that type. All the variants run, each is reported on its own.
+    fn run_accept_variants(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        sys_config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> TestResult {
+        let mut overall_result = TestResult::Passed;
+        let headers = self.headers.clone();
+        for (iteration, variant) in self.accept_variants.clone().into_iter().enumerate() {
+            ts_ctx.set_iteration(iteration as u32);
+            self.headers = headers
+                .iter()
+                .filter(|(key, _)| !key.eq_ignore_ascii_case("accept"))
+                .cloned()
+                .chain([("Accept".to_string(), variant.clone())])
+                .collect();
+            self.accept = Some(variant);
+            let req = self.pre_run_ops(ts_ctx, sys_config);
+            self.execute_request(ts_ctx, req, sys_config, tx);
+            if self.result == TestResult::Failed {
+                overall_result = TestResult::Failed;
+            }
+            self.post_run_ops(ts_ctx, sys_config);
+        }
+        self.headers = headers;
+        self.accept = None;
+        overall_result
+    }
+
+    // Re-sends the request until the post test script passes (ex: for eventually consistent
+    // resources), or the attempts are exhausted. Only the last attempt is reported.
+    fn run_polling(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        sys_config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> TestResult {
+        let max_attempts = self.config.poll_max_attempts.max(1);
+        for attempt in 1..=max_attempts {
+            let req = self.pre_run_ops(ts_ctx, sys_config);
+            ts_ctx.exec(req, self.authorizer_token(), sys_config);
+            self.verify(ts_ctx);
+            if self.result == TestResult::Passed || attempt == max_attempts {
+                break;
+            }
+            // The wait grows with the attempts, as per the `backoff` of the config.
+            let wait = sys_config
+                .backoff
+                .delay_for(attempt, Duration::from_millis(self.config.poll_interval_ms));
+            if !sys_config.quiet {
+                println!(
+                    "Attempt {} of {} did not pass, polling again in {} ms",
+                    attempt,
+                    max_attempts,
+                    wait.as_millis()
+                );
+            }
+            std::thread::sleep(wait);
+        }
+        if self.result == TestResult::Failed {
+            self.dump_failure(ts_ctx, sys_config);
+        }
+        self.fire_end_evt(tx, ts_ctx);
+        self.post_run_ops(ts_ctx, sys_config);
+        self.result.clone()
+    }
+
+    // Runs the iterations with up to `maxConcurrency` requests in flight.  The scripts are not
+    // thread safe, so the requests are prepared and verified here, and only sent in parallel.
+    fn run_concurrently(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        sys_config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> TestResult {
+        let requests: Vec<_> = (0..self.config.repeat_count)
+            .map(|iteration| {
+                ts_ctx.set_iteration(iteration);
+                self.pre_run_ops(ts_ctx, sys_config)
+            })
+            .collect();
+        let responses = ts_ctx.send_all(requests, self.config.max_concurrency);
+
+        let mut overall_result = TestResult::Passed;
+        for (iteration, (response, latency)) in (0..).zip(responses) {
+            ts_ctx.set_iteration(iteration);
+            ts_ctx.record_iteration_time(latency);
+            ts_ctx.record_case_time(self.id, latency);
+            ts_ctx.apply_response(response, self.authorizer_token(), sys_config);
+            self.verify(ts_ctx);
+            // The elapsed time of the context is the one of all the iterations.
+            let end = TestCaseEnd {
+                exec_duration: latency,
+                ..self.get_end_evt_data(ts_ctx)
+            };
+            tx.send(TestEvent::EvtTestCaseEnd(end)).unwrap();
+            self.print_result(ts_ctx, sys_config);
+            if self.result == TestResult::Failed {
+                // Only the first failed iteration is dumped.
+                if overall_result == TestResult::Passed {
+                    self.dump_failure(ts_ctx, sys_config);
+                }
+                overall_result = TestResult::Failed;
+            }
+        }
+        overall_result
+    }
+
+    // Reports the test case as skipped without executing it (ex: an earlier case of the group failed).
+    pub fn skip(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> TestResult {
+        self.fire_start_evt(tx);
+        self.print_skipped("", config);
+        self.result = TestResult::Skipped;
+        self.fire_end_evt(tx, ts_ctx);
+        self.fire_result_evt(tx, ts_ctx, &TestResult::Skipped);
+        TestResult::Skipped
+    }
+
     fn prepare_request(
         &mut self,
         ts_ctx: &mut TestCtx,
-        _config: &Config,
+        config: &Config,
     ) -> reqwest::blocking::RequestBuilder {
         // 1. Retrieve global variables and substitute placeholders in test case parameters
         //    Retrieve global variables and substitute placeholders in test case parameters
@@ -382,20 +837,38 @@ impl TestCase {
             self.substitute_placeholders(&substitute_keywords(&self.payload), ts_ctx);
 
         // 2. if the test case is authorized, then add the jwt token to the headers.
-        if self.is_authorized() {
-            if let Some(token) = ts_ctx.jwt_token.as_ref() {
-                self.headers
-                    .push(("Authorization".to_owned(), format!("Bearer {}", token)));
+        //    With `auto_authorize_group`, every test case after the authorizer of the group is.
+        self.effective_headers = self.headers.clone();
+        let group_authorized =
+            config.auto_authorize_group && ts_ctx.group_authorized && !self.is_authorizer();
+        if self.is_authorized() || group_authorized {
+            if let Some(token) = ts_ctx.tokens.get(self.token_name()) {
+                self.effective_headers.push(token_header(token, config));
+            }
+        }
+        // Tag the request with the run id, unless the test case sets the header itself.
+        if let (Some(name), Some(run_id)) = (&config.tag_header, &ts_ctx.run_id) {
+            let name_lowercase = name.to_lowercase();
+            if !self
+                .effective_headers
+                .iter()
+                .any(|(key, _)| key.to_lowercase() == name_lowercase)
+            {
+                self.effective_headers.push((name.clone(), run_id.clone()));
             }
         }
 
+        // Let the `SAT.onBeforeRequest` hook (if any) adjust the url, headers and body.
+        self.apply_before_request_hook(ts_ctx);
+        ts_ctx.set_request(&self.effective_request());
+
         // 3. Frame the request based on Method type, add headers.
         let mut request = ts_ctx
             .client
             .request(self.method.clone(), &self.effective_url);
 
         // Finally, add the headers to the request.
-        for (key, value) in &self.headers {
+        for (key, value) in &self.effective_headers {
             // Ignore the content-type header, as it will be handled separately.
             if key.to_lowercase() == "content-type" {
                 continue;
@@ -403,8 +876,122 @@ impl TestCase {
             request = request.header(key, value);
         }
 
+        // 4. Tag the request with a fresh trace context, if requested.
+        if config.inject_trace_id {
+            let (trace_id, traceparent) = new_traceparent();
+            ts_ctx.set_last_trace_id(&trace_id);
+            request = request.header("traceparent", traceparent);
+        }
+
         // Prepare payload and return.
-        self.prepare_payload(request)
+        let request = self.prepare_payload(request, ts_ctx, config);
+        let request = self.add_body_checksum(request, config);
+        #[cfg(feature = "ntlm")]
+        let request = self.authenticate_ntlm(request, ts_ctx, config);
+        request
+    }
+
+    // Adds the checksum header of the body (as serialized, ex: after the gzip compression), if
+    // the test case or the config asks for one.
+    fn add_body_checksum(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+        config: &Config,
+    ) -> reqwest::blocking::RequestBuilder {
+        let Some(checksum) = self
+            .config
+            .body_checksum
+            .as_ref()
+            .or(config.body_checksum.as_ref())
+        else {
+            return request;
+        };
+        // The body is only reachable from a built request, so a copy of it is built.
+        let body = request.try_clone().and_then(|r| r.build().ok()).map(|r| {
+            r.body()
+                .and_then(|body| body.as_bytes())
+                .unwrap_or_default()
+                .to_vec()
+        });
+        match body {
+            Some(body) => request.header(&checksum.header, checksum.value_for(&body)),
+            None => {
+                eprintln!("No {} for a streamed (ex: multipart) body", checksum.header);
+                request
+            }
+        }
+    }
+
+    // Runs the NTLM handshake for the request, if the test case is `ntlm`, and returns it with
+    // the answer to the challenge. On a failed handshake, the request is sent as is (and fails
+    // on its 401).
+    #[cfg(feature = "ntlm")]
+    fn authenticate_ntlm(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+        ts_ctx: &mut TestCtx,
+        config: &Config,
+    ) -> reqwest::blocking::RequestBuilder {
+        if !self.is_ntlm() {
+            return request;
+        }
+        let authorization = match &config.ntlm {
+            Some(creds) => ts_ctx.ntlm_authorization(&request, creds),
+            None => Err("No `ntlm` credentials in the config".into()),
+        };
+        match authorization {
+            Ok(authorization) => request.header(reqwest::header::AUTHORIZATION, authorization),
+            Err(e) => {
+                eprintln!("NTLM handshake failed: {}", e);
+                request
+            }
+        }
+    }
+
+    // The effective request, as made available to the scripts (`SAT.request`).
+    fn effective_request(&self) -> Value {
+        serde_json::json!({
+            "method": self.method.to_string(),
+            "url": self.effective_url,
+            "body": self.effective_payload,
+        })
+    }
+
+    // Passes the effective request through the `SAT.onBeforeRequest` hook, if defined,
+    // and takes the url, headers and body it comes back with.
+    fn apply_before_request_hook(&mut self, ts_ctx: &mut TestCtx) {
+        let headers: serde_json::Map<String, Value> = self
+            .effective_headers
+            .iter()
+            .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+            .collect();
+        let request = serde_json::json!({
+            "method": self.method.to_string(),
+            "url": self.effective_url,
+            "headers": headers,
+            "body": self.effective_payload,
+        });
+        let Some(request) = ts_ctx.before_request_hook(&request) else {
+            return;
+        };
+
+        if let Some(url) = request["url"].as_str() {
+            self.effective_url = url.to_string();
+        }
+        if let Some(headers) = request["headers"].as_object() {
+            self.effective_headers = headers
+                .iter()
+                .map(|(key, value)| match value {
+                    Value::String(s) => (key.clone(), s.clone()),
+                    other => (key.clone(), other.to_string()),
+                })
+                .collect();
+        }
+        match &request["body"] {
+            Value::Null => {}
+            Value::String(body) => self.effective_payload = body.clone(),
+            body => self.effective_payload = body.to_string(),
+        }
     }
 
     fn execute_request(
@@ -415,10 +1002,147 @@ impl TestCase {
         tx: &Sender<TestEvent>,
     ) {
         // Fire the request using blocking call.
-        ts_ctx.exec(req, self.is_authorizer(), &config);
+        if config.auto_reauth && self.is_authorizer() {
+            ts_ctx.set_reauth_request(self.token_name(), &req);
+        }
+        // A copy to resend as is on a 401, but for a fresh token (`auto_reauth`).
+        let retry = (config.auto_reauth && !self.is_authorizer())
+            .then(|| req.try_clone())
+            .flatten();
+        ts_ctx.exec(req, self.authorizer_token(), &config);
+        if let Some(retry) = retry.filter(|_| ts_ctx.get_http_status() == 401) {
+            self.reauth_and_retry(ts_ctx, retry, config);
+        }
+        ts_ctx.record_iteration_time(ts_ctx.exec_duration());
+        ts_ctx.record_case_time(self.id, ts_ctx.exec_duration());
 
         // Execute the post test script and verify the result.
-        let result = ts_ctx.verify_result(self.post_test_script.as_deref());
+        self.verify(ts_ctx);
+        if self.result == TestResult::Failed {
+            self.dump_failure(ts_ctx, config);
+        }
+
+        // Fire test case end evt.
+        self.fire_end_evt(tx, ts_ctx);
+    }
+
+    // On a 401 (ex: the token expired mid-run), re-runs the request of the authorizer of the
+    // group to refresh the token, and resends the request once, with only its token swapped.
+    // The request is not prepared again, so its placeholders and hook are not re-evaluated.
+    fn reauth_and_retry(
+        &mut self,
+        ts_ctx: &mut TestCtx,
+        request: reqwest::blocking::RequestBuilder,
+        config: &Config,
+    ) {
+        // Without the token in the request, a fresh one would not help.
+        let (name, _) = token_header("", config);
+        let Some(index) = self
+            .effective_headers
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(&name))
+        else {
+            return;
+        };
+        let Some(token) = ts_ctx.refresh_token(self.token_name(), config) else {
+            if !config.quiet {
+                println!("\tGot a 401, but the authorizer did not return a fresh token");
+            }
+            return;
+        };
+        if !config.quiet {
+            println!("\tGot a 401, retrying the request with a fresh token");
+        }
+        let (name, value) = token_header(&token, config);
+        let mut headers = reqwest::header::HeaderMap::new();
+        match (
+            reqwest::header::HeaderName::from_bytes(name.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            (Ok(name), Ok(value)) => headers.insert(name, value),
+            _ => return,
+        };
+        self.effective_headers[index] = (name, value);
+        ts_ctx.exec(request.headers(headers), None, config);
+    }
+
+    // Writes the effective request, the response, the assertions and the globals of the failed
+    // test case to `<dump_on_failure_dir>/<id>.json`, for debugging.
+    fn dump_failure(&self, ts_ctx: &mut TestCtx, config: &Config) {
+        let Some(dir) = &config.dump_on_failure_dir else {
+            return;
+        };
+        let headers: serde_json::Map<String, Value> = self
+            .effective_headers
+            .iter()
+            .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+            .collect();
+        let dump = serde_json::json!({
+            "id": self.id,
+            "name": self.effective_name,
+            "request": {
+                "method": self.method.to_string(),
+                "url": self.effective_url,
+                "headers": headers,
+                "payload": self.effective_payload,
+            },
+            "response": ts_ctx.eval_json(
+                "{ status: SAT.response.status, headers: SAT.response.headers, body: SAT.response.body }"
+            ),
+            "assertions": ts_ctx.get_assertions(),
+            "globals": ts_ctx.eval_json("SAT.globals"),
+        });
+
+        let path = std::path::Path::new(dir).join(format!("{}.json", self.id));
+        let written = std::fs::create_dir_all(dir).and_then(|_| {
+            std::fs::write(
+                &path,
+                serde_json::to_string_pretty(&dump).unwrap_or_default(),
+            )
+        });
+        match written {
+            Ok(_) => println!("Failure dump written to: {}", path.display()),
+            Err(e) => eprintln!("Failed to write failure dump '{}': {}", path.display(), e),
+        }
+    }
+
+    // Runs the post test script against the response, and records the result
+    // along with the failed warn-only assertions.
+    fn verify(&mut self, ts_ctx: &mut TestCtx) {
+        let mut result = match (&self.post_test_script, &self.expected_sha256) {
+            // The hash alone decides the result, when there is no script.
+            (None, Some(_)) => self.verify_sha256(ts_ctx),
+            (_, Some(_)) => {
+                ts_ctx.verify_result(self.post_test_script.as_deref()) && self.verify_sha256(ts_ctx)
+            }
+            // As do the expected json, error and content type.
+            (None, None)
+                if self.expected_json.is_some()
+                    || self.expected_error.is_some()
+                    || self.accept.is_some()
+                    || ts_ctx.approvals.applies_to(self.id) =>
+            {
+                ts_ctx.clear_assertions();
+                true
+            }
+            (_, None) => ts_ctx.verify_result(self.post_test_script.as_deref()),
+        };
+        if let Some(expected) = &self.expected_json {
+            result = verify_expected_json(expected, ts_ctx) && result;
+        }
+        if let Some(expected) = &self.expected_error {
+            result = verify_expected_error(expected, ts_ctx) && result;
+        }
+        if let Some(accept) = &self.accept {
+            result = verify_content_type(accept, ts_ctx) && result;
+        }
+        if ts_ctx.approvals.applies_to(self.id) {
+            result = verify_approved(self.id, ts_ctx) && result;
+        }
+        // The invariants every response must hold (`global_post_script`).
+        if let Some(script) = ts_ctx.global_post_script.clone() {
+            result = ts_ctx.verify_more(&script) && result;
+        }
 
         // store the test result as an enum.
         let test_result = match result {
@@ -427,8 +1151,26 @@ impl TestCase {
         };
         self.result = test_result;
 
-        // Fire test case end evt.
-        self.fire_end_evt(tx, ts_ctx);
+        self.warnings += ts_ctx
+            .get_assertions()
+            .iter()
+            .filter(|a| a.warning && !a.passed)
+            .count();
+    }
+
+    // Verifies the response body against the expected sha256 hash.
+    fn verify_sha256(&self, ts_ctx: &mut TestCtx) -> bool {
+        let actual = ts_ctx.get_response_sha256();
+        match &self.expected_sha256 {
+            Some(expected) if *expected != actual => {
+                println!(
+                    "sha256 mismatch, expected: {}, actual: {}",
+                    expected, actual
+                );
+                false
+            }
+            _ => true,
+        }
     }
 
     fn is_authorized(&self) -> bool {
@@ -438,13 +1180,28 @@ impl TestCase {
         }
     }
 
-    fn is_authorizer(&self) -> bool {
+    // NTLM is connection based, so these test cases are sent one at a time.
+    fn is_ntlm(&self) -> bool {
+        self.config.auth_type == AuthType::Ntlm
+    }
+
+    pub fn is_authorizer(&self) -> bool {
         match self.config.auth_type {
             AuthType::Authorizer => true,
             _ => false,
         }
     }
 
+    // Name of the token the test case captures (as an authorizer) or sends.
+    fn token_name(&self) -> &str {
+        self.config.token_name.as_deref().unwrap_or(DEFAULT_TOKEN)
+    }
+
+    // Name the token found in the response is stored under, if the case is an authorizer.
+    fn authorizer_token(&self) -> Option<&str> {
+        self.is_authorizer().then(|| self.token_name())
+    }
+
     fn fire_start_evt(&self, tx: &Sender<TestEvent>) {
         tx.send(TestEvent::EvtTestCaseBegin(self.get_start_evt_data()))
             .unwrap();
@@ -455,6 +1212,16 @@ impl TestCase {
             .unwrap();
     }
 
+    // Unlike the end event of an iteration, carries the result of the test case as a whole:
+    // a repeated case fails if any of its iterations did, or if its final script did.
+    fn fire_result_evt(&self, tx: &Sender<TestEvent>, ts_ctx: &mut TestCtx, result: &TestResult) {
+        let end = TestCaseEnd {
+            result: result.clone(),
+            ..self.get_end_evt_data(ts_ctx)
+        };
+        tx.send(TestEvent::EvtTestCaseResult(end)).unwrap();
+    }
+
     fn get_start_evt_data(&self) -> TestCaseBegin {
         TestCaseBegin {
             timestamp: std::time::Instant::now(),
@@ -470,6 +1237,7 @@ impl TestCase {
             payload: self.payload.clone(),
             pre_test_script: self.pre_test_script.clone(),
             post_test_script: self.post_test_script.clone(),
+            notes: self.notes.clone(),
         }
     }
 
@@ -478,11 +1246,21 @@ impl TestCase {
             timestamp: std::time::Instant::now(),
             iteration_id: "1".to_string(),
             testcase_id: self.id,
-            exec_duration: Duration::from_secs(0),
+            exec_duration: match self.result {
+                TestResult::Skipped => Duration::from_secs(0),
+                _ => ts_ctx.exec_duration(),
+            },
             //TODO: Fix these below fields, to return properly filled values.
-            status: self.get_exec_status(ts_ctx),
+            status: match self.result {
+                TestResult::Skipped => 0,
+                _ => self.get_exec_status(ts_ctx),
+            },
+            result: self.result.clone(),
+            assertions: ts_ctx.get_assertions(),
             response: self.get_exec_response(ts_ctx),
             response_json: self.get_exec_response_json(ts_ctx),
+            errors: self.errors.clone(),
+            effective_payload: self.effective_payload.clone(),
         }
     }
 
@@ -512,7 +1290,11 @@ impl TestCase {
         }
     }
 
-    pub fn print_result(&self, ts_ctx: &mut TestCtx, verbose: bool) {
+    pub fn print_result(&self, ts_ctx: &mut TestCtx, config: &Config) {
+        if config.quiet {
+            println!("{}", self.quiet_line(ts_ctx));
+            return;
+        }
         println!("{:<15}: {}", "Test Case ID", self.id);
         println!("{:<15}: {}", "Test Case", self.name);
         println!("{:<15}: {}", "Given", self.given);
@@ -520,34 +1302,84 @@ impl TestCase {
         println!("{:<15}: {}", "Then", self.then);
         println!("{:<15}: {}", "Expected", ts_ctx.get_test_name());
         println!("{:<15}: {}", "Actual", ts_ctx.get_http_status());
+        if let Some(accept) = &self.accept {
+            println!("{:<15}: {}", "Accept", accept);
+        }
 
         // print the below, if only verbose flag is enabled.
-        if verbose {
-            self.print_request_info();
-            ts_ctx.print_response_info();
+        if config.verbose {
+            self.print_request_info(config);
+            ts_ctx.print_response_info(config);
+        }
+
+        // Report the failed warn-only assertions, they do not affect the result.
+        for assertion in ts_ctx.get_assertions() {
+            if assertion.warning && !assertion.passed {
+                let marker = if config.ascii { "[WARN]" } else { "⚠️" };
+                let warning = format!("{} {}", marker, assertion.name);
+                println!("{:<15}: {}", "Warning", warning.yellow());
+            }
         }
 
         // finally print the pass / fail / skip status with symbols.
+        let marker = result_marker(&self.result, config.ascii);
         match self.result {
-            TestResult::Passed => println!("{:<15}: {}", "Result", "✅ PASSED".green()),
-            TestResult::Failed => println!("{:<15}: {}", "Result", "❌ FAILED".red()),
-            TestResult::Skipped => println!("{:<15}: {}", "Result", "⚠️ SKIPPED".yellow()),
+            TestResult::Passed => println!("{:<15}: {}", "Result", marker.green()),
+            TestResult::Failed => println!("{:<15}: {}", "Result", marker.red()),
+            TestResult::Skipped => println!("{:<15}: {}", "Result", marker.yellow()),
             _ => (),
         }
     }
 
-    pub fn print_request_info(&self) {
+    // The single line printed for the test case in quiet mode,
+    // ex: `[PASS] 12 Create user (201, 84ms)`.
+    fn quiet_line(&self, ts_ctx: &mut TestCtx) -> String {
+        let name = match &self.accept {
+            Some(accept) => format!("{} [{}]", self.name, accept),
+            None => self.name.clone(),
+        };
+        let line = format!(
+            "{} {} {} ({}, {}ms)",
+            result_marker(&self.result, true),
+            self.id,
+            name,
+            ts_ctx.get_http_status(),
+            ts_ctx.exec_duration().as_millis()
+        );
+        match self.result {
+            TestResult::Passed => line.green().to_string(),
+            TestResult::Failed => line.red().to_string(),
+            _ => line,
+        }
+    }
+
+    // Reports a test case skipped without sending its request, with the reason (if any).
+    fn print_skipped(&self, reason: &str, config: &Config) {
+        if config.quiet {
+            let marker = result_marker(&TestResult::Skipped, true);
+            println!(
+                "{}",
+                format!("{} {} {}", marker, self.id, self.name).yellow()
+            );
+        } else if reason.is_empty() {
+            println!("Skipping test case: {}", self.name);
+        } else {
+            println!("Skipping test case: {} {}", self.name, reason);
+        }
+    }
+
+    pub fn print_request_info(&self, config: &Config) {
         println!("Request Info: ");
         println!("\tMethod: {:?}", self.method);
         println!("\tURL: {}", self.effective_url);
-        if !self.headers.is_empty() {
+        if !self.effective_headers.is_empty() {
             println!("\tHeaders: ");
-            for (key, value) in &self.headers {
+            for (key, value) in &self.effective_headers {
                 let value = value.replace("\n", "");
                 println!("\t\t{}: {}", key, value);
             }
         }
-        self.print_payload();
+        self.print_payload(config.response_print_limit);
     }
 
     /*
@@ -572,6 +1404,10 @@ impl TestCase {
     /// Substitutes placeholders in the input string with corresponding values.
     ///
     /// - `{{env:VAR_NAME}}` will be replaced with the value of the environment variable `VAR_NAME`.
+    /// - `{{cmd:command}}` will be replaced with the trimmed stdout of the shell command, only
+    ///   with `--allow-cmd-placeholders` (ex: `{{cmd:vault read -field=token secret/api}}`).
+    /// - `{{table:Sheet.key.field}}` will be replaced with the `field` of the row with the `key`
+    ///   in the data table of the `Sheet` worksheet (ex: `{{table:Users.admin.email}}`).
     /// - `{{var}}` will be replaced with the value of the JS context variable `var`.
     /// - If a substitution is not possible, the placeholder remains unchanged.
     ///
@@ -585,7 +1421,8 @@ impl TestCase {
     /// A new `String` with placeholders substituted where possible.
     fn substitute_placeholders(&self, original: &str, ts_ctx: &mut TestCtx) -> String {
         // Compile the regex once for efficiency
-        let re = Regex::new(r"\{\{(.*?)\}\}").unwrap();
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = RE.get_or_init(|| Regex::new(r"\{\{(.*?)\}\}").unwrap());
 
         // Perform substitution using Regex::replace_all with a closure
         re.replace_all(original, |caps: &regex::Captures| {
@@ -606,7 +1443,32 @@ impl TestCase {
                         caps[0].to_string() // Return the original placeholder
                     }
                 }
-             } else if var_expression.starts_with("input:") {
+             } else if let Some(command) = var_expression.strip_prefix("cmd:") {
+                if ts_ctx.allow_cmd_placeholders {
+                    run_cmd_placeholder(command.trim()).unwrap_or_else(|e| {
+                        eprintln!(
+                            "Warning: Command placeholder '{}' failed: {}. Leaving placeholder unchanged.",
+                            command.trim(),
+                            e
+                        );
+                        caps[0].to_string()
+                    })
+                } else {
+                    eprintln!(
+                        "Warning: Command placeholders are disabled (see --allow-cmd-placeholders). Leaving placeholder unchanged."
+                    );
+                    caps[0].to_string()
+                }
+             } else if let Some(reference) = var_expression.strip_prefix("table:") {
+                ts_ctx.data_tables.lookup(reference.trim()).unwrap_or_else(|e| {
+                    eprintln!(
+                        "Warning: Table placeholder '{}' failed: {}. Leaving placeholder unchanged.",
+                        reference.trim(),
+                        e
+                    );
+                    caps[0].to_string()
+                })
+             } else if var_expression.starts_with("input:") {
             // Handle user input for variables
             let input_var_name = var_expression.trim_start_matches("input:").trim();
             let mut user_input = String::new();
@@ -687,7 +1549,7 @@ impl TestCase {
 
     fn post_run_ops(&self, ts_ctx: &mut TestCtx, sys_config: &Config) {
         // Print test results.
-        self.print_result(ts_ctx, sys_config.verbose);
+        self.print_result(ts_ctx, sys_config);
 
         // Setup delay between test cases.
         if self.config.delay > 0 {
@@ -699,52 +1561,85 @@ impl TestCase {
     fn prepare_payload(
         &mut self,
         request: reqwest::blocking::RequestBuilder,
+        ts_ctx: &TestCtx,
+        config: &Config,
     ) -> reqwest::blocking::RequestBuilder {
-        let mut content_type_found = false;
-        for (key, value) in self.headers.iter() {
-            if key.to_lowercase() == "content-type" {
-                content_type_found = true;
-                match value.as_str() {
-                    "application/json" => {
-                        self.content_type = value.clone();
-                        let payload_json: Value = serde_json::from_str(&self.effective_payload)
-                            .unwrap_or(serde_json::json!({}));
-                        return request.json(&payload_json);
-                    }
-                    "application/x-www-form-urlencoded" => {
-                        self.content_type = value.clone();
-                        let url_encoded_data =
-                            serde_json::from_str(self.effective_payload.as_str())
-                                .unwrap_or(serde_json::json!({}));
-                        return request.form(&url_encoded_data);
-                    }
-                    "multipart/form-data" => {
-                        self.content_type = value.clone();
-                        let form_data = serde_json::from_str(self.effective_payload.as_str())
-                            .unwrap_or(serde_json::json!({}));
-                        return self.prepare_multipart_data(request, &form_data);
-                    }
-                    _ => {
-                        eprintln!("Unsupported content type: {}", value);
-                    }
-                }
-                break;
+        // HEAD and OPTIONS requests never carry a body.
+        if self.method == Method::HEAD || self.method == Method::OPTIONS {
+            return request;
+        }
+
+        // Without a content-type header, fall back to the configured default (or JSON).
+        let content_type = self
+            .effective_headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.clone())
+            .or_else(|| config.default_content_type.clone())
+            .unwrap_or_else(|| "application/json".to_string());
+
+        match content_type.as_str() {
+            "application/json" => {
+                self.content_type = content_type;
+                let payload_json: Value =
+                    serde_json::from_str(&self.effective_payload).unwrap_or(serde_json::json!({}));
+                self.json_body(request, &payload_json)
+            }
+            "application/x-www-form-urlencoded" => {
+                self.content_type = content_type;
+                let url_encoded_data = serde_json::from_str(self.effective_payload.as_str())
+                    .unwrap_or(serde_json::json!({}));
+                request.form(&url_encoded_data)
+            }
+            "multipart/form-data" => {
+                self.content_type = content_type;
+                let form_data = serde_json::from_str(self.effective_payload.as_str())
+                    .unwrap_or(serde_json::json!({}));
+                self.prepare_multipart_data(request, &form_data, ts_ctx)
+            }
+            _ => {
+                eprintln!("Unsupported content type: {}", content_type);
+                request
             }
         }
-        // Default to JSON if no matching content type is found
-        if !content_type_found {
-            self.content_type = "application/json".to_string();
-            let payload_json: Value =
-                serde_json::from_str(&self.effective_payload).unwrap_or(serde_json::json!({}));
-            return request.json(&payload_json);
+    }
+
+    // Sets the json payload as the request body, gzip compressed if the
+    // test case has a `Content-Encoding: gzip` header.
+    fn json_body(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+        payload: &Value,
+    ) -> reqwest::blocking::RequestBuilder {
+        if !self.is_gzip_encoded() {
+            return request.json(payload);
+        }
+
+        // The Content-Encoding header itself is already added along with the other headers.
+        let bytes = serde_json::to_vec(payload).unwrap_or_default();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        match encoder.write_all(&bytes).and_then(|_| encoder.finish()) {
+            Ok(compressed) => request
+                .header(CONTENT_TYPE, "application/json")
+                .body(compressed),
+            Err(e) => {
+                eprintln!("Error compressing the payload: {}", e);
+                request.json(payload)
+            }
         }
-        request
+    }
+
+    fn is_gzip_encoded(&self) -> bool {
+        self.effective_headers.iter().any(|(key, value)| {
+            key.eq_ignore_ascii_case("content-encoding") && value.eq_ignore_ascii_case("gzip")
+        })
     }
 
     fn prepare_multipart_data(
         &mut self,
         req: reqwest::blocking::RequestBuilder,
         data: &Value,
+        ts_ctx: &TestCtx,
     ) -> reqwest::blocking::RequestBuilder {
         let mut form = reqwest::blocking::multipart::Form::new();
         let mut effective_payload_parts = Vec::new();
@@ -752,8 +1647,13 @@ impl TestCase {
         // Define the boundary marker (you could use a unique value here)
         let boundary = "--boundary-placeholder";
 
-        // Add fields
-        if let Some(fields) = data["form-data"]["fields"].as_object() {
+        // Add fields, from the `form-data` wrapper, or else the top level object itself
+        // (ex: `{"name": "x", "age": "3"}` for a form of text fields only).
+        let fields = match data.get("form-data") {
+            Some(form_data) => form_data["fields"].as_object(),
+            None => data.as_object(),
+        };
+        if let Some(fields) = fields {
             for (key, value) in fields.clone() {
                 if let Some(string_value) = value.as_str() {
                     // Add to form
@@ -785,7 +1685,7 @@ impl TestCase {
                 let file_path = file_info["filepath"].as_str().unwrap();
 
                 println!("Adding file: {} as {}", file_path, field_name);
-                let mut file = File::open(file_path).expect("file not found");
+                let mut file = File::open(ts_ctx.resolve_path(file_path)).expect("file not found");
                 let mut buffer = Vec::new();
                 file.read_to_end(&mut buffer).expect("Error reading file");
 
@@ -814,13 +1714,14 @@ impl TestCase {
         return req.multipart(form);
     }
 
-    fn print_payload(&self) {
+    fn print_payload(&self, limit: Option<usize>) {
         match self.content_type.as_str() {
             "application/json" => {
                 match serde_json::from_str::<serde_json::Value>(&self.effective_payload) {
                     Ok(json) => {
                         let pretty_json = serde_json::to_string_pretty(&json).unwrap();
-                        let indented_json = pretty_json.replace("\n", "\n\t\t");
+                        let indented_json =
+                            truncate_lines(&pretty_json, limit).replace("\n", "\n\t\t");
                         println!("\tPayload: {}", indented_json);
                     }
                     Err(e) => eprintln!("Error parsing JSON: {}", e),
@@ -859,39 +1760,253 @@ impl TestCase {
     }
 }
 
+// Returns the marker printed for the test result. Plain text markers are used in
+// ascii mode, for terminals which can't render the emoji.
+fn result_marker(result: &TestResult, ascii: bool) -> &'static str {
+    match (result, ascii) {
+        (TestResult::Passed, false) => "✅ PASSED",
+        (TestResult::Failed, false) => "❌ FAILED",
+        (TestResult::Skipped, false) => "⚠️ SKIPPED",
+        (TestResult::Passed, true) => "[PASS]",
+        (TestResult::Failed, true) => "[FAIL]",
+        (TestResult::Skipped, true) => "[SKIP]",
+        (TestResult::NotYetTested, _) => "",
+    }
+}
+
+// Returns the header carrying the token. The standard Authorization header
+// gets a bearer token, while a custom header (ex: X-Auth-Token) gets the raw token.
+fn token_header(token: &str, config: &Config) -> (String, String) {
+    match config.token_header.as_deref() {
+        Some(header) if !header.eq_ignore_ascii_case("authorization") => {
+            (header.to_owned(), token.to_owned())
+        }
+        _ => ("Authorization".to_owned(), format!("Bearer {}", token)),
+    }
+}
+
+// Returns a new W3C trace id, and the `traceparent` header value carrying it.
+fn new_traceparent() -> (String, String) {
+    let trace_id = Uuid::new_v4().simple().to_string();
+    let span_id = format!("{:016x}", rand::random::<u64>());
+    let traceparent = format!("00-{}-{}-01", trace_id, span_id);
+    (trace_id, traceparent)
+}
+
+// Fills the empty cells of the rows naming a template (in the `template` column) from the row
+// of that case, anywhere in the sheet, so a template may come after its users. A template
+// may have its own template. The template cell of a resolved row is cleared.
+pub fn resolve_templates(rows: &[&[calamine::Data]]) -> Vec<Vec<calamine::Data>> {
+    let by_id: HashMap<u32, &[calamine::Data]> = rows
+        .iter()
+        .filter_map(|row| Some((TestCase::row_id(row)?, *row)))
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            let mut resolved = row.to_vec();
+            let mut seen: HashSet<u32> = TestCase::row_id(row).into_iter().collect();
+            while let Some(template_id) = TestCase::template_id(&resolved) {
+                let template = match by_id.get(&template_id) {
+                    Some(template) if seen.insert(template_id) => template,
+                    _ => break, // Reported by `TestCase::new`.
+                };
+                resolved[TEMPLATE_COLUMN] = calamine::Data::Empty;
+                if resolved.len() < template.len() {
+                    resolved.resize(template.len(), calamine::Data::Empty);
+                }
+                for (i, cell) in template.iter().enumerate().skip(FIRST_INHERITED_COLUMN) {
+                    if is_empty_cell(&resolved[i]) {
+                        resolved[i] = cell.clone();
+                    }
+                }
+            }
+            resolved
+        })
+        .collect()
+}
+
+fn is_empty_cell(cell: &calamine::Data) -> bool {
+    cell.get_string()
+        .map_or(cell.is_empty(), |s| s.trim().is_empty())
+}
+
+// Checks that the response json contains the expected fragment, and records it as an assertion.
+fn verify_expected_json(expected: &Value, ts_ctx: &mut TestCtx) -> bool {
+    let actual = ts_ctx.eval_json("SAT.response.json");
+    let mismatch = json_subset_mismatch(expected, &actual, "$");
+    if let Some(mismatch) = &mismatch {
+        println!("expected_json mismatch, {}", mismatch);
+    }
+    ts_ctx.record_assertion("expected_json", mismatch.is_none(), mismatch.as_deref());
+    mismatch.is_none()
+}
+
+// Checks that the response is of the requested media type (the parameters, ex: the charset,
+// are ignored), and records it as an assertion.
+fn verify_content_type(accept: &str, ts_ctx: &mut TestCtx) -> bool {
+    let media_type = |content_type: &str| {
+        let media_type = content_type.split(';').next().unwrap_or_default();
+        media_type.trim().to_lowercase()
+    };
+    let content_type = ts_ctx.eval_json("SAT.response.headers['content-type']");
+    let actual = media_type(content_type.as_str().unwrap_or_default());
+    let passed = actual == media_type(accept);
+    let message = (!passed).then(|| format!("expected {}, but got {:?}", accept, actual));
+    let name = format!("Content-Type: {}", accept);
+    ts_ctx.record_assertion(&name, passed, message.as_deref());
+    passed
+}
+
+// Approves the response (status and body), or compares it with the approved one.
+fn verify_approved(id: u32, ts_ctx: &mut TestCtx) -> bool {
+    let body = ts_ctx.get_response_body();
+    let response = serde_json::json!({
+        "status": ts_ctx.eval_json("SAT.response.status"),
+        "headers": ts_ctx.eval_json("SAT.response.headers"),
+        "body": serde_json::from_str(&body).unwrap_or(Value::String(body)),
+    });
+    let mismatch = ts_ctx.approvals.check(id, &response).err();
+    if let Some(mismatch) = &mismatch {
+        println!("Response differs from the approved one:\n{}", mismatch);
+    }
+    ts_ctx.record_assertion("approved", mismatch.is_none(), mismatch.as_deref());
+    mismatch.is_none()
+}
+
+// Checks the error message of the response, and records it as an assertion.
+fn verify_expected_error(expected: &ExpectedError, ts_ctx: &mut TestCtx) -> bool {
+    let mismatch = expected_error_mismatch(expected, ts_ctx);
+    if let Some(mismatch) = &mismatch {
+        println!("expected_error mismatch, {}", mismatch);
+    }
+    ts_ctx.record_assertion("expected_error", mismatch.is_none(), mismatch.as_deref());
+    mismatch.is_none()
+}
+
+fn expected_error_mismatch(expected: &ExpectedError, ts_ctx: &mut TestCtx) -> Option<String> {
+    let (actual, location) = match &expected.path {
+        Some(path) => {
+            match ts_ctx.eval_json(&format!("SAT.jsonPath({})", Value::from(path.as_str()))) {
+                Value::Null => return Some(format!("no value at {}", path)),
+                Value::String(message) => (message, path.as_str()),
+                value => (value.to_string(), path.as_str()),
+            }
+        }
+        None => (ts_ctx.get_response_body(), "the body"),
+    };
+    if let Some(text) = &expected.contains {
+        if !actual.contains(text.as_str()) {
+            return Some(format!(
+                "expected {:?} in {}, but got {:?}",
+                text, location, actual
+            ));
+        }
+    }
+    if let Some(text) = &expected.equals {
+        if actual != *text {
+            return Some(format!(
+                "expected {:?} at {}, but got {:?}",
+                text, location, actual
+            ));
+        }
+    }
+    None
+}
+
+// Returns the first difference found, if `actual` does not contain `expected`: every key of an
+// expected object must be in the actual one with a matching value, and the items of an
+// expected array must match the actual items at the same positions (extra items are fine).
+fn json_subset_mismatch(expected: &Value, actual: &Value, path: &str) -> Option<String> {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            expected.iter().find_map(|(key, value)| {
+                let path = format!("{}.{}", path, key);
+                match actual.get(key) {
+                    Some(actual) => json_subset_mismatch(value, actual, &path),
+                    None => Some(format!("missing key at {}", path)),
+                }
+            })
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.iter().enumerate().find_map(|(i, value)| {
+                let path = format!("{}[{}]", path, i);
+                match actual.get(i) {
+                    Some(actual) => json_subset_mismatch(value, actual, &path),
+                    None => Some(format!("missing item at {}", path)),
+                }
+            })
+        }
+        // 1 and 1.0 are the same number.
+        (Value::Number(e), Value::Number(a)) if e.as_f64() == a.as_f64() => None,
+        _ if expected == actual => None,
+        _ => Some(format!(
+            "expected {} at {}, but got {}",
+            expected, path, actual
+        )),
+    }
+}
+
+// Runs the command of a `{{cmd:...}}` placeholder through the shell, and returns its trimmed stdout.
+fn run_cmd_placeholder(command: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
 fn substitute_keywords(input: &str) -> String {
+    // Most of the cells have no keywords, skip the regexes for them.
+    if !input.contains('$') {
+        return input.to_string();
+    }
     let mut output = input.to_string();
 
     // Replace $RandomName by iterating manually
-    let re_name = Regex::new(r"\$RandomName").unwrap();
+    static RE_NAME: OnceLock<Regex> = OnceLock::new();
+    let re_name = RE_NAME.get_or_init(|| Regex::new(r"\$RandomName").unwrap());
     while let Some(matched) = re_name.find(&output) {
         let random_name = bc::random_name();
         output = output.replacen(matched.as_str(), &random_name, 1);
     }
 
     // Replace $RandomPhone by iterating manually
-    let re_phone = Regex::new(r"\$RandomPhone").unwrap();
+    static RE_PHONE: OnceLock<Regex> = OnceLock::new();
+    let re_phone = RE_PHONE.get_or_init(|| Regex::new(r"\$RandomPhone").unwrap());
     while let Some(matched) = re_phone.find(&output) {
         let random_phone = bc::random_phone();
         output = output.replacen(matched.as_str(), &random_phone, 1);
     }
 
     // Replace $RandomAddress by iterating manually
-    let re_address = Regex::new(r"\$RandomAddress").unwrap();
+    static RE_ADDRESS: OnceLock<Regex> = OnceLock::new();
+    let re_address = RE_ADDRESS.get_or_init(|| Regex::new(r"\$RandomAddress").unwrap());
     while let Some(matched) = re_address.find(&output) {
         let random_address = bc::random_address();
         output = output.replacen(matched.as_str(), &random_address, 1);
     }
 
     // Replace $RandomCompany by iterating manually
-    let re_company = Regex::new(r"\$RandomCompany").unwrap();
+    static RE_COMPANY: OnceLock<Regex> = OnceLock::new();
+    let re_company = RE_COMPANY.get_or_init(|| Regex::new(r"\$RandomCompany").unwrap());
     while let Some(matched) = re_company.find(&output) {
         let random_company = bc::generate_company_name();
         output = output.replacen(matched.as_str(), &random_company, 1);
     }
 
     //let re_email = Regex::new(r#"\$RandomEmail(?:\("(.+?)"\))?"#).unwrap();
-    let re_email = Regex::new(r#"\$RandomEmail(?:\(\s*(?:"([^"]*)")?\s*\))?"#).unwrap();
+    static RE_EMAIL: OnceLock<Regex> = OnceLock::new();
+    let re_email = RE_EMAIL
+        .get_or_init(|| Regex::new(r#"\$RandomEmail(?:\(\s*(?:"([^"]*)")?\s*\))?"#).unwrap());
     while let Some(matched) = re_email.captures(&output) {
         let domain = matched.get(1).map(|m| m.as_str()); // Capture the domain if present
         let placeholder = matched.get(0).unwrap().as_str(); // Match the entire placeholder
@@ -900,7 +2015,8 @@ fn substitute_keywords(input: &str) -> String {
     }
 
     // Replace $UUID by manually iterating over matches
-    let re_uuid = Regex::new(r"\$UUID").unwrap();
+    static RE_UUID: OnceLock<Regex> = OnceLock::new();
+    let re_uuid = RE_UUID.get_or_init(|| Regex::new(r"\$UUID").unwrap());
     while let Some(matched) = re_uuid.find(&output) {
         let uuid = Uuid::new_v4().to_string();
         output = output.replacen(matched.as_str(), &uuid, 1);
@@ -936,6 +2052,14 @@ fn print_first_10_lines(text: &str) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_events::AssertionResult;
+    use crate::test_utils::{
+        accept_server, capture_server, concurrency_server, delay_server, mock_server,
+        reflect_header_server, request_line_server, sequence_server,
+    };
+    use calamine::Data;
+    use flate2::read::GzDecoder;
+    use std::sync::atomic::Ordering;
     //use anyhow::Ok;
     //use calamine::{open_workbook, Data, Reader, Xlsx};
     //use std::io::{self, Read};
@@ -955,13 +2079,74 @@ mod tests {
 
     #[test]
     fn test_env_vars() {
-        let mut ts_ctx = TestCtx::new().unwrap();
+        let mut ts_ctx = TestCtx::new(&Config::default()).unwrap();
         env::set_var("TEST_VAR", "test_value");
         let input = "Hello {{env:TEST_VAR}}";
         let tc = TestCase::dummy();
         let output = tc.substitute_placeholders(input, &mut ts_ctx);
         assert_eq!(output, "Hello test_value");
     }
+
+    #[test]
+    fn test_cmd_placeholder() {
+        let input = "Bearer {{cmd: echo '  s3cret  '}}";
+        let tc = TestCase::dummy();
+
+        // Disabled by default, the placeholder is left as is.
+        let mut ts_ctx = TestCtx::new(&Config::default()).unwrap();
+        assert_eq!(tc.substitute_placeholders(input, &mut ts_ctx), input);
+
+        let config = Config {
+            allow_cmd_placeholders: true,
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        assert_eq!(
+            tc.substitute_placeholders(input, &mut ts_ctx),
+            "Bearer s3cret"
+        );
+        // A failing command leaves the placeholder unchanged.
+        assert_eq!(
+            tc.substitute_placeholders("{{cmd:exit 3}}", &mut ts_ctx),
+            "{{cmd:exit 3}}"
+        );
+    }
+    #[test]
+    fn test_vars_substitution() {
+        let mut config = Config::default();
+        config.apply_vars([("tenant".to_string(), "acme".to_string())].into());
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        let mut tc = TestCase::dummy();
+        tc.url = "http://localhost:3000/{{tenant}}/users".to_string();
+        let request = tc.prepare_request(&mut ts_ctx, &config).build().unwrap();
+        assert_eq!(request.url().as_str(), "http://localhost:3000/acme/users");
+    }
+
+    #[test]
+    fn test_no_request_body() {
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let no_body = |ts_ctx: &mut TestCtx| {
+            ts_ctx.runtime.eval("SAT.expectNoRequestBody()").unwrap() == Value::Bool(true)
+        };
+
+        let mut tc = TestCase::dummy();
+        tc.method = Method::GET;
+        let _ = tc.prepare_request(&mut ts_ctx, &config);
+        assert_eq!(tc.get_end_evt_data(&mut ts_ctx).effective_payload, "");
+        assert!(no_body(&mut ts_ctx));
+
+        tc.method = Method::POST;
+        tc.payload = r#"{"name": "{{env:USER_NAME_UNSET}}"}"#.to_string();
+        let _ = tc.prepare_request(&mut ts_ctx, &config);
+        assert!(!tc
+            .get_end_evt_data(&mut ts_ctx)
+            .effective_payload
+            .is_empty());
+        assert!(!no_body(&mut ts_ctx));
+    }
+
     #[test]
     fn test_substitute_keywords() {
         let input = "Hello $RandomName, your phone number is $RandomPhone";
@@ -1118,4 +2303,1167 @@ mod tests {
         assert_ne!(name1, name3);
         assert_ne!(name2, name3);
     }
+
+    #[test]
+    fn test_token_header() {
+        let mut config = Config::default();
+        assert_eq!(
+            token_header("abc", &config),
+            ("Authorization".to_string(), "Bearer abc".to_string())
+        );
+
+        config.token_header = Some("X-Auth-Token".to_string());
+        assert_eq!(
+            token_header("abc", &config),
+            ("X-Auth-Token".to_string(), "abc".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expected_json_subset() {
+        let actual = serde_json::json!({
+            "id": 7,
+            "status": "shipped",
+            "customer": { "name": "Asha", "tier": "gold" },
+            "items": [{ "sku": "A1", "qty": 2 }, { "sku": "B2", "qty": 1 }],
+        });
+
+        // Only the fields of the fragment are compared, arrays by position.
+        let subset = serde_json::json!({
+            "id": 7.0,
+            "customer": { "tier": "gold" },
+            "items": [{ "sku": "A1" }],
+        });
+        assert_eq!(json_subset_mismatch(&subset, &actual, "$"), None);
+
+        let missing = serde_json::json!({ "customer": { "email": "a@b.c" } });
+        assert_eq!(
+            json_subset_mismatch(&missing, &actual, "$").as_deref(),
+            Some("missing key at $.customer.email")
+        );
+
+        let mismatch = serde_json::json!({ "items": [{ "sku": "A1" }, { "qty": 3 }] });
+        assert_eq!(
+            json_subset_mismatch(&mismatch, &actual, "$").as_deref(),
+            Some("expected 3 at $.items[1].qty, but got 1")
+        );
+    }
+
+    #[test]
+    fn test_expected_json_column() {
+        let mut ts_ctx = TestCtx::new(&Config::default()).unwrap();
+        ts_ctx
+            .runtime
+            .eval("SAT.response = { status: 200, body: '', json: { id: 7, name: 'box' } }")
+            .unwrap();
+
+        // Without a post test script, the fragment alone decides the result.
+        let mut tc = TestCase::dummy();
+        tc.expected_json = Some(serde_json::json!({ "name": "box" }));
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Passed);
+
+        tc.expected_json = Some(serde_json::json!({ "name": "crate" }));
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Failed);
+        let assertions = ts_ctx.get_assertions();
+        assert_eq!(assertions.len(), 1);
+        assert_eq!(assertions[0].name, "expected_json");
+        assert!(!assertions[0].passed);
+
+        // Along with a passing script, it must match as well.
+        tc.post_test_script =
+            Some("SAT.tester('ok', () => SAT.response.status === 200)".to_string());
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Failed);
+    }
+
+    // A context whose response is a 404 with an error message.
+    fn not_found_ctx() -> TestCtx {
+        let mut ts_ctx = TestCtx::new(&Config::default()).unwrap();
+        ts_ctx
+            .runtime
+            .eval(
+                "SAT.response = { status: 404, body: '{\"error\":{\"message\":\"User 42 not found\"}}', \
+                 json: { error: { message: 'User 42 not found' } } }",
+            )
+            .unwrap();
+        ts_ctx
+    }
+
+    // A test case row, with the given expected_error cell.
+    fn expected_error_row(expected_error: &str) -> Vec<Data> {
+        let mut row = vec![
+            Data::Float(1.0),
+            Data::String("Get a missing user".to_string()),
+            Data::String("no user 42".to_string()),
+            Data::String("user 42 is fetched".to_string()),
+            Data::String("it is not found".to_string()),
+            Data::String("http://localhost:3000/users/42".to_string()),
+            Data::String("GET".to_string()),
+        ];
+        row.resize(18, Data::Empty);
+        row[17] = Data::String(expected_error.to_string());
+        row
+    }
+
+    #[test]
+    fn test_expected_error_substring() {
+        let mut ts_ctx = not_found_ctx();
+        let mut tc = TestCase::new(&expected_error_row("not found"), &Config::default());
+        assert!(tc.errors.is_empty(), "{:?}", tc.errors);
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Passed);
+
+        let mut tc = TestCase::new(&expected_error_row("forbidden"), &Config::default());
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Failed);
+        let assertions = ts_ctx.get_assertions();
+        assert_eq!(assertions[0].name, "expected_error");
+        assert!(!assertions[0].passed);
+    }
+
+    #[test]
+    fn test_expected_error_at_path() {
+        let mut ts_ctx = not_found_ctx();
+        let row = expected_error_row(r#"{"path": "$.error.message", "contains": "User 42"}"#);
+        let mut tc = TestCase::new(&row, &Config::default());
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Passed);
+
+        let row = expected_error_row(r#"{"path": "$.error.message", "equals": "User 42"}"#);
+        let mut tc = TestCase::new(&row, &Config::default());
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Failed);
+
+        let expected = ExpectedError {
+            path: Some("$.error.code".to_string()),
+            contains: Some("42".to_string()),
+            equals: None,
+        };
+        assert_eq!(
+            expected_error_mismatch(&expected, &mut ts_ctx).as_deref(),
+            Some("no value at $.error.code")
+        );
+
+        // A path without contains / equals is an invalid row.
+        let tc = TestCase::new(
+            &expected_error_row(r#"{"path": "$.error"}"#),
+            &Config::default(),
+        );
+        assert_eq!(tc.errors[0].0, "expected_error");
+    }
+
+    #[test]
+    fn test_assertions_in_end_event() {
+        let mut ts_ctx = TestCtx::new(&Config::default()).unwrap();
+        let mut tc = TestCase::dummy();
+        ts_ctx
+            .runtime
+            .eval("SAT.response = { status: 201, body: '', json: { id: 7 } }")
+            .unwrap();
+
+        let post_test_script = r#"
+            SAT.tester('status is 201', () => SAT.response.status === 201);
+            SAT.tester('id is returned', () => SAT.response.json.id === 8);
+        "#;
+        tc.result = match ts_ctx.verify_result(Some(post_test_script)) {
+            true => TestResult::Passed,
+            false => TestResult::Failed,
+        };
+
+        let evt = tc.get_end_evt_data(&mut ts_ctx);
+        assert_eq!(evt.result, TestResult::Failed);
+        assert_eq!(
+            evt.assertions,
+            vec![
+                AssertionResult {
+                    name: "status is 201".to_string(),
+                    passed: true,
+                    message: None,
+                    warning: false,
+                },
+                AssertionResult {
+                    name: "id is returned".to_string(),
+                    passed: false,
+                    message: None,
+                    warning: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_warning_keeps_case_passed() {
+        let mut ts_ctx = TestCtx::new(&Config::default()).unwrap();
+        let mut tc = TestCase::dummy();
+        tc.post_test_script = Some(
+            r#"
+            SAT.warn('responds within SLA', () => SAT.response.time < 100);
+            SAT.tester('status is 200', () => SAT.response.status === 200);
+        "#
+            .to_string(),
+        );
+        ts_ctx
+            .runtime
+            .eval("SAT.response = { status: 200, time: 250, body: '' }")
+            .unwrap();
+
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Passed);
+        assert_eq!(tc.warnings, 1);
+    }
+
+    #[test]
+    fn test_gzip_encoded_payload() {
+        let (url, rx) = capture_server();
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let payload = r#"{"name":"satyanaash","items":[1,2,3]}"#;
+
+        let mut tc = TestCase::dummy();
+        tc.method = Method::POST;
+        tc.url = url;
+        tc.payload = payload.to_string();
+        tc.headers = vec![("Content-Encoding".to_string(), "gzip".to_string())];
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+
+        let (headers, body) = rx.recv().unwrap();
+        assert!(headers.contains("content-encoding: gzip"));
+        assert!(headers.contains("content-type: application/json"));
+
+        let mut decoded = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&decoded).unwrap(),
+            serde_json::from_str::<Value>(payload).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_head_request_without_body() {
+        let (url, rx) = capture_server();
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        let mut tc = TestCase::dummy();
+        tc.method = Method::HEAD;
+        tc.url = url;
+        tc.payload = r#"{"ignored":true}"#.to_string();
+        tc.post_test_script = Some(
+            "SAT.tester('length', () => SAT.response.headers['content-length'] === '2' && SAT.response.body === '')"
+                .to_string(),
+        );
+        let request = tc.prepare_request(&mut ts_ctx, &config);
+        ts_ctx.exec(request, None, &config);
+
+        let (headers, body) = rx.recv().unwrap();
+        assert!(headers.starts_with("head "));
+        assert!(!headers.contains("content-type"));
+        assert!(body.is_empty());
+
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Passed);
+    }
+
+    #[test]
+    fn test_options_request_allow_header() {
+        let url = mock_server(
+            "HTTP/1.1 204 No Content\r\nAllow: GET, HEAD, OPTIONS\r\nConnection: close\r\n\r\n",
+        );
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        let mut tc = TestCase::dummy();
+        tc.method = Method::OPTIONS;
+        tc.url = url;
+        tc.post_test_script = Some(
+            "SAT.tester('allow', () => SAT.response.headers.allow === 'GET, HEAD, OPTIONS')"
+                .to_string(),
+        );
+        let request = tc.prepare_request(&mut ts_ctx, &config);
+        ts_ctx.exec(request, None, &config);
+
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Passed);
+    }
+
+    #[test]
+    fn test_iteration_placeholders() {
+        let (url, rx) = request_line_server(3);
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        let mut tc = TestCase::dummy();
+        tc.url = url + "/users/user{{iteration}}?index={{iteration0}}";
+        tc.config.repeat_count = 3;
+        tc.post_test_script =
+            Some("SAT.tester('ok', () => SAT.response.status === 200)".to_string());
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+
+        let lines: Vec<String> = (0..3).map(|_| rx.recv().unwrap()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "GET /users/user1?index=0 HTTP/1.1",
+                "GET /users/user2?index=1 HTTP/1.1",
+                "GET /users/user3?index=2 HTTP/1.1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_concurrent_iterations_are_capped() {
+        let (url, max_in_flight) = concurrency_server(Duration::from_millis(50));
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        let mut tc = TestCase::dummy();
+        tc.url = url + "/items/{{iteration}}";
+        tc.config.repeat_count = 40;
+        tc.config.max_concurrency = 4;
+        tc.post_test_script = Some(
+            "SAT.tester('own item', () => SAT.response.body === '/items/' + SAT.globals.iteration)"
+                .to_string(),
+        );
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+
+        let max_in_flight = max_in_flight.load(Ordering::SeqCst);
+        assert!(max_in_flight > 1 && max_in_flight <= 4, "{}", max_in_flight);
+
+        // A failing iteration fails the case.
+        tc.post_test_script =
+            Some("SAT.tester('not 7', () => SAT.response.body !== '/items/7')".to_string());
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Failed);
+    }
+
+    #[test]
+    fn test_final_script_after_repeats() {
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        let mut tc = TestCase::dummy();
+        tc.config.repeat_count = 3;
+        tc.pre_test_script = Some("SAT.globals.count = (SAT.globals.count || 0) + 1;".to_string());
+        tc.post_test_script =
+            Some("SAT.tester('ok', () => SAT.response.status === 200)".to_string());
+        tc.final_script = Some("SAT.tester('3 calls', () => SAT.globals.count === 3)".to_string());
+        tc.url = request_line_server(3).0;
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+
+        // The iterations pass, but the count carries on to 6, so the final script fails.
+        tc.url = request_line_server(3).0;
+        tc.final_script = Some("SAT.tester('3 calls', () => SAT.globals.count === 3)".to_string());
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Failed);
+    }
+
+    #[test]
+    fn test_p95_across_repeats() {
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+        let (fast, slow) = (Duration::ZERO, Duration::from_millis(300));
+
+        let mut tc = TestCase::dummy();
+        tc.config.repeat_count = 20;
+        tc.post_test_script =
+            Some("SAT.tester('ok', () => SAT.response.status === 200)".to_string());
+        tc.final_script = Some(
+            "SAT.tester('20 timings', () => SAT.iterationTimesMs.length === 20)
+            && SAT.tester('p95 budget', () => SAT.expectP95Below(200))"
+                .to_string(),
+        );
+
+        // A single slow request out of 20 is within the p95 budget.
+        let mut delays = vec![fast; 20];
+        delays[7] = slow;
+        tc.url = delay_server(delays);
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+
+        // Two of them are not.
+        let mut delays = vec![fast; 20];
+        delays[3] = slow;
+        delays[12] = slow;
+        tc.url = delay_server(delays);
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Failed);
+    }
+
+    #[test]
+    fn test_timings_across_cases() {
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        let mut uncached = TestCase::dummy();
+        uncached.id = 4;
+        uncached.url = delay_server(vec![Duration::from_millis(200)]);
+        uncached.post_test_script =
+            Some("SAT.tester('ok', () => SAT.response.status === 200)".to_string());
+        assert_eq!(uncached.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+
+        let mut cached = TestCase::dummy();
+        cached.id = 5;
+        cached.url = delay_server(vec![Duration::ZERO]);
+        cached.post_test_script = Some(
+            "SAT.tester('faster', () => SAT.globals.timings[5] < SAT.globals.timings[4])"
+                .to_string(),
+        );
+        assert_eq!(cached.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+        assert!(ts_ctx.eval_json("SAT.globals.timings[4]").as_f64().unwrap() >= 200.0);
+    }
+
+    // A test case row, with the given accept variants.
+    fn accept_variants_row(url: &str, variants: &str) -> Vec<Data> {
+        let mut row = vec![
+            Data::Float(1.0),
+            Data::String("Get a report".to_string()),
+            Data::String("a report".to_string()),
+            Data::String("it is fetched in each format".to_string()),
+            Data::String("it is in that format".to_string()),
+            Data::String(format!("{}/report", url)),
+            Data::String("GET".to_string()),
+        ];
+        row.resize(19, Data::Empty);
+        row[18] = Data::String(variants.to_string());
+        row
+    }
+
+    #[test]
+    fn test_accept_variants() {
+        let config = Config::default();
+        let url = accept_server(vec!["application/json", "application/xml"]);
+        let mut tc = TestCase::new(
+            &accept_variants_row(&url, "application/json, application/xml"),
+            &config,
+        );
+        assert_eq!(
+            tc.accept_variants,
+            vec!["application/json", "application/xml"]
+        );
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+
+        // One end event per variant, each with its content type assertion.
+        let assertions: Vec<Vec<AssertionResult>> = rx
+            .try_iter()
+            .filter_map(|event| match event {
+                TestEvent::EvtTestCaseEnd(end) => Some(end.assertions),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(assertions.len(), 2);
+        assert_eq!(assertions[0][0].name, "Content-Type: application/json");
+        assert_eq!(assertions[1][0].name, "Content-Type: application/xml");
+        assert!(assertions.iter().all(|a| a[0].passed));
+
+        // A server which ignores the Accept header fails the xml variant.
+        let url = accept_server(vec!["application/json"]);
+        let mut tc = TestCase::new(
+            &accept_variants_row(&url, "application/json, application/xml"),
+            &config,
+        );
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Failed);
+    }
+
+    #[test]
+    fn test_poll_until_pass() {
+        const PENDING: &str = "HTTP/1.1 200 OK\r\nContent-Length: 20\r\nConnection: close\r\n\r\n{\"status\":\"pending\"}";
+        const DONE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"status\":\"done\"}";
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        let mut tc = TestCase::dummy();
+        tc.config.poll_until_pass = true;
+        tc.config.poll_interval_ms = 10;
+        tc.post_test_script =
+            Some("SAT.tester('done', () => SAT.response.json.status === 'done')".to_string());
+
+        let (url, requests) = sequence_server(vec![PENDING, PENDING, DONE]);
+        tc.url = url;
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+        assert_eq!(requests.try_iter().count(), 3);
+
+        // Fails once the attempts are exhausted.
+        let (url, requests) = sequence_server(vec![PENDING, PENDING, DONE]);
+        tc.url = url;
+        tc.config.poll_max_attempts = 2;
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Failed);
+        assert_eq!(requests.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn test_follow_link() {
+        const ORDERS: &str = "HTTP/1.1 200 OK\r\nContent-Length: 40\r\nConnection: close\r\n\r\n{\"_links\":{\"self\":{\"href\":\"/orders/7\"}}}";
+        const ORDER: &str =
+            "HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\n{\"id\":7}";
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        let (url, requests) = sequence_server(vec![ORDERS, ORDER]);
+        let mut tc = TestCase::dummy();
+        tc.url = format!("{}/orders", url);
+        tc.post_test_script = Some(
+            "SAT.tester('followed', () => SAT.followLink('self').json.id === 7)
+            && SAT.tester('replaced', () => SAT.response.url.endsWith('/orders/7'))"
+                .to_string(),
+        );
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+
+        // The relative href is resolved against the url of the first response.
+        let heads: Vec<String> = requests.try_iter().collect();
+        assert!(heads[1].starts_with("GET /orders/7 "), "{}", heads[1]);
+    }
+
+    #[test]
+    fn test_request_count_of_paging() {
+        const PAGE_1: &str = "HTTP/1.1 200 OK\r\nContent-Length: 38\r\nConnection: close\r\n\r\n{\"_links\":{\"next\":{\"href\":\"?page=2\"}}}";
+        const PAGE_2: &str = "HTTP/1.1 200 OK\r\nContent-Length: 38\r\nConnection: close\r\n\r\n{\"_links\":{\"next\":{\"href\":\"?page=3\"}}}";
+        const PAGE_3: &str =
+            "HTTP/1.1 200 OK\r\nContent-Length: 13\r\nConnection: close\r\n\r\n{\"_links\":{}}";
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        // Follows the next pages, until the last one.
+        let paginate = "while (SAT.response.json._links.next) { SAT.followLink('next'); }";
+        let (url, requests) = sequence_server(vec![PAGE_1, PAGE_2, PAGE_3]);
+        let mut tc = TestCase::dummy();
+        tc.url = format!("{}/items", url);
+        tc.post_test_script = Some(format!(
+            "{} SAT.tester('3 pages', () => SAT.expectRequestCount(3))",
+            paginate
+        ));
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+        assert_eq!(requests.try_iter().count(), 3);
+
+        // The count starts afresh for the next test case.
+        let (url, _requests) = sequence_server(vec![PAGE_3]);
+        tc.url = url;
+        tc.post_test_script = Some(format!(
+            "{} SAT.tester('1 page', () => SAT.expectRequestCount(1))",
+            paginate
+        ));
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+    }
+
+    #[test]
+    fn test_injected_trace_id_echoed() {
+        let url = reflect_header_server("traceparent");
+        let config = Config {
+            inject_trace_id: true,
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        let mut tc = TestCase::dummy();
+        tc.url = url;
+        tc.post_test_script = Some(
+            "SAT.tester('propagated', () => SAT.response.traceId === SAT.globals.lastTraceId)"
+                .to_string(),
+        );
+        let request = tc.prepare_request(&mut ts_ctx, &config);
+        ts_ctx.exec(request, None, &config);
+
+        let trace_id = ts_ctx.runtime.eval("SAT.globals.lastTraceId").unwrap();
+        let trace_id = trace_id.as_str().unwrap();
+        assert_eq!(trace_id.len(), 32);
+        assert!(trace_id.chars().all(|c| c.is_ascii_hexdigit()));
+
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Passed);
+    }
+
+    #[test]
+    fn test_before_request_hook_injects_header() {
+        let (url, rx) = capture_server();
+        let config = Config {
+            js_prelude: Some(
+                "SAT.onBeforeRequest = (req) => { req.headers['X-Signature'] = 'signed:' + req.method; };"
+                    .to_string(),
+            ),
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        let mut tc = TestCase::dummy();
+        tc.method = Method::POST;
+        tc.url = url;
+        tc.headers = vec![("X-Tenant".to_string(), "acme".to_string())];
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+
+        let (headers, _) = rx.recv().unwrap();
+        assert!(headers.contains("x-signature: signed:post"));
+        assert!(headers.contains("x-tenant: acme"));
+    }
+
+    #[test]
+    fn test_tag_header() {
+        let (url, rx) = capture_server();
+        let config = Config {
+            tag_header: Some("X-Test-Run".to_string()),
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        ts_ctx.run_id = Some("ci-42".to_string());
+
+        let mut tc = TestCase::dummy();
+        tc.url = url;
+        tc.headers = vec![("X-Tenant".to_string(), "acme".to_string())];
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+        let (headers, _) = rx.recv().unwrap();
+        assert!(headers.contains("x-test-run: ci-42"), "{}", headers);
+        assert!(headers.contains("x-tenant: acme"), "{}", headers);
+
+        // The header of the test case wins.
+        let (url, rx) = capture_server();
+        tc.url = url;
+        tc.headers = vec![("x-test-run".to_string(), "manual".to_string())];
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+        let (headers, _) = rx.recv().unwrap();
+        assert!(headers.contains("x-test-run: manual"), "{}", headers);
+        assert!(!headers.contains("ci-42"), "{}", headers);
+    }
+
+    #[test]
+    fn test_before_request_hook_returns_new_request() {
+        let (url, rx) = capture_server();
+        let config = Config {
+            js_prelude: Some(
+                "SAT.onBeforeRequest = (req) => ({ url: req.url + '/v2', headers: {}, body: { wrapped: JSON.parse(req.body) } });"
+                    .to_string(),
+            ),
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        let mut tc = TestCase::dummy();
+        tc.method = Method::POST;
+        tc.url = url.clone();
+        tc.payload = r#"{"id":1}"#.to_string();
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+
+        assert_eq!(tc.effective_url, url + "/v2");
+        let (_, body) = rx.recv().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            serde_json::json!({"wrapped": {"id": 1}})
+        );
+    }
+
+    #[test]
+    fn test_default_content_type() {
+        let (url, rx) = capture_server();
+        let config = Config {
+            default_content_type: Some("application/x-www-form-urlencoded".to_string()),
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        let mut tc = TestCase::dummy();
+        tc.method = Method::POST;
+        tc.url = url;
+        tc.payload = r#"{"user":"ann lee"}"#.to_string();
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+
+        let (headers, body) = rx.recv().unwrap();
+        assert!(headers.contains("content-type: application/x-www-form-urlencoded"));
+        assert_eq!(String::from_utf8(body).unwrap(), "user=ann+lee");
+    }
+
+    // Sends the payload as multipart/form-data, and returns the body received by the server.
+    fn send_multipart(payload: &str) -> String {
+        let (url, rx) = capture_server();
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        let mut tc = TestCase::dummy();
+        tc.method = Method::POST;
+        tc.url = url;
+        tc.headers = vec![(
+            "Content-Type".to_string(),
+            "multipart/form-data".to_string(),
+        )];
+        tc.payload = payload.to_string();
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+        String::from_utf8(rx.recv().unwrap().1).unwrap()
+    }
+
+    #[test]
+    fn test_multipart_wrapped_fields() {
+        let body = send_multipart(r#"{"form-data":{"fields":{"name":"x","age":"3"}}}"#);
+        assert!(body.contains("name=\"name\"\r\n\r\nx\r\n"));
+        assert!(body.contains("name=\"age\"\r\n\r\n3\r\n"));
+    }
+
+    #[test]
+    fn test_multipart_flat_fields() {
+        let body = send_multipart(r#"{"name":"x","age":"3"}"#);
+        assert!(body.contains("name=\"name\"\r\n\r\nx\r\n"));
+        assert!(body.contains("name=\"age\"\r\n\r\n3\r\n"));
+    }
+
+    #[test]
+    fn test_preset_jwt_token() {
+        let (url, rx) = capture_server();
+        let config = Config {
+            jwt_token: Some("preset-token".to_string()),
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        let mut tc = TestCase::dummy();
+        tc.url = url;
+        tc.config.auth_type = AuthType::Authorized;
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+
+        let (headers, _) = rx.recv().unwrap();
+        assert!(headers.contains("authorization: bearer preset-token"));
+    }
+
+    #[test]
+    fn test_auto_authorize_group() {
+        let config = Config {
+            token_key: Some("token".to_string()),
+            auto_authorize_group: true,
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        // No token is sent before the authorizer of the group ran.
+        let (url, rx) = capture_server();
+        let mut tc = TestCase::dummy();
+        tc.url = url;
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+        assert!(!rx.recv().unwrap().0.contains("authorization:"));
+
+        let mut login = TestCase::dummy();
+        login.url = mock_server(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"token\":\"group\"}",
+        );
+        login.config.auth_type = AuthType::Authorizer;
+        let request = login.prepare_request(&mut ts_ctx, &config);
+        ts_ctx.exec(request, Some(DEFAULT_TOKEN), &config);
+
+        // A later test case, without `authType: authorized`, carries the token.
+        let (url, rx) = capture_server();
+        tc.url = url;
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+        assert!(rx.recv().unwrap().0.contains("authorization: bearer group"));
+    }
+
+    #[test]
+    fn test_global_post_script() {
+        let config = Config {
+            global_post_script: Some(
+                "SAT.tester('request id', () => 'x-request-id' in SAT.response.headers)"
+                    .to_string(),
+            ),
+            ..Config::default()
+        };
+        let mut tc = TestCase::dummy();
+        tc.post_test_script =
+            Some("SAT.tester('200', () => SAT.response.status === 200)".to_string());
+
+        // The case's own script passes, but the response has no request id.
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let url =
+            mock_server("HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}");
+        let request = ts_ctx.client.get(&url);
+        ts_ctx.exec(request, None, &config);
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Failed);
+        let names: Vec<String> = ts_ctx
+            .get_assertions()
+            .into_iter()
+            .map(|a| a.name)
+            .collect();
+        assert_eq!(names, vec!["200", "request id"]);
+
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nX-Request-Id: r-1\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+        );
+        let request = ts_ctx.client.get(&url);
+        ts_ctx.exec(request, None, &config);
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Passed);
+    }
+
+    #[test]
+    fn test_auto_reauth() {
+        let token = |t: &str| -> &'static str {
+            Box::leak(
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{{\"token\":\"{}\"}}",
+                    t
+                )
+                .into_boxed_str(),
+            )
+        };
+        // The token expires after the first request.
+        let (url, rx) = sequence_server(vec![
+            token("tok-1"),
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            token("tok-2"),
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+        ]);
+        let config = Config {
+            token_key: Some("token".to_string()),
+            auto_reauth: true,
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        let mut login = TestCase::dummy();
+        login.url = format!("{}/login", url);
+        login.config.auth_type = AuthType::Authorizer;
+        login.run(&mut ts_ctx, &config, &tx);
+
+        let mut tc = TestCase::dummy();
+        tc.url = format!("{}/orders/$RandomName", url);
+        tc.config.auth_type = AuthType::Authorized;
+        tc.post_test_script =
+            Some("SAT.tester('200', () => SAT.response.status === 200)".to_string());
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Passed);
+
+        let heads: Vec<String> = rx.try_iter().collect();
+        assert_eq!(heads.len(), 4);
+        assert!(heads[1].starts_with("GET /orders"), "{}", heads[1]);
+        assert!(heads[1].contains("Bearer tok-1"), "{}", heads[1]);
+        assert!(heads[2].starts_with("GET /login"), "{}", heads[2]);
+        // The same request is resent, its random name is not drawn again.
+        assert_eq!(heads[3].lines().next(), heads[1].lines().next());
+        assert!(heads[3].contains("Bearer tok-2"), "{}", heads[3]);
+        assert!(!heads[3].contains("tok-1"), "{}", heads[3]);
+        assert_eq!(ts_ctx.tokens[DEFAULT_TOKEN], "tok-2");
+    }
+
+    #[test]
+    fn test_auto_reauth_failed() {
+        // The token expires after the first request, and the authorizer fails when re-run.
+        let (url, rx) = sequence_server(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"token\":\"tok-1\"}",
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let config = Config {
+            token_key: Some("token".to_string()),
+            auto_reauth: true,
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        let mut login = TestCase::dummy();
+        login.url = format!("{}/login", url);
+        login.config.auth_type = AuthType::Authorizer;
+        login.run(&mut ts_ctx, &config, &tx);
+
+        let mut tc = TestCase::dummy();
+        tc.url = format!("{}/orders", url);
+        tc.config.auth_type = AuthType::Authorized;
+        tc.post_test_script =
+            Some("SAT.tester('200', () => SAT.response.status === 200)".to_string());
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Failed);
+
+        // The request is not retried, and the scripts see its own 401.
+        let heads: Vec<String> = rx.try_iter().collect();
+        assert_eq!(heads.len(), 3);
+        assert_eq!(ts_ctx.get_http_status(), 401);
+        assert_eq!(ts_ctx.tokens[DEFAULT_TOKEN], "tok-1");
+    }
+
+    #[test]
+    fn test_named_tokens() {
+        let config = Config {
+            token_key: Some("token".to_string()),
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+
+        // An admin and a user log in, each token is kept under its name.
+        for (name, response) in [
+            ("admin", "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"token\":\"adm-1\"}"),
+            ("user", "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"token\":\"usr-1\"}"),
+        ] {
+            let mut login = TestCase::dummy();
+            login.url = mock_server(response);
+            login.config.auth_type = AuthType::Authorizer;
+            login.config.token_name = Some(name.to_string());
+            let request = login.prepare_request(&mut ts_ctx, &config);
+            ts_ctx.exec(request, login.authorizer_token(), &config);
+        }
+        assert_eq!(ts_ctx.tokens.len(), 2);
+
+        // Each authorized case sends the token it names.
+        for (name, expected) in [("admin", "adm-1"), ("user", "usr-1")] {
+            let (url, rx) = capture_server();
+            let mut tc = TestCase::dummy();
+            tc.url = url;
+            tc.config.auth_type = AuthType::Authorized;
+            tc.config.token_name = Some(name.to_string());
+            tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+            let (headers, _) = rx.recv().unwrap();
+            assert!(
+                headers.contains(&format!("authorization: bearer {}", expected)),
+                "{}",
+                headers
+            );
+        }
+
+        // Without a name, none of them is sent.
+        let (url, rx) = capture_server();
+        let mut tc = TestCase::dummy();
+        tc.url = url;
+        tc.config.auth_type = AuthType::Authorized;
+        tc.prepare_request(&mut ts_ctx, &config).send().unwrap();
+        assert!(!rx.recv().unwrap().0.contains("authorization:"));
+    }
+
+    #[test]
+    fn test_dump_on_failure() {
+        let dir = std::env::temp_dir().join(format!("dumps_{}", std::process::id()));
+        let config = Config {
+            dump_on_failure_dir: Some(dir.to_string_lossy().to_string()),
+            vars: Some(std::collections::HashMap::from([(
+                "tenant".to_string(),
+                "acme".to_string(),
+            )])),
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let (tx, _events) = std::sync::mpsc::channel();
+
+        let mut tc = TestCase::dummy();
+        tc.id = 7;
+        tc.url = mock_server(
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 4\r\nConnection: close\r\n\r\noops",
+        ) + "/{{tenant}}/orders";
+        tc.post_test_script =
+            Some("SAT.tester('ok', () => SAT.response.status === 200)".to_string());
+        assert_eq!(tc.run(&mut ts_ctx, &config, &tx), TestResult::Failed);
+
+        let dump: Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("7.json")).unwrap()).unwrap();
+        let keys: Vec<&String> = dump.as_object().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            vec!["id", "name", "request", "response", "assertions", "globals"]
+        );
+        assert!(dump["request"]["url"]
+            .as_str()
+            .unwrap()
+            .ends_with("/acme/orders"));
+        assert_eq!(dump["response"]["status"], 500);
+        assert_eq!(dump["response"]["body"], "oops");
+        assert_eq!(dump["assertions"][0]["passed"], false);
+        assert_eq!(dump["globals"]["tenant"], "acme");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_ascii_result_markers() {
+        assert_eq!(result_marker(&TestResult::Passed, true), "[PASS]");
+        assert_eq!(result_marker(&TestResult::Failed, true), "[FAIL]");
+        assert_eq!(result_marker(&TestResult::Skipped, true), "[SKIP]");
+        assert_eq!(result_marker(&TestResult::Passed, false), "✅ PASSED");
+    }
+
+    #[test]
+    fn test_quiet_line() {
+        let url =
+            mock_server("HTTP/1.1 201 Created\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let request = ts_ctx.client.post(&url);
+        ts_ctx.exec(request, None, &config);
+
+        let mut tc = TestCase::dummy();
+        tc.id = 12;
+        tc.name = "Create user".to_string();
+        tc.result = TestResult::Passed;
+        let line = tc.quiet_line(&mut ts_ctx);
+        assert!(line.contains("[PASS] 12 Create user (201, "), "{}", line);
+        assert!(line.contains("ms)"), "{}", line);
+        assert_eq!(line.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_expected_sha256() {
+        // sha256 of the fixture "hello world"
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: 11\r\nConnection: close\r\n\r\nhello world",
+        );
+        let config = Config::default();
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let request = ts_ctx.client.get(&url);
+        ts_ctx.exec(request, None, &config);
+
+        let mut tc = TestCase::dummy();
+        tc.expected_sha256 =
+            Some("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9".to_string());
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Passed);
+
+        tc.expected_sha256 = Some("0".repeat(64));
+        tc.verify(&mut ts_ctx);
+        assert_eq!(tc.result, TestResult::Failed);
+    }
+
+    #[test]
+    fn test_notes_in_begin_event() {
+        let mut row = vec![
+            Data::Float(1.0),
+            Data::String("Get users".to_string()),
+            Data::String("a running server".to_string()),
+            Data::String("users are fetched".to_string()),
+            Data::String("a list is returned".to_string()),
+            Data::String("http://localhost:3000/users".to_string()),
+            Data::String("GET".to_string()),
+        ];
+        row.resize(14, Data::Empty);
+        row[13] = Data::String(" Regression for JIRA-42 ".to_string());
+
+        let tc = TestCase::new(&row, &Config::default());
+        assert_eq!(tc.notes.as_deref(), Some("Regression for JIRA-42"));
+        assert_eq!(
+            tc.get_start_evt_data().notes.as_deref(),
+            Some("Regression for JIRA-42")
+        );
+
+        row.truncate(12);
+        assert_eq!(TestCase::new(&row, &Config::default()).notes, None);
+    }
+
+    // A test case row, with the given url, headers and template cells.
+    fn template_row(id: f64, url: &str, headers: &str, template: &str) -> Vec<Data> {
+        let cell = |s: &str| match s {
+            "" => Data::Empty,
+            s => Data::String(s.to_string()),
+        };
+        let mut row = vec![
+            Data::Float(id),
+            cell(&format!("case {}", id)),
+            cell("given"),
+            cell("when"),
+            cell("then"),
+            cell(url),
+            cell("GET"),
+            cell(headers),
+        ];
+        row.resize(17, Data::Empty);
+        row[16] = cell(template);
+        row
+    }
+
+    #[test]
+    fn test_template_inheritance() {
+        // The child comes before its template, which has a template of its own.
+        let child = template_row(3.0, "http://localhost:3000/orders", "", "2");
+        let template = template_row(2.0, "", "X-Tenant: acme", "1");
+        let base = template_row(1.0, "http://localhost:3000/users", "Accept: text/csv", "");
+        let rows = resolve_templates(&[&child, &template, &base]);
+
+        let tc = TestCase::new(&rows[0], &Config::default());
+        assert!(tc.errors.is_empty(), "{:?}", tc.errors);
+        assert_eq!(tc.url, "http://localhost:3000/orders");
+        assert_eq!(
+            tc.headers,
+            vec![("X-Tenant".to_string(), "acme".to_string())]
+        );
+        assert_eq!(tc.name, "case 3");
+
+        // The template inherits the url of its own template.
+        let tc = TestCase::new(&rows[1], &Config::default());
+        assert_eq!(tc.url, "http://localhost:3000/users");
+    }
+
+    #[test]
+    fn test_unresolved_template() {
+        let missing = template_row(1.0, "http://localhost:3000/users", "", "9");
+        let cyclic_a = template_row(2.0, "http://localhost:3000/a", "", "3");
+        let cyclic_b = template_row(3.0, "http://localhost:3000/b", "", "2");
+        let rows = resolve_templates(&[&missing, &cyclic_a, &cyclic_b]);
+        for row in &rows {
+            let tc = TestCase::new(row, &Config::default());
+            assert!(tc.errors.iter().any(|(field, _)| field == "template"));
+        }
+    }
+
+    #[cfg(feature = "ntlm")]
+    #[test]
+    fn test_ntlm_handshake() {
+        use crate::config::NtlmConfig;
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let (url, authorizations) = crate::test_utils::ntlm_server();
+        let config = Config {
+            ntlm: Some(NtlmConfig {
+                user: "User".to_string(),
+                password: "Password".to_string(),
+                domain: Some("Domain".to_string()),
+                ..NtlmConfig::default()
+            }),
+            ..Config::default()
+        };
+        let mut ts_ctx = TestCtx::new(&config).unwrap();
+        let mut tc = TestCase::dummy();
+        tc.url = format!("{}/intranet", url);
+        tc.config.auth_type = AuthType::Ntlm;
+
+        // The challenge is answered on the connection it came on.
+        let request = tc.prepare_request(&mut ts_ctx, &config);
+        ts_ctx.exec(request, None, &config);
+        assert_eq!(tc.get_exec_status(&mut ts_ctx), 200);
+
+        // The authenticate message carries the user, in UTF-16.
+        let message = STANDARD.decode(authorizations.recv().unwrap()).unwrap();
+        let user: Vec<u8> = "User".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert!(message.windows(user.len()).any(|w| w == user.as_slice()));
+    }
+
+    #[test]
+    fn test_body_checksum() {
+        let send = |tc: &mut TestCase, config: &Config| {
+            let (url, rx) = capture_server();
+            let mut ts_ctx = TestCtx::new(config).unwrap();
+            tc.method = Method::POST;
+            tc.url = url;
+            tc.payload = r#"{"a": 1}"#.to_string();
+            tc.prepare_request(&mut ts_ctx, config).send().unwrap();
+            rx.recv().unwrap()
+        };
+
+        // The checksum of the config is computed over the body as sent.
+        let config = Config {
+            body_checksum: serde_yaml::from_str("header: x-amz-content-sha256\nalgorithm: sha256")
+                .unwrap(),
+            ..Config::default()
+        };
+        let (headers, body) = send(&mut TestCase::dummy(), &config);
+        assert_eq!(body, br#"{"a":1}"#);
+        assert!(headers.contains(
+            "x-amz-content-sha256: 015abd7f5cc57a2dd94b7590f04ad8084273905ee33ec5cebeae62276a97f862"
+        ));
+
+        // The one of the test case overrides it.
+        let mut tc = TestCase::dummy();
+        tc.config = serde_json::from_str(
+            r#"{"bodyChecksum": {"header": "Content-MD5", "algorithm": "md5", "encoding": "base64"}}"#,
+        )
+        .unwrap();
+        let (headers, _) = send(&mut tc, &config);
+        assert!(headers.contains("content-md5: u2y1xo30zslbyvzso2by2a=="));
+        assert!(!headers.contains("x-amz-content-sha256"));
+    }
 }
@@ -1,22 +1,58 @@
+use std::collections::HashMap;
 use std::error::Error;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 
-use crate::{config::Config, v8engine::JsEngine};
+use crate::approval::Approvals;
+use crate::data_table::DataTables;
+#[cfg(feature = "ntlm")]
+use crate::ntlm;
+use crate::transport::{SendResult, Transport};
+use crate::{config::Config, test_events::AssertionResult, v8engine::JsEngine};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use encoding_rs::Encoding;
+use reqwest::cookie::Jar;
+use reqwest::header::SET_COOKIE;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+// Name of the token of the authorizers and authorized test cases without a `tokenName`.
+pub const DEFAULT_TOKEN: &str = "default";
 
 // A convenient struct for packing the arguments for testcase::run.
 // In future, we may be able to add more params, without changing the run method signature.
 #[derive(Debug)]
 pub struct TestCtx {
     pub client: reqwest::blocking::Client,
-    pub jwt_token: Option<String>,
+    cookie_jar: Arc<Jar>,      // Cookies shared by the requests of the group.
+    transport: Arc<Transport>, // Sends the requests (or replays them), shared by the groups.
+    pub tokens: HashMap<String, String>, // Tokens captured by the authorizers, by their name.
+    pub group_authorized: bool, // An authorizer test case of the group captured a token.
+    pub allow_cmd_placeholders: bool, // Resolve the `{{cmd:...}}` placeholders (--allow-cmd-placeholders).
+    pub global_post_script: Option<String>, // Checks every response, along with the test case's checks.
+    pub approvals: Approvals, // Approves the responses, or compares them with the approved ones.
+    pub data_tables: DataTables, // The worksheets of the `{{table:...}}` placeholders.
+    pub run_id: Option<String>, // Id of the run, sent in the `tag_header` of the config.
+    pub base_dir: PathBuf,    // The relative paths of the test cases are resolved against it.
+    reauth_requests: HashMap<String, reqwest::blocking::RequestBuilder>, // Of the authorizers, by their token (`auto_reauth`).
     pub runtime: JsEngine,
 
     // More fields as necessary
     exec_duration: std::time::Duration,
+    request_count: Arc<AtomicUsize>, // Requests sent for the current test case, `SAT.followLink` included.
 }
 
 impl TestCtx {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
+        Self::with_transport(config, Arc::default())
+    }
+
+    pub fn with_transport(
+        config: &Config,
+        transport: Arc<Transport>,
+    ) -> Result<Self, Box<dyn Error>> {
         let mut runtime = JsEngine::new();
         //runtime.initialize_globals().unwrap();
         runtime.initialize_globals().map_err(|e| {
@@ -24,87 +60,375 @@ impl TestCtx {
             e
         })?;
 
-        let client = reqwest::blocking::Client::builder()
+        runtime.eval(&format!(
+            "SAT.numberTolerance = {};",
+            config.number_tolerance
+        ))?;
+
+        // Seed the globals with the vars (ex: --var tenant=acme), so that the placeholders resolve.
+        for (key, value) in config.vars.iter().flatten() {
+            runtime.eval(&format!(
+                "SAT.globals[{}] = {};",
+                serde_json::to_string(key)?,
+                serde_json::to_string(value)?
+            ))?;
+        }
+
+        // Make the user defined helpers (if any) available to all the scripts.
+        if let Some(prelude) = &config.js_prelude {
+            runtime
+                .load_script("[js_prelude]", prelude.clone())
+                .map_err(|e| {
+                    format!(
+                        "Error evaluating js_prelude_file '{}': {}",
+                        config.js_prelude_file.as_deref().unwrap_or_default(),
+                        e
+                    )
+                })?;
+        }
+
+        let cookie_jar = Arc::new(Jar::default());
+        let mut builder = reqwest::blocking::Client::builder()
             .danger_accept_invalid_certs(true)
-            .build()
-            .map_err(|e| {
-                eprintln!("Failed to create reqwest client: {}", e);
-                e
-            })?;
+            .cookie_provider(cookie_jar.clone());
+
+        // Speak HTTP/2 right away, without an upgrade (ex: for h2c endpoints).
+        if config.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+
+        // Header names are lowercased on the wire, unless Title-Case is asked for (HTTP/1 only).
+        if config.http1_title_case_headers {
+            builder = builder.http1_title_case_headers();
+        }
+
+        // Send from the given local address, ex: to test the routing of a multi-homed host.
+        if let Some(local_address) = config.local_address {
+            builder = builder.local_address(local_address);
+        }
+
+        // With `max_redirects: 0`, a redirect is not followed, for the scripts to check it.
+        match config.max_redirects {
+            Some(0) => builder = builder.redirect(reqwest::redirect::Policy::none()),
+            Some(max) => builder = builder.redirect(reqwest::redirect::Policy::limited(max)),
+            None => {}
+        }
+
+        // Tune the reuse of the connections, ex: for the benchmark runs.
+        if let Some(max_idle) = config.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(timeout) = config.pool_idle_timeout_secs {
+            builder = builder.pool_idle_timeout(std::time::Duration::from_secs(timeout));
+        }
+
+        let client = builder.build().map_err(|e| {
+            eprintln!("Failed to create reqwest client: {}", e);
+            e
+        })?;
+
+        // `SAT.followLink` sends its requests through the client and the cookies of the group.
+        let request_count = Arc::new(AtomicUsize::new(0));
+        let (link_client, link_jar, link_transport, link_count) = (
+            client.clone(),
+            cookie_jar.clone(),
+            transport.clone(),
+            request_count.clone(),
+        );
+        runtime.set_http_get(Box::new(move |href, base| {
+            let url = reqwest::Url::parse(base)
+                .and_then(|base| base.join(href))
+                .or_else(|_| reqwest::Url::parse(href))?;
+            let request = link_client.get(url).build()?;
+            let count = link_count.fetch_add(1, Ordering::SeqCst) + 1;
+            let response = link_transport
+                .send(&link_client, &link_jar, request)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            let mut response = sat_response(response).0;
+            response["requestCount"] = Value::from(count);
+            Ok(response)
+        }));
 
         Ok(TestCtx {
             client,
-            jwt_token: None,
+            cookie_jar,
+            transport,
+            tokens: config
+                .preset_jwt_token()?
+                .map(|token| (DEFAULT_TOKEN.to_string(), token))
+                .into_iter()
+                .collect(),
+            group_authorized: false,
+            allow_cmd_placeholders: config.allow_cmd_placeholders,
+            global_post_script: config.global_post_script.clone(),
+            approvals: Approvals::new(config.approvals.as_ref(), config.approve),
+            data_tables: DataTables::default(),
+            run_id: None,
+            base_dir: PathBuf::new(),
+            reauth_requests: HashMap::new(),
             runtime,
             exec_duration: std::time::Duration::new(0, 0),
+            request_count,
         })
     }
 
-    pub fn update_token(&mut self, token: Option<String>) {
-        self.jwt_token = token;
+    // Resolves a path of a test case (ex: an uploaded file) against the base directory. An
+    // absolute path is kept as is.
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        self.base_dir.join(path)
+    }
+
+    // Keeps a copy of the request of an authorizer, to fetch a fresh token with on a 401
+    // (`auto_reauth`). A request with a streamed body can't be copied, and is not kept.
+    pub fn set_reauth_request(
+        &mut self,
+        token_name: &str,
+        request: &reqwest::blocking::RequestBuilder,
+    ) {
+        if let Some(request) = request.try_clone() {
+            self.reauth_requests.insert(token_name.to_string(), request);
+        }
+    }
+
+    // Re-sends the kept request of the authorizer of the token (`auto_reauth`), and stores the
+    // token of its response. Returns the token, if the authorizer succeeded and returned one.
+    // The response is not exposed to the scripts, `SAT.response` stays the one of the test case.
+    pub fn refresh_token(&mut self, token_name: &str, config: &Config) -> Option<String> {
+        let request = self
+            .reauth_requests
+            .get(token_name)?
+            .try_clone()?
+            .build()
+            .ok()?;
+        self.request_count.fetch_add(1, Ordering::SeqCst);
+        self.add_pending_cookies(request.url());
+        let response = self
+            .transport
+            .send(&self.client, &self.cookie_jar, request)
+            .ok()
+            .filter(|response| response.status().is_success())?;
+        let (_, body) = sat_response(response);
+        let token = extract_token(&body, config)?;
+        self.update_token(token_name, token.clone());
+        Some(token)
+    }
+
+    pub fn update_token(&mut self, name: &str, token: String) {
+        self.tokens.insert(name.to_string(), token);
     }
 
     pub fn exec(
         &mut self,
         request: reqwest::blocking::RequestBuilder,
-        is_authorizer: bool,
+        token_name: Option<&str>,
         config: &Config,
     ) {
         let start = std::time::Instant::now();
-        let response = request.send();
-        println!("DEBUG: response: {:?}", response);
+        self.request_count.fetch_add(1, Ordering::SeqCst);
+        let response = match request.build() {
+            Ok(request) => {
+                self.add_pending_cookies(request.url());
+                self.transport.send(&self.client, &self.cookie_jar, request)
+            }
+            Err(e) => Err(e.into()),
+        };
         self.exec_duration = start.elapsed();
+        self.apply_response(response, token_name, config);
+    }
+
+    // Sends the request with an NTLM negotiate message, and answers the challenge of the 401.
+    // Returns the `Authorization` value the request is to be sent with, on the same connection.
+    #[cfg(feature = "ntlm")]
+    pub fn ntlm_authorization(
+        &mut self,
+        request: &reqwest::blocking::RequestBuilder,
+        creds: &crate::config::NtlmConfig,
+    ) -> Result<String, Box<dyn Error>> {
+        use reqwest::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+
+        let scheme = creds.scheme();
+        let negotiate = request
+            .try_clone()
+            .ok_or("The request body can't be sent twice, for the handshake")?
+            .header(
+                AUTHORIZATION,
+                format!("{} {}", scheme, ntlm::negotiate_message()),
+            )
+            .build()?;
+        self.add_pending_cookies(negotiate.url());
+        self.request_count.fetch_add(1, Ordering::SeqCst);
+        let response = self
+            .transport
+            .send(&self.client, &self.cookie_jar, negotiate)
+            .map_err(|e| e.to_string())?;
+        let challenge = response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(|value| ntlm::parse_challenge(value, scheme).ok())
+            .ok_or_else(|| {
+                format!(
+                    "No {} challenge in the {} response",
+                    scheme,
+                    response.status()
+                )
+            })?;
+        // Drain the body, for the connection to be reused.
+        let _ = response.bytes();
+
+        let password = creds.password()?;
+        Ok(format!(
+            "{} {}",
+            scheme,
+            ntlm::authenticate_message(creds, &password, &challenge)
+        ))
+    }
+
+    // Sends the requests with at most `max_concurrency` of them in flight, and returns the
+    // responses (with their latency) in the order of the requests.  Only the sends run on the
+    // worker threads, the responses are to be applied (and verified) on this thread with
+    // `apply_response`.
+    pub fn send_all(
+        &mut self,
+        requests: Vec<reqwest::blocking::RequestBuilder>,
+        max_concurrency: usize,
+    ) -> Vec<(SendResult, std::time::Duration)> {
+        let mut responses: Vec<_> = requests.iter().map(|_| None).collect();
+        self.send_each(requests, max_concurrency, |_, index, response, latency| {
+            responses[index] = Some((response, latency));
+        });
+        responses.into_iter().flatten().collect()
+    }
+
+    // Like `send_all`, but hands each response (along with the index of its request, and its
+    // latency) to `on_response` on this thread as soon as it arrives, so in the order of completion.
+    // The runtime is not thread safe, so the scripts are only ever run from `on_response`.
+    pub fn send_each(
+        &mut self,
+        requests: Vec<reqwest::blocking::RequestBuilder>,
+        max_concurrency: usize,
+        mut on_response: impl FnMut(&mut Self, usize, SendResult, std::time::Duration),
+    ) {
+        let start = std::time::Instant::now();
+        let pending: Vec<_> = requests
+            .into_iter()
+            .map(|request| {
+                request.build().inspect(|request| {
+                    self.add_pending_cookies(request.url());
+                })
+            })
+            .enumerate()
+            .collect();
+        let count = pending.len();
+        self.request_count.fetch_add(count, Ordering::SeqCst);
+        let pending = Mutex::new(pending.into_iter());
+
+        // The workers get their own handles, to leave this context free for `on_response`.
+        let (client, cookie_jar, transport) = (
+            self.client.clone(),
+            self.cookie_jar.clone(),
+            self.transport.clone(),
+        );
+        let (tx, rx) = mpsc::channel();
+        thread::scope(|scope| {
+            // Each worker sends the next pending request, until there are none left.
+            for _ in 0..max_concurrency.clamp(1, count.max(1)) {
+                let tx = tx.clone();
+                let (pending, client, cookie_jar, transport) =
+                    (&pending, &client, &cookie_jar, &transport);
+                scope.spawn(move || loop {
+                    let Some((index, request)) = pending.lock().unwrap().next() else {
+                        break;
+                    };
+                    let sent = std::time::Instant::now();
+                    let response = match request {
+                        Ok(request) => transport.send(client, cookie_jar, request),
+                        Err(e) => Err(e.into()),
+                    };
+                    let _ = tx.send((index, response, sent.elapsed()));
+                });
+            }
+            drop(tx);
+
+            for (index, response, latency) in rx {
+                self.exec_duration = latency;
+                on_response(self, index, response, latency);
+            }
+        });
+        self.exec_duration = start.elapsed();
+    }
+
+    // Starts afresh the count of the requests, exposed as `SAT.response.requestCount`.
+    pub fn clear_request_count(&mut self) {
+        self.request_count.store(0, Ordering::SeqCst);
+    }
+
+    // Makes the response (or the error) available to the scripts as `SAT.response`.
+    // The token found in the response of an authorizer is stored under the given name.
+    pub fn apply_response(
+        &mut self,
+        response: SendResult,
+        token_name: Option<&str>,
+        config: &Config,
+    ) {
+        if config.print_debug() {
+            println!("DEBUG: response: {:?}", response);
+        }
         match response {
             Ok(response) => {
-                // Get the status
-                let status = response.status().as_u16();
-
-                // Get the body as a string
-                let body = response
-                    .text()
-                    .unwrap_or_else(|_| String::from("Failed to read response body"));
-
-                // Sanitize the body string for JavaScript
-                let sanitized_body = body
-                    .replace('\\', "\\\\") // Escape backslashes
-                    .replace('`', "\\`") // Escape backticks
-                    .replace('"', "\\\"") // Escape double quotes
-                    .replace('\'', "\\'") // Escape single quotes
-                    .replace('\n', "\\n") // Replace newlines with \n
-                    .replace('\r', "\\r"); // Replace carriage returns with \r
-
-                println!("DBG: response Body : {}", body);
-
-                // Parse the body string as JSON
-                let body_json: Value = match serde_json::from_str::<Value>(&body) {
-                    Ok(json) => {
-                        // if is_authorizer is true, extract and store the token
-                        if is_authorizer {
-                            // extract the token's key from config file.
-                            if let Some(token) = extract_token(&body, config) {
-                                self.update_token(Some(token));
-                            }
-                        }
-                        json
+                let (sat_response, body) = sat_response(response);
+                if config.print_debug() {
+                    println!("DBG: response Body : {}", body);
+                }
+
+                // if the test case is an authorizer, extract and store the token
+                if let Some(name) = token_name.filter(|_| !sat_response["json"].is_null()) {
+                    // extract the token's key from config file.
+                    if let Some(token) = extract_token(&body, config) {
+                        self.update_token(name, token);
+                        self.group_authorized = true;
                     }
-                    Err(_) => Value::Null,
-                };
+                }
 
                 // Pass the status, body, and body_json to the JavaScript context
                 self.runtime
-                    .eval(&format!(
-                        "SAT.response = {{ status: {}, body: `{}`, json: {} }}",
-                        status, sanitized_body, body_json
-                    ))
+                    .eval(&format!("SAT.response = {};", sat_response))
                     .unwrap();
             }
             Err(e) => {
                 // Clear the response in the JavaScript context
                 self.runtime
-                    .eval(&format!("SAT.response = {{ status: 0, body: `{}` }}", e))
+                    .eval(&format!(
+                        "SAT.response = {{ status: 0, headers: {{}}, cookies: {{}}, body: `{}` }}",
+                        e
+                    ))
                     .unwrap();
             }
         }
+
+        let _ = self.runtime.eval(&format!(
+            "SAT.response.requestCount = {};",
+            self.request_count.load(Ordering::SeqCst)
+        ));
+
+        // Some APIs reply 200 with an error body, which the detector flags as `isSoftError`.
+        // It is compiled at runtime, so that a syntax error in it is reported like a throw.
+        if let Some(detector) = &config.soft_error_detector {
+            let body = Value::String(format!("return ({});", detector));
+            let error = self.eval_json(&format!(
+                "(function() {{ try {{ SAT.response.isSoftError = new Function({}).call() === true; return null; }} catch (e) {{ SAT.response.isSoftError = false; return String(e); }} }})()",
+                body
+            ));
+            if let Some(error) = error.as_str() {
+                eprintln!("Error evaluating soft_error_detector: {}", error);
+            }
+        }
+
+        // Let the `SAT.onAfterResponse` hook (if defined) see every response.
+        let _ = self.runtime.eval(
+            "if (typeof SAT.onAfterResponse === 'function') { SAT.onAfterResponse(SAT.response); }",
+        );
     }
 
     // Verify if the test has passed or failed.
@@ -112,9 +436,12 @@ impl TestCtx {
         // Debug and see if the SAT.test function exists in the runtime.
         //println!("DEBUG: SAT.test: {:?}", self.runtime.eval("SAT.test"));
         if let Some(script) = script {
+            // Start afresh, so that only this script's assertions are recorded.
+            let _ = self.runtime.eval("SAT.assertions = [];");
             match self.runtime.eval(script) {
                 Ok(result) => match result.as_bool() {
-                    Some(true) => true,
+                    // Every assertion in the script must pass, not just the last one.
+                    Some(true) => self.get_assertions().iter().all(|a| a.passed || a.warning),
                     _ => false,
                 },
                 Err(e) => {
@@ -127,6 +454,151 @@ impl TestCtx {
         }
     }
 
+    // Like `verify_result`, but keeps the assertions recorded so far (ex: by the post test
+    // script), and only checks the ones recorded by this script.
+    pub fn verify_more(&mut self, script: &str) -> bool {
+        let recorded = self.get_assertions().len();
+        match self.runtime.eval(script) {
+            Ok(result) if result.as_bool() == Some(true) => self
+                .get_assertions()
+                .iter()
+                .skip(recorded)
+                .all(|a| a.passed || a.warning),
+            Ok(_) => false,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                false
+            }
+        }
+    }
+
+    // Evaluates the expression, and returns its value as json (null if it can't be serialized).
+    pub fn eval_json(&mut self, expr: &str) -> Value {
+        self.runtime
+            .eval(&format!("JSON.stringify({})", expr))
+            .ok()
+            .and_then(|json| serde_json::from_str(json.as_str()?).ok())
+            .unwrap_or(Value::Null)
+    }
+
+    // Starts afresh, for the checks made without a post test script (ex: `expected_json`).
+    pub fn clear_assertions(&mut self) {
+        let _ = self.runtime.eval("SAT.assertions = [];");
+    }
+
+    // Records the result of a check made outside of the scripts, along with their assertions.
+    pub fn record_assertion(&mut self, name: &str, passed: bool, message: Option<&str>) {
+        let assertion = serde_json::json!({ "name": name, "passed": passed, "message": message });
+        let _ = self
+            .runtime
+            .eval(&format!("SAT.assertions.push({});", assertion));
+    }
+
+    // Returns the assertions recorded by `SAT.tester` during the last post test script.
+    pub fn get_assertions(&mut self) -> Vec<AssertionResult> {
+        self.runtime
+            .eval("JSON.stringify(SAT.assertions)")
+            .ok()
+            .and_then(|json| serde_json::from_str(json.as_str()?).ok())
+            .unwrap_or_default()
+    }
+
+    // Starts afresh the durations of the iterations, exposed as `SAT.iterationTimesMs`.
+    pub fn clear_iteration_times(&mut self) {
+        let _ = self.runtime.eval("SAT.iterationTimesMs = [];");
+    }
+
+    // Adds the duration of an iteration to `SAT.iterationTimesMs`, for the scripts to check
+    // the latencies across the repeats (ex: `SAT.expectP95Below(200)`).
+    pub fn record_iteration_time(&mut self, duration: std::time::Duration) {
+        let _ = self.runtime.eval(&format!(
+            "SAT.iterationTimesMs.push({});",
+            duration.as_secs_f64() * 1000.0
+        ));
+    }
+
+    // Sets `SAT.globals[name]` to the string, ex: for the `{{name}}` placeholders.
+    pub fn set_global(&mut self, name: &str, value: &str) {
+        let _ = self.runtime.eval(&format!(
+            "SAT.globals[{}] = {};",
+            Value::String(name.to_string()),
+            Value::String(value.to_string())
+        ));
+    }
+
+    // Stores the duration of the test case as `SAT.globals.timings[id]` (in ms), for the later
+    // test cases to compare the latencies (ex: a cached call is faster than the uncached one).
+    pub fn record_case_time(&mut self, id: u32, duration: std::time::Duration) {
+        let _ = self.runtime.eval(&format!(
+            "SAT.globals.timings = SAT.globals.timings || {{}}; SAT.globals.timings[{}] = {};",
+            id,
+            duration.as_secs_f64() * 1000.0
+        ));
+    }
+
+    // Exposes the repeat iteration to the placeholders, as {{iteration}} (1-based) and {{iteration0}}.
+    pub fn set_iteration(&mut self, iteration: u32) {
+        let _ = self.runtime.eval(&format!(
+            "SAT.globals.iteration = '{}'; SAT.globals.iteration0 = '{}';",
+            iteration + 1,
+            iteration
+        ));
+    }
+
+    // Calls the `SAT.onBeforeRequest` hook (if defined) with the request, and returns the
+    // request as changed in place by the hook, or the one returned by it.
+    pub fn before_request_hook(&mut self, request: &Value) -> Option<Value> {
+        let json = self
+            .runtime
+            .eval(&format!(
+                "(function(req) {{
+                    if (typeof SAT.onBeforeRequest !== 'function') return null;
+                    const result = SAT.onBeforeRequest(req);
+                    return JSON.stringify(result === undefined ? req : result);
+                }})({})",
+                request
+            ))
+            .ok()?;
+        serde_json::from_str(json.as_str()?).ok()
+    }
+
+    // Adds the cookies set with `SAT.setCookie` to the jar, for the given url.
+    fn add_pending_cookies(&mut self, url: &reqwest::Url) {
+        let cookies: Vec<Value> = self
+            .runtime
+            .eval("JSON.stringify(SAT.pendingCookies.splice(0))")
+            .ok()
+            .and_then(|json| serde_json::from_str(json.as_str()?).ok())
+            .unwrap_or_default();
+        for cookie in cookies {
+            if let (Some(name), Some(value)) = (cookie["name"].as_str(), cookie["value"].as_str()) {
+                self.cookie_jar
+                    .add_cookie_str(&format!("{}={}; Path=/", name, value), url);
+            }
+        }
+    }
+
+    // Makes the effective request available to the post test script, as `SAT.request`.
+    pub fn set_request(&mut self, request: &Value) {
+        let _ = self.runtime.eval(&format!("SAT.request = {};", request));
+    }
+
+    // Records the trace id injected into the last request, for the scripts.
+    pub fn set_last_trace_id(&mut self, trace_id: &str) {
+        let _ = self
+            .runtime
+            .eval(&format!("SAT.globals.lastTraceId = '{}';", trace_id));
+    }
+
+    pub fn get_response_sha256(&mut self) -> String {
+        self.runtime
+            .eval("SAT.response.sha256")
+            .unwrap_or_default()
+            .as_str()
+            .unwrap_or_default()
+            .to_owned()
+    }
+
     pub fn get_test_name(&mut self) -> String {
         self.runtime
             .eval("SAT.testName")
@@ -162,20 +634,31 @@ impl TestCtx {
             .to_owned()
     }
 
-    pub fn print_response_info(&mut self) {
+    pub fn print_response_info(&mut self, config: &Config) {
         println!("Response Info:");
         println!("\tStatus: {}", self.get_http_status());
+        println!(
+            "\tBody: {}",
+            self.printable_body(config.response_print_limit)
+        );
+    }
 
-        match serde_json::from_str::<Value>(&self.get_response_body()) {
-            Ok(json) => {
-                let pretty_json = serde_json::to_string_pretty(&json).unwrap_or_default();
-                let indented_json = pretty_json.replace("\n", "\n\t");
-                println!("\tBody: {}", indented_json);
-            }
-            Err(_) => {
-                println!("\tBody: {}", self.get_response_body());
-            }
+    // The response body as printed, pretty if it is json, and truncated to the given no. of
+    // lines. The scripts always see the full body.
+    fn printable_body(&mut self, limit: Option<usize>) -> String {
+        if let Ok(Value::String(body_base64)) = self.runtime.eval("SAT.response.bodyBase64") {
+            let len = STANDARD.decode(body_base64).map_or(0, |bytes| bytes.len());
+            return format!("<binary, {} bytes>", len);
         }
+        let body = self.get_response_body();
+        if body.is_empty() {
+            return String::from("<empty>");
+        }
+        let body = match serde_json::from_str::<Value>(&body) {
+            Ok(json) => serde_json::to_string_pretty(&json).unwrap_or_default(),
+            Err(_) => body,
+        };
+        truncate_lines(&body, limit).replace("\n", "\n\t")
     }
 
     pub fn exec_duration(&self) -> std::time::Duration {
@@ -183,6 +666,172 @@ impl TestCtx {
     }
 }
 
+// Keeps the first `limit` lines of the text (all of them, without a limit), and marks the
+// rest as truncated.
+pub fn truncate_lines(text: &str, limit: Option<usize>) -> String {
+    match limit {
+        Some(limit) if text.lines().count() > limit => {
+            let mut kept: Vec<&str> = text.lines().take(limit).collect();
+            kept.push("... (truncated)");
+            kept.join("\n")
+        }
+        _ => text.to_string(),
+    }
+}
+
+// Converts the response into the `SAT.response` object of the scripts, and returns it along
+// with the body as a string.
+fn sat_response(response: reqwest::blocking::Response) -> (Value, String) {
+    // Get the status and the negotiated protocol version (ex: HTTP/1.1, HTTP/2.0)
+    let status = response.status().as_u16();
+    let http_version = format!("{:?}", response.version());
+    let url = response.url().to_string();
+
+    // Response headers by their lowercase name, repeated headers are joined with ", "
+    let mut headers = serde_json::Map::new();
+    for (name, value) in response.headers() {
+        let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+        match headers.get_mut(name.as_str()) {
+            Some(Value::String(joined)) => *joined = format!("{}, {}", joined, value),
+            _ => {
+                headers.insert(name.to_string(), Value::String(value));
+            }
+        }
+    }
+
+    // Cookies set by the server, by their name: their value, and along with their attributes.
+    let set_cookies: serde_json::Map<String, Value> = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(parse_set_cookie)
+        .collect();
+    let cookies: serde_json::Map<String, Value> = set_cookies
+        .iter()
+        .map(|(name, cookie)| (name.clone(), cookie["value"].clone()))
+        .collect();
+
+    // The trace id echoed back by the server, in its `traceparent` header
+    let trace_id = headers
+        .get("traceparent")
+        .and_then(Value::as_str)
+        .and_then(|traceparent| traceparent.split('-').nth(1))
+        .map_or(Value::Null, |id| Value::String(id.to_string()));
+
+    // The compression applied by the server (ex: gzip), the body is passed as received.
+    let content_encoding = headers
+        .get("content-encoding")
+        .and_then(Value::as_str)
+        .map_or(Value::Null, |encoding| {
+            Value::String(encoding.to_lowercase())
+        });
+
+    // Get the raw body, its hash (for integrity checks) and the body as a string,
+    // decoded in the declared charset.
+    let content_type = headers
+        .get("content-type")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    // A binary body (ex: an image), which is not text in any charset, is passed as base64.
+    let (body, sha256, charset, charset_valid, body_base64) = match response.bytes() {
+        Ok(bytes) => {
+            let (body, charset, charset_valid) = decode_body(&bytes, content_type.as_deref());
+            let sha256 = format!("{:x}", Sha256::digest(&bytes));
+            match charset_valid || std::str::from_utf8(&bytes).is_ok() {
+                true => (body, sha256, charset, charset_valid, None),
+                false => {
+                    let body_base64 = STANDARD.encode(&bytes);
+                    (String::new(), sha256, charset, false, Some(body_base64))
+                }
+            }
+        }
+        Err(_) => (
+            String::from("Failed to read response body"),
+            String::new(),
+            String::new(),
+            false,
+            None,
+        ),
+    };
+
+    // Parse the body string as JSON. An empty body (ex: 204 No Content) has no json.
+    let body_json = match serde_json::from_str::<Value>(&body) {
+        _ if body.is_empty() => Value::Null,
+        Ok(json) => json,
+        Err(_) => Value::Null,
+    };
+
+    let sat_response = serde_json::json!({
+        "status": status,
+        "url": url,
+        "httpVersion": http_version,
+        "headers": headers,
+        // The blocking client drops the trailers of a chunked response, so there are none to
+        // pass. The key is kept, so that the scripts checking it don't break.
+        "trailers": {},
+        "cookies": cookies,
+        "setCookies": set_cookies,
+        "traceId": trace_id,
+        "contentEncoding": content_encoding,
+        "charset": charset,
+        "charsetValid": charset_valid,
+        "sha256": sha256,
+        "body": body,
+        "json": body_json,
+        "isBinary": body_base64.is_some(),
+        "bodyBase64": body_base64,
+    });
+    (sat_response, body)
+}
+
+// Parses a `Set-Cookie` header into its name, and its value along with its attributes, by their
+// lowercased name (ex: `{ value: "abc", attributes: { httponly: true, samesite: "Strict" } }`).
+// A flag (ex: `HttpOnly`) is `true`, the other attributes are strings.
+fn parse_set_cookie(header: &str) -> Option<(String, Value)> {
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let attributes: serde_json::Map<String, Value> = parts
+        .map(str::trim)
+        .filter(|attribute| !attribute.is_empty())
+        .map(|attribute| match attribute.split_once('=') {
+            Some((key, value)) => (
+                key.trim().to_lowercase(),
+                Value::String(value.trim().to_string()),
+            ),
+            None => (attribute.to_lowercase(), Value::Bool(true)),
+        })
+        .collect();
+    Some((
+        name.trim().to_string(),
+        serde_json::json!({ "value": value.trim(), "attributes": attributes }),
+    ))
+}
+
+// Decodes the body in the charset declared by the content type (utf-8 if none is declared).
+// Returns the body, the charset and whether the body is valid in that charset.
+fn decode_body(bytes: &[u8], content_type: Option<&str>) -> (String, String, bool) {
+    let charset = content_type
+        .and_then(|ct| {
+            ct.split(';').skip(1).find_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("charset")
+                    .then(|| value.trim().trim_matches('"').to_lowercase())
+            })
+        })
+        .unwrap_or_else(|| "utf-8".to_string());
+
+    match Encoding::for_label(charset.as_bytes()) {
+        Some(encoding) => {
+            let (body, had_errors) = encoding.decode_without_bom_handling(bytes);
+            (body.into_owned(), charset, !had_errors)
+        }
+        // An unknown charset can't be checked, so the body is flagged as invalid.
+        None => (String::from_utf8_lossy(bytes).to_string(), charset, false),
+    }
+}
+
 fn extract_token(body: &str, config: &Config) -> Option<String> {
     let json: Value = match serde_json::from_str(body) {
         Ok(json) => json,
@@ -211,11 +860,16 @@ fn extract_token(body: &str, config: &Config) -> Option<String> {
 mod tests {
     //use crate::test_context::TestCtx;
     use super::*;
-    use crate::config::Config;
+    use crate::config::{Config, EnvConfig};
+    use crate::test_utils::{mock_server, request_line_server, sequence_server};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::collections::HashMap;
+    use std::io::Write;
 
     #[test]
     fn test_new() {
-        let mut ts_ctx = TestCtx::new().unwrap();
+        let mut ts_ctx = TestCtx::new(&Config::default()).unwrap();
         let typeof_sat = ts_ctx
             .runtime
             .eval("console.log('type of SAT.tester is:', typeof SAT.tester); typeof SAT.tester")
@@ -226,7 +880,7 @@ mod tests {
     #[test]
     fn test_sat_test_for_true() {
         // Create a new TestCtx instance
-        let mut tctx = TestCtx::new().unwrap();
+        let mut tctx = TestCtx::new(&Config::default()).unwrap();
 
         // Create a mock function that returns true
         let mock_fn = "function() { return true; }";
@@ -244,7 +898,7 @@ mod tests {
     #[test]
     fn test_sat_test_non_boolean() {
         // Create a new TestCtx instance
-        let mut tctx = TestCtx::new().unwrap();
+        let mut tctx = TestCtx::new(&Config::default()).unwrap();
 
         // Create a mock function that returns a non-boolean value
         let mock_fn = "function() { return 'non-boolean'; }";
@@ -263,14 +917,8 @@ mod tests {
     fn test_flat_key_extraction() {
         let body = r#"{ "token": "abc123" }"#;
         let config = Config {
-            worksheet: None,
-            groups: None,
-            verbose: false,
-            start_row: None,
-            end_row: None,
-            test_file: None,
-            base_url: None,
             token_key: Some("token".to_string()),
+            ..Config::default()
         };
 
         let extracted_token = extract_token(body, &config);
@@ -281,14 +929,8 @@ mod tests {
     fn test_nested_key_extraction() {
         let body = r#"{ "token": { "access_token": "nested_token_value" } }"#;
         let config = Config {
-            worksheet: None,
-            groups: None,
-            verbose: false,
-            start_row: None,
-            end_row: None,
-            test_file: None,
-            base_url: None,
             token_key: Some("token.access_token".to_string()),
+            ..Config::default()
         };
 
         let extracted_token = extract_token(body, &config);
@@ -299,14 +941,8 @@ mod tests {
     fn test_key_not_found() {
         let body = r#"{ "token": { "access_token": "nested_token_value" } }"#;
         let config = Config {
-            worksheet: None,
-            groups: None,
-            verbose: false,
-            start_row: None,
-            end_row: None,
-            test_file: None,
-            base_url: None,
             token_key: Some("nonexistent.key".to_string()),
+            ..Config::default()
         };
 
         let extracted_token = extract_token(body, &config);
@@ -317,17 +953,861 @@ mod tests {
     fn test_empty_token_key() {
         let body = r#"{ "token": "abc123" }"#;
         let config = Config {
-            worksheet: None,
-            groups: None,
-            verbose: false,
-            start_row: None,
-            end_row: None,
-            test_file: None,
-            base_url: None,
             token_key: None,
+            ..Config::default()
         };
 
         let extracted_token = extract_token(body, &config);
         assert_eq!(extracted_token, None);
     }
+
+    #[test]
+    fn test_prelude_helper_in_post_test_script() {
+        let config = Config {
+            js_prelude_file: Some("prelude.js".to_string()),
+            js_prelude: Some(
+                "function assertPaginated(json) { return Array.isArray(json.items) && json.page >= 1; }"
+                    .to_string(),
+            ),
+            ..Config::default()
+        };
+        let mut tctx = TestCtx::new(&config).unwrap();
+        tctx.runtime
+            .eval(r#"SAT.response = { status: 200, json: { items: [1, 2], page: 1 } }"#)
+            .unwrap();
+
+        let post_test_script = "SAT.tester('paginated', () => assertPaginated(SAT.response.json))";
+        assert!(tctx.verify_result(Some(post_test_script)));
+    }
+
+    #[test]
+    fn test_prelude_with_errors() {
+        let config = Config {
+            js_prelude_file: Some("prelude.js".to_string()),
+            js_prelude: Some("function broken( {".to_string()),
+            ..Config::default()
+        };
+        let err = TestCtx::new(&config).unwrap_err();
+        assert!(err.to_string().contains("js_prelude_file 'prelude.js'"));
+    }
+
+    #[test]
+    fn test_token_key_per_environment() {
+        let body = r#"{ "token": "dev_token", "auth": { "access_token": "prod_token" } }"#;
+        let environments: HashMap<String, EnvConfig> = [
+            (
+                "dev".to_string(),
+                EnvConfig {
+                    token_key: Some("token".to_string()),
+                    ..EnvConfig::default()
+                },
+            ),
+            (
+                "prod".to_string(),
+                EnvConfig {
+                    token_key: Some("auth.access_token".to_string()),
+                    ..EnvConfig::default()
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut config = Config {
+            environments: Some(environments),
+            ..Config::default()
+        };
+
+        config.apply_env("dev").unwrap();
+        assert_eq!(extract_token(body, &config), Some("dev_token".to_string()));
+
+        config.apply_env("prod").unwrap();
+        assert_eq!(extract_token(body, &config), Some("prod_token".to_string()));
+
+        assert!(config.apply_env("staging").is_err());
+    }
+
+    #[test]
+    fn test_http_version_exposed() {
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 11\r\nConnection: close\r\n\r\n{\"ok\":true}",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert_eq!(
+            tctx.runtime.eval("SAT.response.httpVersion").unwrap(),
+            Value::String("HTTP/1.1".to_string())
+        );
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('http/1.1', () => SAT.response.httpVersion === 'HTTP/1.1')"
+        )));
+    }
+
+    #[test]
+    fn test_latin1_charset() {
+        let url = mock_server(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=ISO-8859-1\r\nContent-Length: 4\r\nConnection: close\r\n\r\ncaf\xe9",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert_eq!(
+            tctx.runtime.eval("SAT.response.charset").unwrap(),
+            Value::String("iso-8859-1".to_string())
+        );
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('latin-1', () => SAT.expectCharset('ISO-8859-1') && SAT.response.body === 'café')"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('utf-8', () => SAT.expectCharset('utf-8'))"
+        )));
+    }
+
+    #[test]
+    fn test_body_not_decodable_in_declared_charset() {
+        let url = mock_server(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 4\r\nConnection: close\r\n\r\ncaf\xe9",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert_eq!(
+            tctx.runtime.eval("SAT.response.charsetValid").unwrap(),
+            Value::Bool(false)
+        );
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('utf-8', () => SAT.expectCharset('utf-8'))"
+        )));
+    }
+
+    #[test]
+    fn test_binary_body() {
+        let url = mock_server(
+            b"HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 10\r\nConnection: close\r\n\r\n\x89PNG\r\n\x1a\n\xff\xfe",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert_eq!(
+            tctx.runtime.eval("SAT.response.isBinary").unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(tctx.get_response_body(), "");
+        let body_base64 = tctx.runtime.eval("SAT.response.bodyBase64").unwrap();
+        assert_eq!(
+            STANDARD.decode(body_base64.as_str().unwrap()).unwrap(),
+            b"\x89PNG\r\n\x1a\n\xff\xfe"
+        );
+        assert_eq!(tctx.printable_body(None), "<binary, 10 bytes>");
+    }
+
+    // A context whose response is the given body, with the given extra headers.
+    fn response_ctx(extra_headers: &str, body: &[u8]) -> TestCtx {
+        let mut raw = format!(
+            "HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+            extra_headers,
+            body.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(body);
+        response_ctx_raw(raw)
+    }
+
+    // A context whose response is the given raw http response.
+    fn response_ctx_raw<T: AsRef<[u8]> + Send + 'static>(raw: T) -> TestCtx {
+        let url = mock_server(raw);
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+        tctx
+    }
+
+    #[test]
+    fn test_expect_compressed() {
+        let body = "{\"users\":[]}".repeat(20);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let mut tctx = response_ctx(
+            "Content-Type: application/json\r\nContent-Encoding: GZIP\r\n",
+            &gzipped,
+        );
+        assert_eq!(
+            tctx.runtime.eval("SAT.response.contentEncoding").unwrap(),
+            Value::String("gzip".to_string())
+        );
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('compressed', () => SAT.expectCompressed())"
+        )));
+
+        let mut tctx = response_ctx("Content-Type: application/json\r\n", body.as_bytes());
+        assert_eq!(
+            tctx.runtime.eval("SAT.response.contentEncoding").unwrap(),
+            Value::Null
+        );
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('compressed', () => SAT.expectCompressed())"
+        )));
+    }
+
+    #[test]
+    fn test_chunked_response_with_trailers() {
+        let mut tctx = response_ctx_raw(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\nTrailer: Grpc-Status\r\nConnection: close\r\n\r\nb\r\n{\"ok\":true}\r\n0\r\nGrpc-Status: 0\r\n\r\n",
+        );
+        assert_eq!(tctx.get_http_status(), 200);
+        assert_eq!(tctx.get_response_body(), "{\"ok\":true}");
+        assert_eq!(
+            tctx.runtime
+                .eval("JSON.stringify(SAT.response.trailers)")
+                .unwrap(),
+            Value::String("{}".to_string())
+        );
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('no trailer', () => SAT.response.trailers['grpc-status'] === undefined)"
+        )));
+    }
+
+    // A context whose response is a list of users.
+    fn users_ctx() -> TestCtx {
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 55\r\nConnection: close\r\n\r\n{\"users\":[{\"id\":1,\"name\":\"ann\"},{\"id\":2,\"name\":\"bob\"}]}",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+        tctx
+    }
+
+    #[test]
+    fn test_expect_array_length() {
+        let mut tctx = users_ctx();
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('two users', () => SAT.expectArrayLength('$.users', 2))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('three users', () => SAT.expectArrayLength('$.users', 3))"
+        )));
+    }
+
+    #[test]
+    fn test_expect_status_class() {
+        let url =
+            mock_server("HTTP/1.1 201 Created\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.post(&url);
+        tctx.exec(request, None, &config);
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('created', () => SAT.expectStatusClass('2xx'))"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('success', () => SAT.expectStatusClass(2))"
+        )));
+
+        let url =
+            mock_server("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('success', () => SAT.expectStatusClass('2xx'))"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('client error', () => SAT.expectStatusClass('4XX'))"
+        )));
+    }
+
+    #[test]
+    fn test_title_case_headers_in_order() {
+        let send = |config: &Config| {
+            let (url, heads) = sequence_server(vec![
+                "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            ]);
+            let mut tctx = TestCtx::new(config).unwrap();
+            let request = tctx
+                .client
+                .get(&url)
+                .header("X-Custom-Header", "abc")
+                .header("x-second", "def");
+            tctx.exec(request, None, config);
+            heads.recv().unwrap()
+        };
+
+        let head = send(&Config::default());
+        assert!(head.contains("x-custom-header: abc"));
+
+        let head = send(&Config {
+            http1_title_case_headers: true,
+            ..Config::default()
+        });
+        let custom = head.find("X-Custom-Header: abc").unwrap();
+        let second = head.find("X-Second: def").unwrap();
+        assert!(custom < second);
+    }
+
+    #[test]
+    fn test_local_address() {
+        let send = |config: &Config| {
+            let (url, _requests) = request_line_server(1);
+            let mut tctx = TestCtx::new(config).unwrap();
+            let request = tctx.client.get(&url);
+            tctx.exec(request, None, config);
+            tctx.get_http_status()
+        };
+
+        let config: Config = serde_yaml::from_str("local_address: 127.0.0.1").unwrap();
+        assert_eq!(config.local_address, Some("127.0.0.1".parse().unwrap()));
+        assert_eq!(send(&config), 200);
+
+        // An address which is not of this host can't be bound, so the request is not sent.
+        if cfg!(target_os = "linux") {
+            let config = Config {
+                local_address: Some("192.0.2.1".parse().unwrap()),
+                ..Config::default()
+            };
+            assert_eq!(send(&config), 0);
+        }
+    }
+
+    #[test]
+    fn test_pool_settings() {
+        let config: Config =
+            serde_yaml::from_str("pool_max_idle_per_host: 4\npool_idle_timeout_secs: 30").unwrap();
+        assert_eq!(config.pool_max_idle_per_host, Some(4));
+        assert_eq!(config.pool_idle_timeout_secs, Some(30));
+
+        // The client is built with the settings, here without keeping any idle connection.
+        let config = Config {
+            pool_max_idle_per_host: Some(0),
+            ..config
+        };
+        let (url, requests) = request_line_server(2);
+        let mut tctx = TestCtx::new(&config).unwrap();
+        for _ in 0..2 {
+            let request = tctx.client.get(&url);
+            tctx.exec(request, None, &config);
+            assert_eq!(tctx.get_http_status(), 200);
+        }
+        assert_eq!(requests.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_expect_redirect_to() {
+        let redirect = |location: &str| {
+            let config = Config {
+                max_redirects: Some(0),
+                ..Config::default()
+            };
+            let url = mock_server(format!(
+                "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                location
+            ));
+            let mut tctx = TestCtx::new(&config).unwrap();
+            let request = tctx.client.get(format!("{}/account/settings", url));
+            tctx.exec(request, None, &config);
+            (tctx, url)
+        };
+
+        // The 302 itself is the response, with a relative location.
+        let (mut tctx, url) = redirect("/login?next=settings");
+        assert_eq!(tctx.get_http_status(), 302);
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('to login', () => SAT.expectRedirectTo('/login?next=settings'))"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('suffix', () => SAT.expectRedirectTo('?next=settings'))"
+        )));
+        let script = format!(
+            "SAT.tester('absolute', () => SAT.expectRedirectTo('{}/login?next=settings'))",
+            url
+        );
+        assert!(tctx.verify_result(Some(&script)));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('to home', () => SAT.expectRedirectTo('/home'))"
+        )));
+
+        // A location relative to the path of the request.
+        let (mut tctx, _) = redirect("profile");
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('sibling', () => SAT.expectRedirectTo('/account/profile'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('not root', () => SAT.expectRedirectTo('http://other/profile'))"
+        )));
+    }
+
+    #[test]
+    fn test_soft_error_detector() {
+        let config = Config {
+            soft_error_detector: Some(
+                "SAT.response.json && 'error' in SAT.response.json".to_string(),
+            ),
+            ..Config::default()
+        };
+        let flagged = |body: &'static str| {
+            let url = mock_server(format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            ));
+            let mut tctx = TestCtx::new(&config).unwrap();
+            let request = tctx.client.get(&url);
+            tctx.exec(request, None, &config);
+            tctx.eval_json("SAT.response.isSoftError")
+        };
+
+        assert_eq!(flagged(r#"{"error":"not found"}"#), Value::Bool(true));
+        assert_eq!(flagged(r#"{"id":7}"#), Value::Bool(false));
+
+        // A broken detector flags nothing.
+        let config = Config {
+            soft_error_detector: Some("SAT.response.json.(".to_string()),
+            ..Config::default()
+        };
+        let url = mock_server("HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+        assert_eq!(
+            tctx.eval_json("SAT.response.isSoftError"),
+            Value::Bool(false)
+        );
+    }
+
+    #[test]
+    fn test_expect_header() {
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nX-RateLimit-Remaining: 42\r\nCache-Control: no-store\r\nETag: \"v1\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('rate limit', () => SAT.expectHeader('X-RateLimit-Remaining').exists())"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('retry', () => SAT.expectHeader('Retry-After').exists())"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('no cache', () => SAT.expectHeader('Cache-Control').equals('no-store'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('cache', () => SAT.expectHeader('Cache-Control').equals('no-cache'))"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('etag', () => SAT.expectHeader('ETag').matches(/\".+\"/))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('weak etag', () => SAT.expectHeader('ETag').matches('^W/'))"
+        )));
+    }
+
+    #[test]
+    fn test_expect_every() {
+        let mut tctx = users_ctx();
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('ids', () => SAT.expectEvery('$.users', (user) => user.id > 0))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('names', () => SAT.expectEvery('$.users', (user) => user.name === 'ann'))"
+        )));
+    }
+
+    #[test]
+    fn test_expect_sorted_by() {
+        let body = r#"{"items":[{"id":1,"name":"ann","at":"2024-01-05T10:00:00Z"},{"id":5,"name":"bob","at":"2024-02-01"},{"id":12,"name":"carl","at":"2024-11-30T08:00:00+05:30"}]}"#;
+        let url = mock_server(format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        // Ascending, with numbers compared as numbers (not as "1" < "12" < "5").
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('by id', () => SAT.expectSortedBy('$.items', 'id', 'asc'))"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('by date', () => SAT.expectSortedBy('$.items', 'at'))"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('by name', () => SAT.expectSortedBy('$.items', 'name', 'asc'))"
+        )));
+
+        // Descending, on the reversed list.
+        assert!(tctx.verify_result(Some(
+            "SAT.response.json.items.reverse(); SAT.tester('desc', () => SAT.expectSortedBy('$.items', 'id', 'desc'))"
+        )));
+
+        // Out of order.
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('not asc', () => SAT.expectSortedBy('$.items', 'at', 'asc'))"
+        )));
+    }
+
+    #[test]
+    fn test_expect_contains_object() {
+        let mut tctx = users_ctx();
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('bob', () => SAT.expectContainsObject('$.users', { id: 2, name: 'bob' }))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('carl', () => SAT.expectContainsObject('$.users', { name: 'carl' }))"
+        )));
+    }
+
+    #[test]
+    fn test_no_content_response() {
+        let url = mock_server("HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.delete(&url);
+        tctx.exec(request, None, &config);
+
+        assert_eq!(tctx.runtime.eval("SAT.response.json").unwrap(), Value::Null);
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('no content', () => SAT.expectNoContent())"
+        )));
+        tctx.print_response_info(&config);
+    }
+
+    #[test]
+    fn test_printed_body_truncated() {
+        let mut tctx = users_ctx();
+        assert!(!tctx.printable_body(None).contains("(truncated)"));
+
+        let printed = tctx.printable_body(Some(3));
+        assert_eq!(printed.lines().count(), 4);
+        assert!(printed.ends_with("... (truncated)"));
+
+        // The scripts still see the full body.
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('two users', () => SAT.expectArrayLength('$.users', 2))"
+        )));
+    }
+
+    #[test]
+    fn test_no_content_fails_for_a_body() {
+        let mut tctx = users_ctx();
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('no content', () => SAT.expectNoContent())"
+        )));
+    }
+
+    #[test]
+    fn test_json_checks_of_a_text_response() {
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('items', () => SAT.expectArrayLength('$.items', 2));
+            SAT.tester('id', () => SAT.response.json.id === 1)"
+        )));
+        let messages: Vec<Option<String>> = tctx
+            .get_assertions()
+            .into_iter()
+            .map(|a| a.message)
+            .collect();
+        assert_eq!(
+            messages[0].as_deref(),
+            Some("Error: response body is not JSON")
+        );
+        let message = messages[1].as_deref().unwrap_or_default();
+        assert!(
+            message.starts_with("response body is not JSON (TypeError"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_cookie_attributes() {
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nSet-Cookie: session=abc123; Path=/; HttpOnly; Secure; SameSite=Strict; Max-Age=3600\r\nSet-Cookie: theme=dark; SameSite=Lax\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert_eq!(
+            tctx.eval_json("SAT.response.setCookies.session"),
+            serde_json::json!({
+                "value": "abc123",
+                "attributes": { "path": "/", "httponly": true, "secure": true, "samesite": "Strict", "max-age": "3600" },
+            })
+        );
+        // The values are still available by name.
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('value', () => SAT.response.cookies.theme === 'dark')"
+        )));
+
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('http only', () => SAT.expectCookieAttr('session', 'HttpOnly'))
+            && SAT.tester('secure', () => SAT.expectCookie('session').secure())
+            && SAT.tester('strict', () => SAT.expectCookie('session').sameSite('strict'))
+            && SAT.tester('max age', () => SAT.expectCookieAttr('session', 'Max-Age', 3600))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('http only', () => SAT.expectCookie('theme').httpOnly())"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('strict', () => SAT.expectCookie('theme').sameSite('Strict'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('missing', () => SAT.expectCookie('token').exists())"
+        )));
+    }
+
+    #[test]
+    fn test_cookies_from_login_are_sent() {
+        let (url, rx) = sequence_server(vec![
+            "HTTP/1.1 200 OK\r\nSet-Cookie: session=abc123; Path=/; HttpOnly\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+        ]);
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+
+        let request = tctx.client.post(format!("{}/login", url));
+        tctx.exec(request, None, &config);
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('session', () => SAT.response.cookies.session === 'abc123')"
+        )));
+
+        tctx.runtime.eval("SAT.setCookie('theme', 'dark')").unwrap();
+        let request = tctx.client.get(format!("{}/profile", url));
+        tctx.exec(request, None, &config);
+
+        let _login = rx.recv().unwrap();
+        let profile = rx.recv().unwrap().to_lowercase();
+        let cookie = profile
+            .lines()
+            .find_map(|line| line.strip_prefix("cookie:"))
+            .unwrap();
+        assert!(cookie.contains("session=abc123"));
+        assert!(cookie.contains("theme=dark"));
+    }
+
+    #[test]
+    fn test_after_response_hook() {
+        let url = mock_server("HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+        let config = Config {
+            js_prelude: Some(
+                "SAT.onAfterResponse = (res) => { SAT.globals.lastStatus = String(res.status); };"
+                    .to_string(),
+            ),
+            ..Config::default()
+        };
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert_eq!(
+            tctx.runtime.eval("SAT.globals.lastStatus").unwrap(),
+            Value::String("204".to_string())
+        );
+    }
+
+    #[test]
+    fn test_deep_equal_within_tolerance() {
+        let config = Config {
+            number_tolerance: 0.000001,
+            ..Config::default()
+        };
+        let mut tctx = TestCtx::new(&config).unwrap();
+
+        let within = tctx
+            .runtime
+            .eval("SAT.deepEqual({ price: 1.0000001, tags: [2.5] }, { price: 1.0, tags: [2.5] })")
+            .unwrap();
+        assert_eq!(within, Value::Bool(true));
+
+        let beyond = tctx
+            .runtime
+            .eval("SAT.deepEqual({ price: 1.1, tags: [2.5] }, { price: 1.0, tags: [2.5] })")
+            .unwrap();
+        assert_eq!(beyond, Value::Bool(false));
+    }
+
+    #[test]
+    fn test_deep_equal_exact_by_default() {
+        let mut tctx = TestCtx::new(&Config::default()).unwrap();
+        let result = tctx.runtime.eval("SAT.deepEqual(1.0000001, 1.0)").unwrap();
+        assert_eq!(result, Value::Bool(false));
+    }
+
+    #[test]
+    fn test_expect_valid_json() {
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"users\": [1, 2]}",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('valid json', () => SAT.expectValidJson())"
+        )));
+    }
+
+    #[test]
+    fn test_expect_valid_json_for_html() {
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 21\r\nConnection: close\r\n\r\n<html>It works</html>",
+        );
+        let config = Config::default();
+        let mut tctx = TestCtx::new(&config).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('valid json', () => SAT.expectValidJson())"
+        )));
+    }
+
+    #[test]
+    fn test_expect_timestamps() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let body = format!(
+            r#"{{"createdAt":{},"expiresAt":{},"updatedAt":"2001-01-01T00:00:00Z","due":"2024-02-30","at":"soon"}}"#,
+            now - 10,
+            now + 3600
+        );
+        let mut tctx = response_ctx("Content-Type: application/json\r\n", body.as_bytes());
+
+        // A recent (epoch seconds), a stale (ISO) and an invalid timestamp.
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('recent', () => SAT.expectRecentTimestamp('$.createdAt', 60))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('stale', () => SAT.expectRecentTimestamp('$.updatedAt', 60))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('invalid', () => SAT.expectRecentTimestamp('$.at', 60))"
+        )));
+
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('future', () => SAT.expectFutureTimestamp('$.expiresAt'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('past', () => SAT.expectFutureTimestamp('$.createdAt'))"
+        )));
+
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('rfc3339', () => SAT.expectValidDate('$.updatedAt', 'rfc3339'))"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('epoch', () => SAT.expectValidDate('$.createdAt', 'epoch'))"
+        )));
+        // Feb 30 is not rolled over to Mar 1.
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('feb 30', () => SAT.expectValidDate('$.due', 'date'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('text', () => SAT.expectValidDate('$.at'))"
+        )));
+    }
+
+    #[test]
+    fn test_expect_type_and_range() {
+        let body =
+            br#"{"id":7,"price":12.5,"name":"pen","tags":[],"meta":{},"active":true,"code":"12"}"#;
+        let mut tctx = response_ctx("Content-Type: application/json\r\n", body);
+
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('in range', () => SAT.expectNumberInRange('$.price', 0, 1000))"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('bounds', () => SAT.expectNumberInRange('$.id', 7, 7))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('out of range', () => SAT.expectNumberInRange('$.price', 0, 10))"
+        )));
+        // A numeric string is not a number.
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('string', () => SAT.expectNumberInRange('$.code', 0, 100))"
+        )));
+
+        for (path, r#type) in [
+            ("$.id", "integer"),
+            ("$.price", "number"),
+            ("$.name", "string"),
+            ("$.tags", "array"),
+            ("$.meta", "object"),
+            ("$.active", "boolean"),
+        ] {
+            let script = format!(
+                "SAT.tester('type', () => SAT.expectType('{}', '{}'))",
+                path, r#type
+            );
+            assert!(
+                tctx.verify_result(Some(&script)),
+                "{} is not {}",
+                path,
+                r#type
+            );
+        }
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('not an integer', () => SAT.expectType('$.price', 'integer'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('not an object', () => SAT.expectType('$.tags', 'object'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('missing', () => SAT.expectType('$.nope', 'string'))"
+        )));
+    }
+
+    #[test]
+    fn test_expect_fields_related() {
+        let body = br#"{"createdAt":"2024-03-01T10:00:00Z","updatedAt":"2024-03-01T12:30:00+02:00","closedAt":"2024-02-28","total":30.5,"items":[{"price":10},{"price":20.5}],"count":"9"}"#;
+        let mut tctx = response_ctx("Content-Type: application/json\r\n", body);
+
+        // 12:30 at +02:00 is 10:30 UTC, after the creation.
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('created first', () => SAT.expectFieldsRelated('$.createdAt', '<=', '$.updatedAt'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('closed after', () => SAT.expectFieldsRelated('$.createdAt', '<=', '$.closedAt'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('missing', () => SAT.expectFieldsRelated('$.createdAt', '<=', '$.deletedAt'))"
+        )));
+
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('total', () => SAT.expectFieldsRelated('$.total', '==',
+                SAT.jsonPath('$.items').reduce((sum, item) => sum + item.price, 0)))"
+        )));
+        assert!(tctx.verify_result(Some(
+            "SAT.tester('more', () => SAT.expectFieldsRelated('$.total', '>', '$.items[1].price'))"
+        )));
+        assert!(!tctx.verify_result(Some(
+            "SAT.tester('less', () => SAT.expectFieldsRelated('$.total', '<', '$.items[0].price'))"
+        )));
+    }
 }
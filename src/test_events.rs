@@ -5,6 +5,8 @@
 // test event is fired.  When a test ends, a test event
 // is fired.  When a test fails, a test event is fired.
 //
+use crate::test_case::TestResult;
+use serde::{Deserialize, Serialize};
 use std::time::Instant;
 
 #[derive(Debug)]
@@ -35,6 +37,8 @@ pub struct TestGroupEnd {
     pub iteration_id: String,
     pub group_name: String,
     pub exec_duration: std::time::Duration,
+    pub globals: Option<serde_json::Value>, // Final SAT.globals, redacted, with `report_globals`.
+    pub tokens: Vec<String>,                // Names of the tokens captured by the authorizers.
 }
 
 #[derive(Debug)]
@@ -52,10 +56,21 @@ pub struct TestCaseBegin {
     pub payload: String,
     pub pre_test_script: Option<String>,
     pub post_test_script: Option<String>,
+    pub notes: Option<String>,
     //pub is_authorizer: bool,
     //pub is_authorized: bool,
 }
 
+// Outcome of a single `SAT.tester` assertion within a post test script.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssertionResult {
+    pub name: String,
+    pub passed: bool,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub warning: bool, // A `SAT.warn` assertion, which does not fail the test case.
+}
+
 #[derive(Debug)]
 pub struct TestCaseEnd {
     pub timestamp: Instant,
@@ -63,8 +78,32 @@ pub struct TestCaseEnd {
     pub testcase_id: u32,
     pub exec_duration: std::time::Duration,
     pub status: i64,
+    pub result: TestResult,
+    pub assertions: Vec<AssertionResult>,
     pub response: String,
     pub response_json: Option<serde_json::Value>,
+    pub errors: Vec<(String, String)>, // Field errors of an invalid row, which is skipped.
+    pub effective_payload: String, // Payload of the request, after the placeholders and the hook.
+}
+
+#[cfg(test)]
+impl TestCaseEnd {
+    // An end event of the test case with the result, to feed the listeners in the tests.
+    pub fn new(testcase_id: u32, result: TestResult) -> Self {
+        TestCaseEnd {
+            timestamp: Instant::now(),
+            iteration_id: "1".to_string(),
+            testcase_id,
+            exec_duration: std::time::Duration::from_secs(0),
+            status: 0,
+            result,
+            assertions: vec![],
+            response: "".to_string(),
+            response_json: None,
+            errors: vec![],
+            effective_payload: "".to_string(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -75,4 +114,6 @@ pub enum TestEvent {
     EvtTestGroupEnd(TestGroupEnd),
     EvtTestCaseBegin(TestCaseBegin),
     EvtTestCaseEnd(TestCaseEnd),
+    // Fired once the test case is over (after all its iterations), with its overall result.
+    EvtTestCaseResult(TestCaseEnd),
 }
@@ -4,41 +4,57 @@
 */
 
 use crate::config::Config;
+use crate::data_table::DataTables;
+use crate::redact;
+use crate::step::{self, StepAction};
 use crate::test_case::{TestCase, TestResult};
 use crate::test_context::TestCtx;
 use crate::test_events::{TestEvent, TestGroupBegin, TestGroupEnd};
+use crate::transport::Transport;
 use std::error::Error;
+use std::io::{self, IsTerminal};
+use std::path::Path;
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
 use std::time::Instant;
 
 #[derive(Debug)]
 pub struct TestGroup {
     pub name: String,
-    test_cases: Vec<TestCase>,
     group_ctx: TestCtx,
+    pub parallel_cases: Option<usize>, // Max. cases in flight, with the `parallel_cases` directive.
+    pub stopped: bool,                 // The run was stopped in step mode.
 
     // stats
     pub total: usize,
     pub passed: usize,
     pub failed: usize,
     pub skipped: usize,
+    pub warnings: usize,
     pub exec_duration: std::time::Duration,
 }
 
 impl TestGroup {
-    pub fn new(group_name: &str, tx: &Sender<TestEvent>) -> Self {
+    pub fn new(
+        group_name: &str,
+        config: &Config,
+        transport: &Arc<Transport>,
+        tx: &Sender<TestEvent>,
+    ) -> Result<Self, Box<dyn Error>> {
         let tg = TestGroup {
             name: group_name.to_string(),
-            test_cases: vec![],
-            group_ctx: TestCtx::new().unwrap(),
+            group_ctx: TestCtx::with_transport(config, transport.clone())?,
+            parallel_cases: None,
+            stopped: false,
             total: 0,
             passed: 0,
             failed: 0,
             skipped: 0,
+            warnings: 0,
             exec_duration: std::time::Duration::new(0, 0),
         };
         tg.fire_start_evt(tx);
-        tg
+        Ok(tg)
     }
 
     pub fn name(&self) -> &str {
@@ -48,8 +64,8 @@ impl TestGroup {
     pub fn print_stats(&self) {
         println!("");
         println!(
-            "Group Summary: {{ Name: {}, Total: {}, Passed: {}, Failed: {}, Skipped: {} }}",
-            self.name, self.total, self.passed, self.failed, self.skipped
+            "Group Summary: {{ Name: {}, Total: {}, Passed: {}, Failed: {}, Skipped: {}, Warnings: {} }}",
+            self.name, self.total, self.passed, self.failed, self.skipped, self.warnings
         );
         println!("{}", "-".repeat(80));
         println!("");
@@ -67,9 +83,85 @@ impl TestGroup {
         // Create an instance of test case, and execute it.
         let mut tc = TestCase::new(row, config);
         let t_result = tc.run(&mut self.group_ctx, config, tx);
-        self.test_cases.push(tc);
 
-        // update group counts
+        // update the exec duration..
+        self.exec_duration += self.group_ctx.exec_duration();
+        self.tally(&tc, &t_result);
+
+        // In step mode, wait for the user before the next test case.
+        if config.step && io::stdin().is_terminal() {
+            let action = step::prompt(
+                &mut io::stdin().lock(),
+                &mut io::stdout(),
+                &mut self.group_ctx,
+            );
+            if action == StepAction::Quit {
+                self.stopped = true;
+                return Err("Stopped in step mode".into());
+            }
+        }
+        outcome(t_result)
+    }
+
+    // Executes the test cases of the rows concurrently, with at most `max_in_flight` requests
+    // in flight. The authorizers, and the cases which repeat or poll, are run one after the
+    // other first. Only the sends are concurrent, each case is prepared and verified on this
+    // thread, as the runtime is not thread safe. Returns the result of each row, in order.
+    pub fn exec_parallel(
+        &mut self,
+        rows: &[&[calamine::Data]],
+        max_in_flight: usize,
+        config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> Vec<TestResult> {
+        let mut cases: Vec<TestCase> = rows.iter().map(|row| TestCase::new(row, config)).collect();
+        let mut results: Vec<_> = cases.iter().map(|_| None).collect();
+
+        for (tc, result) in cases.iter_mut().zip(results.iter_mut()) {
+            if !tc.is_parallelizable() {
+                let t_result = tc.run(&mut self.group_ctx, config, tx);
+                self.exec_duration += self.group_ctx.exec_duration();
+                self.tally(tc, &t_result);
+                *result = Some(t_result);
+            }
+        }
+
+        // Prepare the requests, and remember the case each of them belongs to.
+        let mut sent = vec![];
+        let mut requests = vec![];
+        for (index, tc) in cases.iter_mut().enumerate() {
+            if results[index].is_some() {
+                continue;
+            }
+            match tc.start_parallel(&mut self.group_ctx, config, tx) {
+                Some(request) => {
+                    sent.push(index);
+                    requests.push(request);
+                }
+                None => {
+                    self.tally(tc, &TestResult::Skipped);
+                    results[index] = Some(TestResult::Skipped);
+                }
+            }
+        }
+
+        let mut finished = vec![];
+        self.group_ctx
+            .send_each(requests, max_in_flight, |ctx, i, response, _| {
+                let tc = &mut cases[sent[i]];
+                finished.push((sent[i], tc.finish_parallel(ctx, response, config, tx)));
+            });
+        self.exec_duration += self.group_ctx.exec_duration();
+        for (index, t_result) in finished {
+            self.tally(&cases[index], &t_result);
+            results[index] = Some(t_result);
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    // Updates the group counts with the result of the test case.
+    fn tally(&mut self, tc: &TestCase, t_result: &TestResult) {
+        self.warnings += tc.warnings;
         self.total += 1;
         match t_result {
             TestResult::Passed => self.passed += 1,
@@ -77,15 +169,29 @@ impl TestGroup {
             TestResult::Skipped => self.skipped += 1,
             _ => {}
         }
-        // update the exec duration..
-        self.exec_duration += self.group_ctx.exec_duration();
-        //Ok(t_result)
-        match t_result {
-            TestResult::Passed => Ok(TestResult::Passed),
-            //TestResult::Failed => Err("Test Failed".into()),
-            TestResult::Skipped => Err("Test Skipped".into()),
-            _ => Err("Test Failed".into()),
-        }
+    }
+
+    pub fn set_run_id(&mut self, run_id: &str) {
+        self.group_ctx.set_global("runId", run_id);
+        self.group_ctx.run_id = Some(run_id.to_string());
+    }
+
+    pub fn set_base_dir(&mut self, base_dir: &Path) {
+        self.group_ctx.base_dir = base_dir.to_path_buf();
+    }
+
+    // The workbook the data tables of the `{{table:...}}` placeholders are read from.
+    pub fn set_test_file(&mut self, test_file: &Path) {
+        self.group_ctx.data_tables = DataTables::new(Some(test_file));
+    }
+
+    // Records the test case of the row as skipped, without executing it.
+    pub fn skip(&mut self, row: &[calamine::Data], config: &Config, tx: &Sender<TestEvent>) {
+        let mut tc = TestCase::new(row, config);
+        tc.skip(&mut self.group_ctx, config, tx);
+
+        self.total += 1;
+        self.skipped += 1;
     }
 
     fn fire_start_evt(&self, tx: &Sender<TestEvent>) {
@@ -93,8 +199,8 @@ impl TestGroup {
             .unwrap();
     }
 
-    pub fn fire_end_evt(&self, tx: &Sender<TestEvent>) {
-        tx.send(TestEvent::EvtTestGroupEnd(self.get_end_evt_data()))
+    pub fn fire_end_evt(&mut self, config: &Config, tx: &Sender<TestEvent>) {
+        tx.send(TestEvent::EvtTestGroupEnd(self.get_end_evt_data(config)))
             .unwrap();
     }
 
@@ -108,12 +214,33 @@ impl TestGroup {
     }
 
     // Returns TestGroup's event data for end event.
-    pub fn get_end_evt_data(&self) -> TestGroupEnd {
+    // The globals are read from the runtime of the group, which is dropped right after.
+    pub fn get_end_evt_data(&mut self, config: &Config) -> TestGroupEnd {
+        let globals = config.report_globals.then(|| {
+            let mut globals = self.group_ctx.eval_json("SAT.globals");
+            redact::redact(&mut globals, &redact::keys(config));
+            globals
+        });
+        let mut tokens: Vec<String> = self.group_ctx.tokens.keys().cloned().collect();
+        tokens.sort();
         TestGroupEnd {
             timestamp: Instant::now(),
             exec_duration: self.exec_duration,
             iteration_id: "1".to_string(),
             group_name: self.name.clone(),
+            globals,
+            tokens,
         }
     }
 }
+
+// Maps the result of a test case to the outcome of its row, where anything but a pass is an error.
+pub fn outcome(t_result: TestResult) -> Result<TestResult, Box<dyn Error>> {
+    //Ok(t_result)
+    match t_result {
+        TestResult::Passed => Ok(TestResult::Passed),
+        //TestResult::Failed => Err("Test Failed".into()),
+        TestResult::Skipped => Err("Test Skipped".into()),
+        _ => Err("Test Failed".into()),
+    }
+}
@@ -1,69 +1,201 @@
 use crate::config::Config;
-use crate::test_case::TestResult;
+use crate::reruns::Reruns;
+use crate::test_case::{resolve_templates, TestCase, TestResult};
 use crate::test_events::TestEvent;
 use crate::test_events::{TestSuiteBegin, TestSuiteEnd};
-use crate::test_group::TestGroup;
+use crate::test_group::{self, TestGroup};
+use crate::transport::Transport;
 use anyhow::Result;
 use calamine::DataType;
 use calamine::Reader;
 use calamine::Xlsx;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
 use std::time::Instant;
 use std::{
     error::Error,
     io::{Read, Seek},
 };
+use uuid::Uuid;
+
+// Name of the group which is run at the end, to clean up the resources created by the tests.
+const CLEANUP_GROUP: &str = "Cleanup";
 
 pub struct TestSuite {
-    test_groups: Vec<TestGroup>,
     total: usize,
     passed: usize,
     failed: usize,
     skipped: usize,
+    warnings: usize,
     exec_duration: std::time::Duration, // Total duration for test suite execution
-}
-
-impl Drop for TestSuite {
-    fn drop(&mut self) {
-        while let Some(_test_group) = self.test_groups.pop() {
-            //test_group.drop_js_engine();
-            //println!("Dropping test group: {}", test_group.name());
-        }
-    }
+    reruns: Reruns,                     // Rows of the failed test cases, for `--write-failed`.
+    seen_groups: HashSet<(String, String)>, // (worksheet, group) of every group row scanned.
+    transport: Arc<Transport>,          // Shared by the groups, to record / replay across them.
+    case_found: bool,                   // The `--case` test case was found in a group.
+    run_id: String,                     // Fresh id of the run, unless one is given with `--run-id`.
+    base_dir: PathBuf, // The relative paths of the test cases are resolved against it.
+    shuffle_seed: u64, // Random seed of `--shuffle`, unless one is given with `--seed`.
+    test_file: Option<PathBuf>, // The workbook of the suite, if read from one.
 }
 
 impl TestSuite {
     pub fn new() -> Self {
         // Initialize the test suite object and return.
         TestSuite {
-            test_groups: vec![],
             total: 0,
             passed: 0,
             failed: 0,
             skipped: 0,
+            warnings: 0,
             exec_duration: std::time::Duration::new(0, 0),
+            reruns: Reruns::default(),
+            seen_groups: HashSet::new(),
+            transport: Arc::default(),
+            case_found: false,
+            run_id: Uuid::new_v4().to_string(),
+            base_dir: PathBuf::new(),
+            shuffle_seed: rand::random(),
+            test_file: None,
+        }
+    }
+
+    pub fn with_transport(transport: Transport) -> Self {
+        TestSuite {
+            transport: Arc::new(transport),
+            ..Self::new()
         }
     }
 
+    // True if the test case selected with `--case` was found (and run) in the selected groups.
+    pub fn case_found(&self) -> bool {
+        self.case_found
+    }
+
+    // The id of the run, seeded into the globals of every group as `runId`, so that the test
+    // cases can namespace the resources they create (ex: `user-{{runId}}@test.com`).
+    pub fn run_id<'a>(&'a self, config: &'a Config) -> &'a str {
+        config.run_id.as_deref().unwrap_or(&self.run_id)
+    }
+
+    // The seed the test cases are shuffled with (`--shuffle`), reported so that the order of a
+    // run can be reproduced with `--seed`.
+    pub fn shuffle_seed(&self, config: &Config) -> u64 {
+        config.seed.unwrap_or(self.shuffle_seed)
+    }
+
+    // Sets the directory the relative paths of the test cases (ex: the uploaded files) are
+    // resolved against, the current directory by default.
+    pub fn set_base_dir(&mut self, base_dir: PathBuf) {
+        self.base_dir = base_dir;
+    }
+
+    // Sets the workbook the suite is read from, which also holds its data tables.
+    pub fn set_test_file(&mut self, test_file: &Path) {
+        self.test_file = Some(test_file.to_path_buf());
+    }
+
+    // Writes the exchanges recorded during the run (with `--record`).
+    pub fn save_recording(&self) -> Result<(), Box<dyn Error>> {
+        self.transport.save()
+    }
+
     pub fn exec<R: Read + Seek>(
         &mut self,
         excel: &mut Xlsx<R>,
         worksheet_name: &str,
         config: &Config,
         tx: &Sender<TestEvent>,
+    ) -> Result<TestResult, Box<dyn Error>> {
+        let range = excel.worksheet_range(worksheet_name)?;
+        self.exec_sheet(
+            &range.rows().collect::<Vec<_>>(),
+            worksheet_name,
+            config,
+            tx,
+        )
+    }
+
+    // Executes the rows of a worksheet, the first one being its header. The rows are read
+    // from excel, or built in code (see `SuiteBuilder`).
+    pub fn exec_sheet(
+        &mut self,
+        rows: &[&[calamine::Data]],
+        worksheet_name: &str,
+        config: &Config,
+        tx: &Sender<TestEvent>,
     ) -> Result<TestResult, Box<dyn Error>> {
         // Fire an event to indicate that the test suite has started.
         self.fire_start_evt(tx);
 
-        let range = excel.worksheet_range(worksheet_name)?;
+        let header = rows.first().copied().unwrap_or_default();
+        self.reruns.start_sheet(worksheet_name, header);
+        let rows = resolve_templates(rows);
+        let rows = rows.iter().map(Vec::as_slice).enumerate();
+        self.exec_rows(rows, worksheet_name, config, tx)?;
+
+        // Print test suite level statistics.
+        self.print_stats();
+
+        // Fire test suite end event.
+        self.fire_end_evt(tx);
+
+        // If we reached here, all applicable tests would have passed.
+        Ok(TestResult::Passed)
+    }
+
+    // Executes the test case rows of the worksheet, group by group. The "Cleanup" group is
+    // deferred to the very end, and runs even if a test case of an earlier group failed.
+    fn exec_rows<'a>(
+        &mut self,
+        rows: impl Iterator<Item = (usize, &'a [calamine::Data])>,
+        worksheet_name: &str,
+        config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (regular_rows, cleanup_rows): (Vec<_>, Vec<_>) = {
+            let mut in_cleanup = false;
+            rows.partition(|(_, row)| {
+                let first_cell = row[0].get_string().unwrap_or("");
+                if first_cell.starts_with("Group:") {
+                    let group_name = first_cell.trim_start_matches("Group:").trim();
+                    in_cleanup = group_name.eq_ignore_ascii_case(CLEANUP_GROUP);
+                }
+                !in_cleanup
+            })
+        };
+
+        let result = self.exec_groups(regular_rows.into_iter(), worksheet_name, config, tx);
+        let cleanup_result = self.exec_groups(cleanup_rows.into_iter(), worksheet_name, config, tx);
+        result.and(cleanup_result)
+    }
+
+    fn exec_groups<'a>(
+        &mut self,
+        rows: impl Iterator<Item = (usize, &'a [calamine::Data])>,
+        worksheet_name: &str,
+        config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> Result<(), Box<dyn Error>> {
         let mut current_group: Option<TestGroup> = None;
+        // First failure of a group, reported once all the groups ran (with `group_fail_fast`).
+        let mut failure: Option<Box<dyn Error>> = None;
+
+        // Test case rows of the current group, executed when the group ends.
+        let mut group_rows: Vec<&[calamine::Data]> = vec![];
+        let mut rng = config
+            .shuffle
+            .then(|| StdRng::seed_from_u64(self.shuffle_seed(config)));
 
         // Parse the config groups into a HashMap for quick lookup
         let config_groups = parse_config_groups(config, worksheet_name);
 
-        for (i, row) in range.rows().enumerate() {
+        for (i, row) in rows {
             // skip rows until start_row
             if i < config.start_row.unwrap_or(1) {
                 continue;
@@ -71,11 +203,20 @@ impl TestSuite {
 
             let first_cell = row[0].get_string().unwrap_or("");
             if first_cell.starts_with("Group:") {
-                // Finalize the previous group if it exists
-                self.finalize_group(&mut current_group, tx);
+                // Run and finalize the previous group if it exists
+                self.run_and_finalize_group(
+                    &mut current_group,
+                    &mut group_rows,
+                    &mut rng,
+                    &mut failure,
+                    config,
+                    tx,
+                )?;
 
                 // Extract the group name from the first cell.
                 let group_name = first_cell.trim_start_matches("Group:").trim();
+                self.seen_groups
+                    .insert((worksheet_name.to_string(), group_name.to_string()));
 
                 // If the group name is specified in the config for this worksheet,
                 // construct and run the test group.
@@ -84,7 +225,14 @@ impl TestSuite {
                         .get(worksheet_name)
                         .map_or(false, |groups| groups.contains(group_name))
                 {
-                    current_group = Some(TestGroup::new(group_name, tx));
+                    let mut group = TestGroup::new(group_name, config, &self.transport, tx)?;
+                    group.set_run_id(self.run_id(config));
+                    group.set_base_dir(&self.base_dir);
+                    if let Some(test_file) = &self.test_file {
+                        group.set_test_file(test_file);
+                    }
+                    group.parallel_cases = parallel_cases_directive(row);
+                    current_group = Some(group);
                     println!("{}", "-".repeat(80));
                     println!(
                         "Starting Group: {}...",
@@ -92,34 +240,211 @@ impl TestSuite {
                     );
                     println!("{}", "-".repeat(80));
                 }
-            } else {
-                // If we are in a group, call the group's exec method
-                if let Some(group) = current_group.as_mut() {
-                    group.exec(row, config, tx)?;
+            } else if current_group.is_some() {
+                // Collect the rows of the group, they are executed once the group is complete.
+                group_rows.push(row);
+            }
+        }
+
+        // Run and finalize the last group if it exists
+        self.run_and_finalize_group(
+            &mut current_group,
+            &mut group_rows,
+            &mut rng,
+            &mut failure,
+            config,
+            tx,
+        )?;
+        failure.map_or(Ok(()), Err)
+    }
+
+    // Parses every test case row of the worksheet without executing it, and
+    // prints the rows with invalid fields. Returns the no. of invalid rows.
+    pub fn lint<R: Read + Seek>(
+        &self,
+        excel: &mut Xlsx<R>,
+        worksheet_name: &str,
+        config: &Config,
+    ) -> Result<usize, Box<dyn Error>> {
+        let range = excel.worksheet_range(worksheet_name)?;
+        let rows = resolve_templates(&range.rows().collect::<Vec<_>>());
+        let rows = rows
+            .iter()
+            .map(Vec::as_slice)
+            .enumerate()
+            .skip(config.start_row.unwrap_or(1));
+        let invalid_rows = lint_rows(rows, config);
+
+        for (row_num, name, errors) in &invalid_rows {
+            println!("{}, row {}: {}", worksheet_name, row_num, name);
+            for (field, error) in errors {
+                println!("\t{}: {}", field, error);
+            }
+        }
+        println!(
+            "Linted sheet: {}, invalid rows: {}",
+            worksheet_name,
+            invalid_rows.len()
+        );
+        Ok(invalid_rows.len())
+    }
+
+    // Parses the test cases of the selected groups of the worksheet, without executing them.
+    pub fn load<R: Read + Seek>(
+        &self,
+        excel: &mut Xlsx<R>,
+        worksheet_name: &str,
+        config: &Config,
+    ) -> Result<Vec<LoadedGroup>, Box<dyn Error>> {
+        let range = excel.worksheet_range(worksheet_name)?;
+        let rows = resolve_templates(&range.rows().collect::<Vec<_>>());
+        let rows = rows
+            .iter()
+            .map(Vec::as_slice)
+            .enumerate()
+            .skip(config.start_row.unwrap_or(1));
+        Ok(load_groups(rows, worksheet_name, config))
+    }
+
+    // Runs the group, and finalizes it. A failure aborts the suite, unless `group_fail_fast`
+    // is set, in which case it is kept in `failure` and the next group is run.
+    fn run_and_finalize_group(
+        &mut self,
+        group: &mut Option<TestGroup>,
+        rows: &mut Vec<&[calamine::Data]>,
+        rng: &mut Option<StdRng>,
+        failure: &mut Option<Box<dyn Error>>,
+        config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = self.run_group(group, rows, rng, config, tx) {
+            // Stopping in step mode stops the whole run.
+            if !config.group_fail_fast || group.as_ref().is_some_and(|g| g.stopped) {
+                return Err(e);
+            }
+            failure.get_or_insert(e);
+        }
+
+        // A group header without any test case rows is usually a mistake in the sheet. Nothing
+        // ran, so the next groups are still run, and the error is reported at the end.
+        if let Some(empty) = group.as_ref().filter(|g| g.total == 0) {
+            if config.error_empty_groups && config.case.is_none() {
+                failure.get_or_insert(format!("Group '{}' has no test cases", empty.name()).into());
+            }
+        }
+        self.finalize_group(group, config, tx);
+        Ok(())
+    }
+
+    // Executes the collected rows of the group, shuffling them first if requested.
+    // With `group_fail_fast`, the rows following a failed test case are skipped.
+    fn run_group(
+        &mut self,
+        group: &mut Option<TestGroup>,
+        rows: &mut Vec<&[calamine::Data]>,
+        rng: &mut Option<StdRng>,
+        config: &Config,
+        tx: &Sender<TestEvent>,
+    ) -> Result<(), Box<dyn Error>> {
+        // With `--case`, only that test case runs, after the authorizers of its group.
+        if let Some(case_id) = config.case {
+            self.case_found |= select_case(rows, case_id);
+        }
+        if let Some(rng) = rng.as_mut() {
+            shuffle_rows(rows, rng);
+        }
+
+        // The cases of a `parallel_cases` group all run, there is nothing to fail fast on.
+        // In step mode, they run one at a time, to pause after each of them.
+        let parallel_cases = group.as_ref().and_then(|g| g.parallel_cases);
+        if let Some(max_in_flight) = parallel_cases.filter(|_| !config.step) {
+            let group = group.as_mut().unwrap();
+            let results = group.exec_parallel(rows, max_in_flight, config, tx);
+            // The authorizers run first, before the cases sent in parallel.
+            let authorizers: Vec<_> = rows
+                .iter()
+                .copied()
+                .filter(|row| TestCase::is_authorizer_row(row))
+                .collect();
+            let mut failure = None;
+            for (row, result) in rows.drain(..).zip(results) {
+                if result == TestResult::Failed {
+                    let before: Vec<_> =
+                        authorizers.iter().copied().filter(|a| *a != row).collect();
+                    self.reruns.record(group.name(), row, &before);
+                }
+                if let Err(e) = test_group::outcome(result) {
+                    failure.get_or_insert(e);
                 }
             }
+            return failure.map_or(Ok(()), Err);
         }
 
-        // Finalize the last group if it exists
-        self.finalize_group(&mut current_group, tx);
+        let mut failure = None;
+        let mut authorizers = vec![]; // Run so far in the group.
+        for row in rows.drain(..) {
+            if let Some(group) = group.as_mut() {
+                if failure.is_some() {
+                    group.skip(row, config, tx);
+                    continue;
+                }
 
-        // Print test suite level statistics.
-        self.print_stats();
+                let failed = group.failed;
+                let result = group.exec(row, config, tx);
+                if group.failed > failed {
+                    self.reruns.record(group.name(), row, &authorizers);
+                }
+                if TestCase::is_authorizer_row(row) {
+                    authorizers.push(row);
+                }
+                if let Err(e) = result {
+                    if !config.group_fail_fast {
+                        return Err(e);
+                    }
+                    failure = Some(e);
+                }
+            }
+        }
+        failure.map_or(Ok(()), Err)
+    }
 
-        // Fire test suite end event.
-        self.fire_end_evt(tx);
+    // Returns the group selectors of the config (ex: `-g Sheet1.Orders`), which matched
+    // none of the groups scanned so far.
+    pub fn unmatched_groups(&self, config: &Config) -> Vec<String> {
+        config
+            .groups
+            .iter()
+            .flatten()
+            .filter(|(worksheet, group)| {
+                !self.seen_groups.iter().any(|(seen_worksheet, seen_group)| {
+                    seen_group == group && worksheet.as_ref().is_none_or(|w| w == seen_worksheet)
+                })
+            })
+            .map(|(worksheet, group)| match worksheet {
+                Some(worksheet) => format!("{}.{}", worksheet, group),
+                None => group.clone(),
+            })
+            .collect()
+    }
 
-        // If we reached here, all applicable tests would have passed.
-        Ok(TestResult::Passed)
+    // Writes the failed test cases to a new workbook, and returns their count.
+    pub fn write_failed(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        self.reruns.write(path)
     }
 
-    fn finalize_group(&mut self, group: &mut Option<TestGroup>, tx: &Sender<TestEvent>) {
-        if let Some(group) = group.take() {
+    fn finalize_group(
+        &mut self,
+        group: &mut Option<TestGroup>,
+        config: &Config,
+        tx: &Sender<TestEvent>,
+    ) {
+        if let Some(mut group) = group.take() {
             group.print_stats();
             self.update_stats(&group);
 
-            group.fire_end_evt(tx);
-            self.test_groups.push(group);
+            group.fire_end_evt(config, tx);
+            // The group, and its JS runtime, is dropped here. So only one runtime is alive at a time,
+            // which keeps the memory flat and honours v8's reverse drop order of the isolates.
         }
     }
 
@@ -127,8 +452,8 @@ impl TestSuite {
         println!("");
         println!("Test Suite Summary:");
         println!(
-            "Total: {}, Passed: {}, Failed: {}, Skipped: {}",
-            self.total, self.passed, self.failed, self.skipped
+            "Total: {}, Passed: {}, Failed: {}, Skipped: {}, Warnings: {}",
+            self.total, self.passed, self.failed, self.skipped, self.warnings
         );
         println!("Execution Time: {:?}", self.exec_duration);
         println!("{}", "-".repeat(80));
@@ -140,6 +465,7 @@ impl TestSuite {
         self.passed += group.passed;
         self.failed += group.failed;
         self.skipped += group.skipped;
+        self.warnings += group.warnings;
         self.exec_duration += group.exec_duration();
     }
 
@@ -172,6 +498,19 @@ impl TestSuite {
     }
 }
 
+// Reads the `parallel_cases` directive from the cells following the name of a group header
+// row, as `parallel_cases` (all the cases in flight) or `parallel_cases=4` (at most 4).
+fn parallel_cases_directive(row: &[calamine::Data]) -> Option<usize> {
+    row.iter().skip(1).find_map(|cell| {
+        let directive = cell.get_string()?.trim();
+        match directive.split_once('=') {
+            Some((name, limit)) if name.trim() == "parallel_cases" => limit.trim().parse().ok(),
+            None if directive == "parallel_cases" => Some(usize::MAX),
+            _ => None,
+        }
+    })
+}
+
 fn parse_config_groups(
     config: &Config,
     default_worksheet: &str,
@@ -192,3 +531,694 @@ fn parse_config_groups(
     }
     config_groups
 }
+
+// A group of parsed test cases, as (group name, test cases).
+pub type LoadedGroup = (String, Vec<TestCase>);
+
+// An invalid row as (excel row number, test case name, field errors).
+type InvalidRow = (usize, String, Vec<(String, String)>);
+
+// Parses the test case rows (group rows are skipped) and returns the invalid ones.
+fn lint_rows<'a>(
+    rows: impl Iterator<Item = (usize, &'a [calamine::Data])>,
+    config: &Config,
+) -> Vec<InvalidRow> {
+    rows.filter(|(_, row)| !row[0].get_string().unwrap_or("").starts_with("Group:"))
+        .map(|(i, row)| (i + 1, TestCase::new(row, config)))
+        .filter(|(_, tc)| !tc.errors.is_empty())
+        .map(|(row_num, tc)| (row_num, tc.name, tc.errors))
+        .collect()
+}
+
+// Parses the test case rows into their groups, as (group name, test cases). The rows before
+// the first group, and the groups not selected in the config, are left out (as in `exec`).
+fn load_groups<'a>(
+    rows: impl Iterator<Item = (usize, &'a [calamine::Data])>,
+    worksheet_name: &str,
+    config: &Config,
+) -> Vec<LoadedGroup> {
+    let config_groups = parse_config_groups(config, worksheet_name);
+    let mut groups: Vec<LoadedGroup> = vec![];
+    let mut in_selected_group = false;
+    for (_, row) in rows {
+        let first_cell = row[0].get_string().unwrap_or("");
+        if first_cell.starts_with("Group:") {
+            let group_name = first_cell.trim_start_matches("Group:").trim();
+            in_selected_group = config_groups.is_empty()
+                || config_groups
+                    .get(worksheet_name)
+                    .is_some_and(|groups| groups.contains(group_name));
+            if in_selected_group {
+                groups.push((group_name.to_string(), vec![]));
+            }
+        } else if in_selected_group {
+            if let Some((_, cases)) = groups.last_mut() {
+                cases.push(TestCase::new(row, config));
+            }
+        }
+    }
+    groups
+}
+
+// Keeps the row of the test case with the given id, along with the authorizers before it (it
+// may need their token), and returns true. Without such a row, none of the rows are kept.
+fn select_case(rows: &mut Vec<&[calamine::Data]>, case_id: u32) -> bool {
+    let Some(position) = rows
+        .iter()
+        .position(|row| TestCase::row_id(row) == Some(case_id))
+    else {
+        rows.clear();
+        return false;
+    };
+    rows.truncate(position + 1);
+    rows.retain(|row| TestCase::row_id(row) == Some(case_id) || TestCase::is_authorizer_row(row));
+    true
+}
+
+// Shuffles the rows of a group, but for its authorizers, which are moved first (in their
+// order), as the other test cases need their tokens.
+fn shuffle_rows(rows: &mut [&[calamine::Data]], rng: &mut StdRng) {
+    rows.sort_by_key(|row| !TestCase::is_authorizer_row(row));
+    let authorizers = rows
+        .iter()
+        .take_while(|row| TestCase::is_authorizer_row(row))
+        .count();
+    shuffle_cases(&mut rows[authorizers..], rng);
+}
+
+// Randomizes the order of the test cases of a group, deterministic for a given rng seed.
+fn shuffle_cases<T>(cases: &mut [T], rng: &mut StdRng) {
+    cases.shuffle(rng);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::Data;
+
+    fn row(id: f64, url: &str, method: &str) -> Vec<Data> {
+        let mut row = vec![
+            Data::Float(id),
+            Data::String("Get users".to_string()),
+            Data::String("a running server".to_string()),
+            Data::String("users are fetched".to_string()),
+            Data::String("a list is returned".to_string()),
+            Data::String(url.to_string()),
+            Data::String(method.to_string()),
+        ];
+        row.resize(12, Data::Empty);
+        row
+    }
+
+    fn group_row(name: &str) -> Vec<Data> {
+        let mut row = vec![Data::String(format!("Group: {}", name))];
+        row.resize(12, Data::Empty);
+        row
+    }
+
+    // A test case against an unreachable server, checked by the given post test script.
+    fn case_row(id: f64, post_test_script: &str) -> Vec<Data> {
+        let mut row = row(id, "http://127.0.0.1:1/orders", "GET");
+        row[11] = Data::String(post_test_script.to_string());
+        row
+    }
+
+    #[test]
+    fn test_cleanup_group_runs_after_failing_group() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let rows = [
+            group_row("Header"), // skipped, as the start row
+            group_row("Cleanup"),
+            case_row(
+                3.0,
+                "SAT.tester('cleaned up', () => SAT.response.status === 0)",
+            ),
+            group_row("Orders"),
+            case_row(
+                1.0,
+                "SAT.tester('ordered', () => SAT.response.status === 200)",
+            ),
+            case_row(2.0, "SAT.tester('not reached', () => true)"),
+        ];
+
+        let mut ts = TestSuite::new();
+        let result = ts.exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &Config::default(),
+            &tx,
+        );
+        assert!(result.is_err());
+
+        let events: Vec<TestEvent> = rx.try_iter().collect();
+        let groups: Vec<&str> = events
+            .iter()
+            .filter_map(|e| match e {
+                TestEvent::EvtTestGroupBegin(g) => Some(g.group_name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(groups, vec!["Orders", "Cleanup"]);
+
+        let cases: Vec<(u32, TestResult)> = events
+            .iter()
+            .filter_map(|e| match e {
+                TestEvent::EvtTestCaseEnd(c) => Some((c.testcase_id, c.result.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            cases,
+            vec![(1, TestResult::Failed), (3, TestResult::Passed)]
+        );
+    }
+
+    #[test]
+    fn test_group_fail_fast_skips_rest_of_group() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let rows = [
+            group_row("Header"), // skipped, as the start row
+            group_row("Orders"),
+            case_row(
+                1.0,
+                "SAT.tester('ordered', () => SAT.response.status === 200)",
+            ),
+            case_row(2.0, "SAT.tester('not reached', () => true)"),
+            group_row("Users"),
+            case_row(
+                3.0,
+                "SAT.tester('still runs', () => SAT.response.status === 0)",
+            ),
+        ];
+        let config = Config {
+            group_fail_fast: true,
+            ..Config::default()
+        };
+
+        let mut ts = TestSuite::new();
+        let result = ts.exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &config,
+            &tx,
+        );
+        assert!(result.is_err());
+        assert_eq!((ts.failed, ts.skipped, ts.passed), (1, 1, 1));
+
+        let cases: Vec<(u32, TestResult)> = rx
+            .try_iter()
+            .filter_map(|e| match e {
+                TestEvent::EvtTestCaseEnd(c) => Some((c.testcase_id, c.result)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            cases,
+            vec![
+                (1, TestResult::Failed),
+                (2, TestResult::Skipped),
+                (3, TestResult::Passed)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_failed_cases() {
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let rows = [
+            group_row("Header"), // skipped, as the start row
+            group_row("Orders"),
+            case_row(
+                1.0,
+                "SAT.tester('ordered', () => SAT.response.status === 200)",
+            ),
+            case_row(2.0, "SAT.tester('skipped', () => true)"),
+            group_row("Users"),
+            case_row(3.0, "SAT.tester('passes', () => SAT.response.status === 0)"),
+            case_row(
+                4.0,
+                "SAT.tester('listed', () => SAT.response.status === 200)",
+            ),
+        ];
+        let config = Config {
+            group_fail_fast: true,
+            ..Config::default()
+        };
+
+        let mut ts = TestSuite::new();
+        ts.reruns.start_sheet("Sheet1", &rows[0]);
+        let _ = ts.exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &config,
+            &tx,
+        );
+
+        let path = std::env::temp_dir().join(format!("failed_{}.xlsx", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert_eq!(ts.write_failed(path).unwrap(), 2);
+
+        let mut excel: Xlsx<_> = calamine::open_workbook(path).unwrap();
+        let range = excel.worksheet_range("Sheet1").unwrap();
+        let ids: Vec<f64> = range.rows().filter_map(|r| r[0].get_float()).collect();
+        assert_eq!(ids, vec![1.0, 4.0]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_unmatched_group_selectors() {
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let rows = [group_row("Header"), group_row("Orders"), group_row("Users")];
+        let config = Config {
+            groups: Some(vec![
+                (None, "Users".to_string()),
+                (None, "Ordrs".to_string()),
+                (Some("Sheet2".to_string()), "Orders".to_string()),
+            ]),
+            ..Config::default()
+        };
+
+        let mut ts = TestSuite::new();
+        ts.exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &config,
+            &tx,
+        )
+        .unwrap();
+        assert_eq!(ts.unmatched_groups(&config), vec!["Ordrs", "Sheet2.Orders"]);
+    }
+
+    #[test]
+    fn test_empty_group_error() {
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let rows = [
+            group_row("Header"), // skipped, as the start row
+            group_row("Orders"),
+            group_row("Users"),
+            case_row(1.0, "SAT.tester('runs', () => SAT.response.status === 0)"),
+        ];
+        let exec = |config: &Config| {
+            TestSuite::new().exec_rows(
+                rows.iter().map(|r| r.as_slice()).enumerate(),
+                "Sheet1",
+                config,
+                &tx,
+            )
+        };
+
+        assert!(exec(&Config::default()).is_ok());
+
+        let config = Config {
+            error_empty_groups: true,
+            ..Config::default()
+        };
+        let err = exec(&config).unwrap_err();
+        assert_eq!(err.to_string(), "Group 'Orders' has no test cases");
+    }
+
+    #[test]
+    fn test_single_case() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let passes = "SAT.tester('sent', () => SAT.response.status === 0)";
+        let authorizer = |id: f64| {
+            let mut row = case_row(id, passes);
+            row[9] = Data::String(r#"{"authType": "authorizer"}"#.to_string());
+            row
+        };
+        let rows = [
+            group_row("Header"), // skipped, as the start row
+            group_row("Orders"),
+            authorizer(1.0),
+            case_row(2.0, passes),
+            case_row(3.0, passes),
+            authorizer(4.0),
+            group_row("Users"),
+            case_row(5.0, passes),
+        ];
+        let config = Config {
+            case: Some(3),
+            ..Config::default()
+        };
+
+        let mut ts = TestSuite::new();
+        let result = ts.exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &config,
+            &tx,
+        );
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(ts.case_found());
+
+        // Only the case ran, after the authorizer before it.
+        let executed: Vec<u32> = rx
+            .try_iter()
+            .filter_map(|e| match e {
+                TestEvent::EvtTestCaseEnd(c) => Some(c.testcase_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(executed, vec![1, 3]);
+
+        let config = Config {
+            case: Some(9),
+            ..Config::default()
+        };
+        let mut ts = TestSuite::new();
+        ts.exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &config,
+            &tx,
+        )
+        .unwrap();
+        assert!(!ts.case_found());
+    }
+
+    #[test]
+    fn test_duration_of_concurrent_repeats() {
+        let delay = std::time::Duration::from_millis(50);
+        let (url, _) = crate::test_utils::concurrency_server(delay);
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        let mut case = row(1.0, &format!("{}/items", url), "GET");
+        case[9] = Data::String(r#"{"repeatCount": 8, "maxConcurrency": 2}"#.to_string());
+        case[11] = Data::String("SAT.tester('ok', () => SAT.response.status === 200)".to_string());
+        let rows = [group_row("Header"), group_row("Catalog"), case];
+
+        let mut ts = TestSuite::new();
+        let result = ts.exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &Config::default(),
+            &tx,
+        );
+        assert!(result.is_ok(), "{:?}", result);
+
+        // The 8 requests, 2 at a time, take at least 4 delays, while each one takes about one.
+        assert!(ts.exec_duration >= delay * 4, "{:?}", ts.exec_duration);
+        let latencies: Vec<_> = rx
+            .try_iter()
+            .filter_map(|e| match e {
+                TestEvent::EvtTestCaseEnd(c) => Some(c.exec_duration),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(latencies.len(), 8);
+        assert!(
+            latencies.iter().all(|l| *l >= delay && *l < delay * 4),
+            "{:?}",
+            latencies
+        );
+    }
+
+    #[test]
+    fn test_parallel_cases_in_step_mode() {
+        // From a terminal, the step prompt would wait for the user.
+        if std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+            return;
+        }
+        let (url, max_in_flight) =
+            crate::test_utils::concurrency_server(std::time::Duration::from_millis(20));
+        let (tx, _rx) = std::sync::mpsc::channel();
+
+        let mut header = group_row("Catalog");
+        header[1] = Data::String("parallel_cases".to_string());
+        let mut rows = vec![group_row("Header"), header];
+        for id in 1..=3 {
+            let mut row = row(id as f64, &format!("{}/items/{}", url, id), "GET");
+            row[11] =
+                Data::String("SAT.tester('ok', () => SAT.response.status === 200)".to_string());
+            rows.push(row);
+        }
+        let config = Config {
+            step: true,
+            ..Config::default()
+        };
+        let result = TestSuite::new().exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &config,
+            &tx,
+        );
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_parallel_cases() {
+        let delay = std::time::Duration::from_millis(300);
+        let (url, max_in_flight) = crate::test_utils::concurrency_server(delay);
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        let mut header = group_row("Catalog");
+        header[1] = Data::String("parallel_cases".to_string());
+        let mut rows = vec![group_row("Header"), header]; // the first one is the start row
+        for id in 1..=4 {
+            let mut row = row(id as f64, &format!("{}/items/{}", url, id), "GET");
+            // Each case checks its own response, they complete in any order.
+            row[11] = Data::String(format!(
+                "SAT.tester('own response', () => SAT.response.body === '/items/{}')",
+                id
+            ));
+            rows.push(row);
+        }
+
+        let start = Instant::now();
+        let result = TestSuite::new().exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &Config::default(),
+            &tx,
+        );
+        let elapsed = start.elapsed();
+        assert!(result.is_ok(), "{:?}", result);
+
+        // Sequentially, the 4 cases would take at least 4 delays.
+        assert!(elapsed < delay * 3, "{:?}", elapsed);
+        assert!(max_in_flight.load(std::sync::atomic::Ordering::SeqCst) > 1);
+
+        let mut passed: Vec<u32> = rx
+            .try_iter()
+            .filter_map(|e| match e {
+                TestEvent::EvtTestCaseEnd(c) if c.result == TestResult::Passed => {
+                    Some(c.testcase_id)
+                }
+                _ => None,
+            })
+            .collect();
+        passed.sort();
+        assert_eq!(passed, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_parallel_cases_directive() {
+        let mut header = group_row("Catalog");
+        assert_eq!(parallel_cases_directive(&header), None);
+        header[1] = Data::String("parallel_cases".to_string());
+        assert_eq!(parallel_cases_directive(&header), Some(usize::MAX));
+        header[1] = Data::String("parallel_cases = 2".to_string());
+        assert_eq!(parallel_cases_directive(&header), Some(2));
+    }
+
+    #[test]
+    fn test_lint_reports_bad_row() {
+        let config = Config {
+            base_url: Some("http://localhost:3000/api".to_string()),
+            ..Config::default()
+        };
+        let rows = [
+            group_row("Users"),
+            row(1.0, "/users", "GET"),
+            row(2.0, "/users", "FETCH ME"),
+            row(3.0, "/users/1", "DELETE"),
+        ];
+
+        let invalid = lint_rows(rows.iter().map(|r| r.as_slice()).enumerate(), &config);
+        assert_eq!(invalid.len(), 1);
+        let (row_num, _, errors) = &invalid[0];
+        assert_eq!(*row_num, 3);
+        assert_eq!(errors[0].0, "method");
+    }
+
+    // A rough benchmark of the parse path over a synthetic large sheet, run with:
+    // cargo test --release bench_lint_large_sheet -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_lint_large_sheet() {
+        let config = Config {
+            base_url: Some("http://localhost:3000/api".to_string()),
+            ..Config::default()
+        };
+        let rows: Vec<Vec<Data>> = (0..100_000)
+            .map(|i| {
+                if i % 100 == 0 {
+                    group_row(&format!("Group {}", i / 100))
+                } else {
+                    let mut row = row(i as f64, "/users/{{user_id}}", "POST");
+                    row[8] = Data::String(r#"{"name": "$RandomName"}"#.to_string());
+                    row
+                }
+            })
+            .collect();
+
+        let start = Instant::now();
+        let invalid = lint_rows(rows.iter().map(|r| r.as_slice()).enumerate(), &config);
+        println!("Parsed {} rows in {:?}", rows.len(), start.elapsed());
+        assert!(invalid.is_empty());
+    }
+
+    #[test]
+    fn test_shuffle_same_seed_same_order() {
+        let mut first: Vec<u32> = (1..=10).collect();
+        let mut second = first.clone();
+        shuffle_cases(&mut first, &mut StdRng::seed_from_u64(42));
+        shuffle_cases(&mut second, &mut StdRng::seed_from_u64(42));
+        assert_eq!(first, second);
+        assert_ne!(first, (1..=10).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_shuffle_different_seed_differs() {
+        let mut first: Vec<u32> = (1..=10).collect();
+        let mut second = first.clone();
+        shuffle_cases(&mut first, &mut StdRng::seed_from_u64(42));
+        shuffle_cases(&mut second, &mut StdRng::seed_from_u64(7));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_shuffle_keeps_authorizers_first() {
+        let authorizer = |id: f64| {
+            let mut row = row(id, "http://127.0.0.1:1/login", "POST");
+            row[9] = Data::String(r#"{"authType": "authorizer"}"#.to_string());
+            row
+        };
+        let group: Vec<Vec<Data>> = (1..=10)
+            .map(|id| match id {
+                1 | 6 => authorizer(id as f64),
+                _ => row(id as f64, "http://127.0.0.1:1/users", "GET"),
+            })
+            .collect();
+        let mut rows: Vec<&[Data]> = group.iter().map(Vec::as_slice).collect();
+        shuffle_rows(&mut rows, &mut StdRng::seed_from_u64(42));
+
+        let ids: Vec<u32> = rows.iter().filter_map(|r| TestCase::row_id(r)).collect();
+        assert_eq!(ids[..2], [1, 6]);
+        assert_ne!(ids[2..], [2, 3, 4, 5, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_shuffle_seed() {
+        let suite = TestSuite::new();
+        let config = Config {
+            shuffle: true,
+            ..Config::default()
+        };
+        assert_eq!(suite.shuffle_seed(&config), suite.shuffle_seed(&config));
+        let config = Config {
+            seed: Some(7),
+            ..config
+        };
+        assert_eq!(suite.shuffle_seed(&config), 7);
+    }
+
+    #[test]
+    fn test_report_globals_redacted() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let rows = [
+            group_row("Header"), // skipped, as the start row
+            group_row("Checkout"),
+            case_row(
+                1.0,
+                "SAT.globals.orderId = 42; SAT.globals.session = { accessToken: 'abc', user: 'ann' };",
+            ),
+        ];
+        let path = std::env::temp_dir().join(format!("report_{}.json", std::process::id()));
+        let config = Config {
+            report_json: Some(path.to_string_lossy().to_string()),
+            report_globals: true,
+            ..Config::default()
+        };
+        let _ = TestSuite::new().exec_rows(
+            rows.iter().map(|r| r.as_slice()).enumerate(),
+            "Sheet1",
+            &config,
+            &tx,
+        );
+
+        let mut reporters = crate::reporters::build_reporters(&config);
+        for event in rx.try_iter() {
+            reporters[0].on_event(&event);
+        }
+        reporters[0].finish().unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let checkout = &report["groups"][0];
+        assert_eq!(checkout["name"], "Checkout");
+        assert_eq!(checkout["cases"][0]["id"], 1);
+        assert_eq!(checkout["globals"]["orderId"], 42);
+        assert_eq!(
+            checkout["globals"]["session"],
+            serde_json::json!({ "accessToken": "***", "user": "ann" })
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_run_id_shared_by_the_cases() {
+        // Creates a user, namespaced by the run id.
+        let create_user = |id: f64| {
+            let mut row = row(id, "http://127.0.0.1:1/users", "POST");
+            row[8] = Data::String(r#"{"email": "user-{{runId}}@test.com"}"#.to_string());
+            row[11] =
+                Data::String("SAT.tester('sent', () => SAT.response.status === 0)".to_string());
+            row
+        };
+        let rows = [
+            group_row("Header"), // skipped, as the start row
+            group_row("Signup"),
+            create_user(1.0),
+            create_user(2.0),
+            group_row("Login"),
+            create_user(3.0),
+        ];
+        let payloads = |config: &Config| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            TestSuite::new()
+                .exec_rows(
+                    rows.iter().map(|r| r.as_slice()).enumerate(),
+                    "Sheet1",
+                    config,
+                    &tx,
+                )
+                .unwrap();
+            rx.try_iter()
+                .filter_map(|e| match e {
+                    TestEvent::EvtTestCaseEnd(c) if !c.effective_payload.is_empty() => {
+                        Some(c.effective_payload)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<String>>()
+        };
+
+        // A fresh UUID, the same for every test case of the run.
+        let sent = payloads(&Config::default());
+        assert_eq!(sent.len(), 3);
+        assert!(sent.iter().all(|payload| payload == &sent[0]), "{:?}", sent);
+        let run_id = sent[0]
+            .trim_start_matches(r#"{"email": "user-"#)
+            .trim_end_matches(r#"@test.com"}"#);
+        assert!(Uuid::parse_str(run_id).is_ok(), "{}", sent[0]);
+        assert_ne!(payloads(&Config::default())[0], sent[0]);
+
+        // Or the given one.
+        let config = Config {
+            run_id: Some("ci-42".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(payloads(&config)[0], r#"{"email": "user-ci-42@test.com"}"#);
+    }
+}
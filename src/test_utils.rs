@@ -0,0 +1,255 @@
+// Helpers shared by the unit tests.
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Starts a single shot http server which replies with the given raw response,
+// and returns its url.
+pub fn mock_server<T: AsRef<[u8]> + Send + 'static>(raw_response: T) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf);
+            let _ = stream.write_all(raw_response.as_ref());
+        }
+    });
+    format!("http://{}", addr)
+}
+
+// Starts a single shot http server which captures the request as (lowercased headers, body),
+// and replies with an empty json.
+pub fn capture_server() -> (String, Receiver<(String, Vec<u8>)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            let (headers, body) = loop {
+                let n = stream.read(&mut buf).unwrap_or(0);
+                request.extend_from_slice(&buf[..n]);
+                let end = request.windows(4).position(|w| w == b"\r\n\r\n");
+                if let Some(end) = end {
+                    let headers = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                    let len = headers
+                        .lines()
+                        .find_map(|l| l.strip_prefix("content-length:"))
+                        .and_then(|v| v.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if n == 0 || request.len() >= end + 4 + len {
+                        break (headers, request[end + 4..].to_vec());
+                    }
+                } else if n == 0 {
+                    break (String::new(), vec![]);
+                }
+            };
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}");
+            let _ = tx.send((headers, body));
+        }
+    });
+    (url, rx)
+}
+
+// Starts an http server which accepts the given no. of requests, and sends back the request
+// line (ex: "GET /users HTTP/1.1") of each. Every request is replied with an empty json.
+pub fn request_line_server(count: usize) -> (String, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for mut stream in listener.incoming().take(count).flatten() {
+            let mut buf = [0; 4096];
+            let n = stream.read(&mut buf).unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}");
+            let _ = tx.send(request.lines().next().unwrap_or_default().to_string());
+        }
+    });
+    (url, rx)
+}
+
+// Starts a single shot http server which reflects the given request header back in its reply.
+pub fn reflect_header_server(name: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let mut buf = [0; 4096];
+            let n = stream.read(&mut buf).unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            let header = request
+                .lines()
+                .find(|line| line.to_lowercase().starts_with(&format!("{}:", name)))
+                .unwrap_or_default();
+            let _ = stream.write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\n{}\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}",
+                    header
+                )
+                .as_bytes(),
+            );
+        }
+    });
+    format!("http://{}", addr)
+}
+
+// Starts an http server which replies to the requests with the given raw responses, in
+// order, and sends back the head (request line and headers) of each request.
+pub fn sequence_server(responses: Vec<&'static str>) -> (String, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for (response, stream) in responses.into_iter().zip(listener.incoming()) {
+            let Ok(mut stream) = stream else { continue };
+            let mut buf = [0; 4096];
+            let n = stream.read(&mut buf).unwrap_or(0);
+            let _ = tx.send(String::from_utf8_lossy(&buf[..n]).to_string());
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    (url, rx)
+}
+
+// Starts an http server which replies to every request with its path, after the given delay,
+// and tracks the max. no. of requests it has had in flight at once.
+pub fn concurrency_server(delay: Duration) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let max_in_flight = Arc::new(AtomicUsize::new(0));
+    let max = max_in_flight.clone();
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let (in_flight, max) = (in_flight.clone(), max.clone());
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).unwrap_or(0);
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max.fetch_max(current, Ordering::SeqCst);
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                thread::sleep(delay);
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                let _ = stream.write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        path.len(),
+                        path
+                    )
+                    .as_bytes(),
+                );
+            });
+        }
+    });
+    (url, max_in_flight)
+}
+
+// Starts an http server which replies to the requests one at a time, each after the next of
+// the given delays, with an empty json.
+pub fn delay_server(delays: Vec<Duration>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for (delay, stream) in delays.into_iter().zip(listener.incoming()) {
+            let Ok(mut stream) = stream else { continue };
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf);
+            thread::sleep(delay);
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}");
+        }
+    });
+    url
+}
+
+// Starts an http server which replies to every request in the media type of its `Accept`
+// header, if it is one of the given ones, or in the first of them otherwise.
+pub fn accept_server(media_types: Vec<&'static str>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut buf = [0; 4096];
+            let n = stream.read(&mut buf).unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            let accept = request
+                .lines()
+                .find_map(|line| line.strip_prefix("accept:"))
+                .map(str::trim)
+                .unwrap_or_default();
+            let media_type = media_types
+                .iter()
+                .find(|media_type| **media_type == accept)
+                .unwrap_or(&media_types[0]);
+            let _ = stream.write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    media_type
+                )
+                .as_bytes(),
+            );
+        }
+    });
+    url
+}
+
+// Starts an http server which answers an NTLM negotiate message with a challenge, and the
+// request that follows on the same connection with 200 if it is an authenticate message.
+// Returns its url, and the `Authorization` header of that request.
+#[cfg(feature = "ntlm")]
+pub fn ntlm_server() -> (String, Receiver<String>) {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let mut challenge = b"NTLMSSP\0".to_vec();
+    challenge.extend_from_slice(&2u32.to_le_bytes());
+    challenge.extend_from_slice(&[0; 8]); // Target name.
+    challenge.extend_from_slice(&0xa208_8205u32.to_le_bytes());
+    challenge.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    challenge.extend_from_slice(&[0; 8]);
+    challenge.extend_from_slice(&[4, 0, 4, 0, 48, 0, 0, 0]); // Target info, an MsvAvEOL.
+    challenge.extend_from_slice(&[0; 4]);
+    let challenge = STANDARD.encode(challenge);
+
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        let Some(Ok(mut stream)) = listener.incoming().next() else {
+            return;
+        };
+        let mut buf = [0; 4096];
+        for _ in 0..2 {
+            let n = stream.read(&mut buf).unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            let authorization = request
+                .lines()
+                .find_map(|line| line.strip_prefix("authorization: NTLM "))
+                .and_then(|token| STANDARD.decode(token.trim()).ok())
+                .unwrap_or_default();
+            let response = match authorization.get(8) {
+                Some(1) => format!(
+                    "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: NTLM {}\r\nContent-Length: 0\r\n\r\n",
+                    challenge
+                ),
+                Some(3) => {
+                    let _ = tx.send(STANDARD.encode(&authorization));
+                    "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok".to_string()
+                }
+                _ => "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    (url, rx)
+}
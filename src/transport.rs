@@ -0,0 +1,195 @@
+/* How the requests of a run are sent: over the network, over the network while
+    recording every exchange to a cassette file (`--record`), or answered from a
+    recorded cassette without any network access (`--replay`).  The responses are
+    the same either way, so the scripts run identically.
+*/
+
+use crate::config::Config;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::blocking::{Client, Request, Response};
+use reqwest::cookie::{CookieStore, Jar};
+use reqwest::header::{HeaderValue, SET_COOKIE};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::sync::Mutex;
+
+pub type SendResult = Result<Response, Box<dyn Error + Send + Sync>>;
+
+#[derive(Debug, Default)]
+pub enum Transport {
+    #[default]
+    Network,
+    Record {
+        path: String,
+        cassette: Mutex<Vec<Interaction>>,
+    },
+    Replay {
+        cassette: Mutex<Vec<Interaction>>, // The exchanges not replayed yet.
+    },
+}
+
+// A recorded request / response exchange.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub url: String,
+    pub body: String, // The request body.
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub response: String, // The response body, base64 encoded.
+}
+
+impl Transport {
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn Error>> {
+        match (&config.record, &config.replay) {
+            (Some(_), Some(_)) => Err("--record and --replay can't be used together".into()),
+            (Some(path), None) => Ok(Transport::Record {
+                path: path.clone(),
+                cassette: Mutex::new(vec![]),
+            }),
+            (None, Some(path)) => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read cassette '{}': {}", path, e))?;
+                let cassette = serde_json::from_str(&contents)
+                    .map_err(|e| format!("Invalid cassette '{}': {}", path, e))?;
+                Ok(Transport::Replay {
+                    cassette: Mutex::new(cassette),
+                })
+            }
+            (None, None) => Ok(Transport::Network),
+        }
+    }
+
+    pub fn send(&self, client: &Client, cookie_jar: &Jar, request: Request) -> SendResult {
+        match self {
+            Transport::Network => Ok(client.execute(request)?),
+            Transport::Record { cassette, .. } => {
+                let (method, url, body) = request_key(&request);
+                let response = client.execute(request)?;
+                let status = response.status().as_u16();
+                let headers = response
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+                        (name.to_string(), value)
+                    })
+                    .collect();
+                let interaction = Interaction {
+                    method,
+                    url,
+                    body,
+                    status,
+                    headers,
+                    response: STANDARD.encode(response.bytes()?),
+                };
+                cassette.lock().unwrap().push(interaction.clone());
+                interaction.to_response()
+            }
+            Transport::Replay { cassette } => {
+                // The exchanges are replayed in the recorded order, so a repeated request
+                // (ex: polling) gets the successive responses.
+                let (method, url, body) = request_key(&request);
+                let mut cassette = cassette.lock().unwrap();
+                let index = cassette
+                    .iter()
+                    .position(|i| i.method == method && i.url == url && i.body == body)
+                    .ok_or_else(|| format!("No recorded response for {} {}", method, url))?;
+                let interaction = cassette.remove(index);
+
+                // Let the cookie jar see the recorded cookies, as if they came from the server.
+                let cookies: Vec<HeaderValue> = interaction
+                    .headers
+                    .iter()
+                    .filter(|(name, _)| name.eq_ignore_ascii_case(SET_COOKIE.as_str()))
+                    .filter_map(|(_, value)| HeaderValue::from_str(value).ok())
+                    .collect();
+                cookie_jar.set_cookies(&mut cookies.iter(), request.url());
+                interaction.to_response()
+            }
+        }
+    }
+
+    // Writes the recorded exchanges (if recording) to the cassette file.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Transport::Record { path, cassette } = self {
+            let cassette = cassette.lock().unwrap();
+            fs::write(path, serde_json::to_string_pretty(&*cassette)?)
+                .map_err(|e| format!("Failed to write cassette '{}': {}", path, e))?;
+            println!("{} exchange(s) recorded to: {}", cassette.len(), path);
+        }
+        Ok(())
+    }
+}
+
+impl Interaction {
+    fn to_response(&self) -> SendResult {
+        let mut response = http::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            response = response.header(name, value);
+        }
+        Ok(Response::from(
+            response.body(STANDARD.decode(&self.response)?)?,
+        ))
+    }
+}
+
+// The method, url and body a recorded exchange is matched on.
+fn request_key(request: &Request) -> (String, String, String) {
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+        .unwrap_or_default();
+    (
+        request.method().to_string(),
+        request.url().to_string(),
+        body,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_context::TestCtx;
+    use crate::test_utils::mock_server;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_record_then_replay() {
+        let url = mock_server(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 11\r\nConnection: close\r\n\r\n{\"id\":\"42\"}",
+        );
+        let path = std::env::temp_dir().join(format!("cassette_{}.json", std::process::id()));
+        let config = Config {
+            record: Some(path.to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        let script = "SAT.tester('recorded', () => SAT.response.status === 200 && SAT.response.json.id === '42')";
+
+        let transport = Arc::new(Transport::from_config(&config).unwrap());
+        let mut tctx = TestCtx::with_transport(&config, transport.clone()).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+        assert!(tctx.verify_result(Some(script)));
+        transport.save().unwrap();
+
+        // The mock server is gone, so the response can only come from the cassette.
+        let config = Config {
+            replay: config.record,
+            ..Config::default()
+        };
+        let transport = Arc::new(Transport::from_config(&config).unwrap());
+        let mut tctx = TestCtx::with_transport(&config, transport).unwrap();
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+        assert!(tctx.verify_result(Some(script)));
+
+        // Every exchange is replayed once.
+        let request = tctx.client.get(&url);
+        tctx.exec(request, None, &config);
+        assert!(!tctx.verify_result(Some(script)));
+        fs::remove_file(path).unwrap();
+    }
+}
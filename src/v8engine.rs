@@ -4,6 +4,32 @@ use deno_core::v8;
 use deno_core::{JsRuntime, RuntimeOptions};
 use serde_json::Value;
 
+// Sends a GET to a url (resolved against a base url), and returns the response as the
+// `SAT.response` object. It is installed by the test context, to reuse its http client.
+pub type HttpGet = Box<dyn Fn(&str, &str) -> Result<Value, AnyError>>;
+
+// The ops backing the `SAT` helpers, which need the rust side (ex: `SAT.followLink`).
+mod ops {
+    use super::HttpGet;
+    use deno_core::error::AnyError;
+    use deno_core::{extension, op2, OpState};
+
+    #[op2]
+    #[serde]
+    fn op_sat_http_get(
+        state: &mut OpState,
+        #[string] href: String,
+        #[string] base: String,
+    ) -> Result<serde_json::Value, AnyError> {
+        let http_get = state
+            .try_borrow::<HttpGet>()
+            .ok_or_else(|| AnyError::msg("No http client available to follow the link"))?;
+        http_get(&href, &base)
+    }
+
+    extension!(sat_ops, ops = [op_sat_http_get]);
+}
+
 pub struct JsEngine {
     runtime: JsRuntime,
 }
@@ -16,10 +42,18 @@ impl std::fmt::Debug for JsEngine {
 
 impl JsEngine {
     pub fn new() -> Self {
-        let runtime = JsRuntime::new(RuntimeOptions::default());
+        let runtime = JsRuntime::new(RuntimeOptions {
+            extensions: vec![ops::sat_ops::init_ops()],
+            ..Default::default()
+        });
         JsEngine { runtime }
     }
 
+    // Lets the scripts send GET requests (ex: `SAT.followLink`) with the given function.
+    pub fn set_http_get(&mut self, http_get: HttpGet) {
+        self.runtime.op_state().borrow_mut().put(http_get);
+    }
+
     pub fn eval(&mut self, js_code: &str) -> Result<Value, AnyError> {
         let scope = &mut self.runtime.handle_scope();
         let code = v8::String::new(scope, js_code).unwrap();
@@ -38,16 +72,349 @@ impl JsEngine {
             var SAT = {};
             SAT.globals = {};
             //console.log("global object created", global);
+            SAT.assertions = [];
             SAT.tester = function(name, cb) { 
                 console.log(`Executing '${name}'...`);
-                let result = cb(); 
-                return result === true ? true : false;
+                let result = false;
+                let message = null;
+                try {
+                    result = cb() === true;
+                } catch (e) {
+                    message = SAT.failureMessage(e);
+                }
+                SAT.assertions.push({ name: name, passed: result, message: message });
+                return result;
+            };
+            // Like SAT.tester, but a failure is only reported as a warning and
+            // never fails the test case (ex: latency SLAs).
+            SAT.warn = function(name, cb) {
+                console.log(`Executing '${name}' (warn only)...`);
+                let result = false;
+                let message = null;
+                try {
+                    result = cb() === true;
+                } catch (e) {
+                    message = SAT.failureMessage(e);
+                }
+                SAT.assertions.push({ name: name, passed: result, message: message, warning: true });
+                return true;
+            };
+            // The message of a failed assertion. A script dereferencing the json of a response
+            // which is not JSON (ex: `SAT.response.json.id` of a text body) gets it explained.
+            SAT.notJson = function() {
+                return SAT.response !== undefined && SAT.response.json == null && !SAT.expectValidJson();
+            };
+            SAT.failureMessage = function(e) {
+                if (e instanceof TypeError && SAT.notJson()) {
+                    return "response body is not JSON (" + String(e) + ")";
+                }
+                return String(e);
+            };
+            // True if the response body is a valid JSON document.
+            SAT.expectValidJson = function() {
+                try {
+                    JSON.parse(SAT.response.body);
+                    return true;
+                } catch (e) {
+                    return false;
+                }
+            };
+            // Cookies to be added to the jar of the group, before its next request.
+            SAT.pendingCookies = [];
+            SAT.setCookie = function(name, value) {
+                SAT.pendingCookies.push({ name: String(name), value: String(value) });
+            };
+            // The effective request sent (ex: `{ method, url, body }`), set before each request.
+            SAT.request = {};
+            // True if the request was prepared without a payload (ex: for a GET or DELETE).
+            SAT.expectNoRequestBody = function() {
+                return !SAT.request.body;
+            };
+            // Follows the HATEOAS link of the response by its rel (ex: `SAT.followLink("next")`),
+            // found at `SAT.linksPath` of the response json (HAL style `_links` by default), and
+            // replaces `SAT.response` with the response of the followed link.
+            SAT.linksPath = "$._links";
+            SAT.followLink = function(rel) {
+                const links = SAT.jsonPath(SAT.linksPath) || {};
+                const link = Array.isArray(links[rel]) ? links[rel][0] : links[rel];
+                const href = typeof link === "string" ? link : link && link.href;
+                if (!href) {
+                    throw new Error("No '" + rel + "' link at " + SAT.linksPath);
+                }
+                const base = SAT.response.url || SAT.request.url || "";
+                SAT.response = Deno.core.ops.op_sat_http_get(String(href), String(base));
+                return SAT.response;
+            };
+            // True if the test case sent exactly `n` requests so far: its repeats, polls and the
+            // links followed included.
+            SAT.expectRequestCount = function(n) {
+                return SAT.response.requestCount === n;
+            };
+            // Durations (in ms) of the iterations of a repeated test case so far, for the final
+            // script to check the latencies across them, ex: `SAT.expectP95Below(200)`.
+            SAT.iterationTimesMs = [];
+            // The duration at the given percentile of the iterations (by the nearest rank).
+            SAT.iterationPercentile = function(p) {
+                const times = SAT.iterationTimesMs.slice().sort((a, b) => a - b);
+                const rank = Math.ceil(p / 100 * times.length);
+                return times[Math.min(Math.max(rank, 1), times.length) - 1];
+            };
+            SAT.expectP95Below = function(ms) {
+                return SAT.iterationTimesMs.length > 0 && SAT.iterationPercentile(95) < ms;
+            };
+            // True for a 204 No Content response, without a body.
+            SAT.expectNoContent = function() {
+                return SAT.response.status === 204 && SAT.response.body === "";
+            };
+            // Resolves a simple JSONPath (ex: `$.data.users[0].tags`) against the response json.
+            // Throws if the response is not JSON, so that the `SAT.expect*` checks built on it fail
+            // with a clear message.
+            SAT.jsonPath = function(path) {
+                if (SAT.notJson()) {
+                    throw new Error("response body is not JSON");
+                }
+                const keys = String(path).replace(/^\$\.?/, "").match(/[^.[\]'"]+/g) || [];
+                return keys.reduce((value, key) => (value == null ? undefined : value[key]), SAT.response.json);
+            };
+            // Array checks at the given JSONPath of the response json.
+            SAT.expectArrayLength = function(path, n) {
+                const items = SAT.jsonPath(path);
+                return Array.isArray(items) && items.length === n;
+            };
+            SAT.expectEvery = function(path, predicate) {
+                const items = SAT.jsonPath(path);
+                return Array.isArray(items) && items.every((item, i) => predicate(item, i) === true);
+            };
+            SAT.expectContainsObject = function(path, fields) {
+                const items = SAT.jsonPath(path);
+                return Array.isArray(items) && items.some((item) => item !== null && typeof item === "object"
+                    && Object.keys(fields).every((key) => SAT.deepEqual(item[key], fields[key])));
+            };
+            // True if the status is in the given class, as a digit (2) or a pattern ("2xx").
+            SAT.expectStatusClass = function(statusClass) {
+                const digit = Number(String(statusClass).toLowerCase().replace(/xx$/, ""));
+                return Math.floor(SAT.response.status / 100) === digit;
+            };
+            // True for a 3xx response whose `Location` (resolved against the url of the request) is
+            // the given url, or ends with the given path (ex: `SAT.expectRedirectTo("/login")`).
+            // The redirects are only returned to the scripts with `max_redirects: 0`.
+            SAT.expectRedirectTo = function(expected) {
+                const location = SAT.response.headers["location"];
+                if (Math.floor(SAT.response.status / 100) !== 3 || location === undefined) {
+                    return false;
+                }
+                const base = String(SAT.response.url || SAT.request.url || "");
+                const origin = (base.match(/^[a-z][a-z0-9+.-]*:\/\/[^/?#]*/i) || [""])[0];
+                let resolved;
+                if (/^[a-z][a-z0-9+.-]*:/i.test(location)) {
+                    resolved = location;
+                } else if (location.startsWith("//")) {
+                    resolved = base.split(":")[0] + ":" + location;
+                } else if (location.startsWith("/")) {
+                    resolved = origin + location;
+                } else {
+                    const path = base.slice(origin.length).split(/[?#]/)[0];
+                    resolved = origin + path.slice(0, path.lastIndexOf("/") + 1) + location;
+                }
+                expected = String(expected);
+                if (/^[a-z][a-z0-9+.-]*:/i.test(expected)) {
+                    return resolved === expected;
+                }
+                return resolved.endsWith(expected);
+            };
+            // Checks on a response header, by its case insensitive name (ex:
+            // `SAT.expectHeader("ETag").matches(/".+"/)`).
+            SAT.expectHeader = function(name) {
+                const value = SAT.response.headers[String(name).toLowerCase()];
+                return {
+                    exists: () => value !== undefined,
+                    equals: (expected) => value === String(expected),
+                    matches: (re) => value !== undefined
+                        && (re instanceof RegExp ? re : new RegExp(re)).test(value),
+                };
+            };
+            // Checks on a cookie set by the response, and its attributes (by their case insensitive
+            // name), ex: `SAT.expectCookie("session").httpOnly()`, `.sameSite("Strict")`.
+            SAT.expectCookie = function(name) {
+                const cookie = SAT.response.setCookies && SAT.response.setCookies[name];
+                const attr = (attribute, expected) => {
+                    const value = cookie && cookie.attributes[String(attribute).toLowerCase()];
+                    if (value === undefined || expected === undefined) {
+                        return value !== undefined;
+                    }
+                    return String(value).toLowerCase() === String(expected).toLowerCase();
+                };
+                return {
+                    exists: () => cookie !== undefined,
+                    attr: attr,
+                    httpOnly: () => attr("HttpOnly"),
+                    secure: () => attr("Secure"),
+                    sameSite: (expected) => attr("SameSite", expected),
+                    maxAge: (expected) => attr("Max-Age", expected),
+                };
+            };
+            // True if the cookie has the attribute, with the given value if any (ex:
+            // `SAT.expectCookieAttr("session", "HttpOnly")`, `SAT.expectCookieAttr("id", "SameSite", "Lax")`).
+            SAT.expectCookieAttr = function(name, attribute, expected) {
+                return SAT.expectCookie(name).attr(attribute, expected);
+            };
+            // True if the items of the array at the path are ordered by the field ("asc" by default).
+            // Numbers are compared as numbers, ISO dates as dates, and other values as strings.
+            SAT.expectSortedBy = function(path, field, order) {
+                const items = SAT.jsonPath(path);
+                if (!Array.isArray(items)) {
+                    return false;
+                }
+                const isoDate = /^\d{4}-\d{2}-\d{2}/;
+                const key = (value) => {
+                    if (typeof value === "string" && isoDate.test(value) && !isNaN(Date.parse(value))) {
+                        return Date.parse(value);
+                    }
+                    return value;
+                };
+                const sign = order === "desc" ? -1 : 1;
+                return items.every((item, i) => {
+                    if (i === 0) {
+                        return true;
+                    }
+                    const prev = key(items[i - 1][field]);
+                    const curr = key(item[field]);
+                    return sign * (prev < curr ? -1 : prev > curr ? 1 : 0) <= 0;
+                });
+            };
+            // True if the response is in the given charset, and its body is valid in it.
+            SAT.expectCharset = function(charset) {
+                const normalize = (name) => String(name).toLowerCase().replace(/[^a-z0-9]/g, "");
+                return normalize(SAT.response.charset) === normalize(charset)
+                    && SAT.response.charsetValid === true;
+            };
+            // True if the server compressed the response body (gzip, br, deflate, ..).
+            SAT.compressionEncodings = ["gzip", "x-gzip", "br", "deflate", "zstd", "compress"];
+            SAT.expectCompressed = function() {
+                return String(SAT.response.contentEncoding || "").split(",")
+                    .some((encoding) => SAT.compressionEncodings.includes(encoding.trim()));
+            };
+            // Date checks on the value at the given JSONPath, an ISO 8601 string or epoch seconds
+            // (ex: `SAT.expectRecentTimestamp("$.createdAt", 60)`). The server's clock may be
+            // ahead of ours by up to `SAT.clockSkewSec`.
+            SAT.clockSkewSec = 5;
+            SAT.dateFormats = {
+                date: /^(\d{4})-(\d{2})-(\d{2})$/,
+                rfc3339: /^(\d{4})-(\d{2})-(\d{2})[Tt ]\d{2}:\d{2}:\d{2}(\.\d+)?([Zz]|[+-]\d{2}:\d{2})$/,
+                iso8601: /^(\d{4})-(\d{2})-(\d{2})([Tt ]\d{2}:\d{2}(:\d{2}(\.\d+)?)?([Zz]|[+-]\d{2}:?\d{2})?)?$/,
+            };
+            // The time of the value in ms, or NaN if it is not a valid date in the format
+            // (any of the ISO ones, or epoch seconds, by default).
+            SAT.parseTimestamp = function(value, format) {
+                const isEpoch = typeof value === "number" || (typeof value === "string" && /^\d+(\.\d+)?$/.test(value));
+                if (format === undefined || format === "epoch") {
+                    if (isEpoch) {
+                        return Number(value) * 1000;
+                    }
+                    if (format === "epoch") {
+                        return NaN;
+                    }
+                }
+                const match = typeof value === "string" && value.match(SAT.dateFormats[format || "iso8601"]);
+                if (!match) {
+                    return NaN;
+                }
+                // Date.parse would roll an invalid day over (ex: Feb 30 to Mar 1).
+                const [year, month, day] = match.slice(1, 4).map(Number);
+                const date = new Date(Date.UTC(year, month - 1, day));
+                if (date.getUTCMonth() !== month - 1 || date.getUTCDate() !== day) {
+                    return NaN;
+                }
+                return Date.parse(value);
+            };
+            SAT.expectValidDate = function(path, format) {
+                return !isNaN(SAT.parseTimestamp(SAT.jsonPath(path), format));
+            };
+            SAT.expectRecentTimestamp = function(path, maxAgeSec) {
+                const age = Date.now() - SAT.parseTimestamp(SAT.jsonPath(path));
+                return age >= -SAT.clockSkewSec * 1000 && age <= maxAgeSec * 1000;
+            };
+            SAT.expectFutureTimestamp = function(path) {
+                return SAT.parseTimestamp(SAT.jsonPath(path)) > Date.now();
+            };
+            // Cross-field invariants of the response json, ex: `SAT.expectFieldsRelated("$.createdAt",
+            // "<=", "$.updatedAt")`. An operand not starting with `$` is taken as is (ex: a sum
+            // computed by the script). ISO dates are compared as dates, and other values as numbers
+            // or strings; a missing value never satisfies the relation.
+            SAT.expectFieldsRelated = function(pathA, op, pathB) {
+                const operand = (path) => typeof path === "string" && path.startsWith("$") ? SAT.jsonPath(path) : path;
+                const key = (value) => {
+                    const time = typeof value === "string" ? SAT.parseTimestamp(value, "iso8601") : NaN;
+                    return isNaN(time) ? value : time;
+                };
+                const [a, b] = [operand(pathA), operand(pathB)];
+                if (a == null || b == null) {
+                    return false;
+                }
+                const [x, y] = [key(a), key(b)];
+                const equal = typeof x === "object" || typeof y === "object" ? SAT.deepEqual(x, y)
+                    : typeof x === "number" && typeof y === "number" ? Math.abs(x - y) <= SAT.numberTolerance
+                    : x === y;
+                switch (op) {
+                    case "==": return equal;
+                    case "<": return !equal && x < y;
+                    case "<=": return equal || x < y;
+                    case ">": return !equal && x > y;
+                    case ">=": return equal || x > y;
+                    default: throw new Error("Unknown operator: " + op);
+                }
+            };
+            // Schema-light checks of the value at the given JSONPath: its type, one of "number",
+            // "integer", "string", "boolean", "array", "object" or "null", and the range of a
+            // number (inclusive, ex: `SAT.expectNumberInRange("$.price", 0, 1000)`).
+            SAT.typeOf = function(value) {
+                if (value === null) {
+                    return "null";
+                }
+                return Array.isArray(value) ? "array" : typeof value;
+            };
+            SAT.expectType = function(path, type) {
+                const value = SAT.jsonPath(path);
+                if (type === "integer") {
+                    return Number.isInteger(value);
+                }
+                return SAT.typeOf(value) === type;
+            };
+            SAT.expectNumberInRange = function(path, min, max) {
+                const value = SAT.jsonPath(path);
+                return typeof value === "number" && value >= min && value <= max;
+            };
+            // Deep equality of two values, numbers are equal within SAT.numberTolerance.
+            SAT.numberTolerance = 0;
+            SAT.deepEqual = function(actual, expected) {
+                if (typeof actual === "number" && typeof expected === "number") {
+                    return Math.abs(actual - expected) <= SAT.numberTolerance;
+                }
+                if (Array.isArray(actual) || Array.isArray(expected)) {
+                    return Array.isArray(actual) && Array.isArray(expected)
+                        && actual.length === expected.length
+                        && actual.every((value, i) => SAT.deepEqual(value, expected[i]));
+                }
+                if (actual && expected && typeof actual === "object" && typeof expected === "object") {
+                    const keys = Object.keys(actual);
+                    return keys.length === Object.keys(expected).length
+                        && keys.every((key) => Object.prototype.hasOwnProperty.call(expected, key)
+                            && SAT.deepEqual(actual[key], expected[key]));
+                }
+                return actual === expected;
             };
             console.log("Done with initialization.");
         "#,
         )?;
         Ok(())
     }
+
+    // Evaluates a complete script (ex: a user supplied prelude) in the global scope.
+    // Unlike `eval`, syntax errors and uncaught exceptions are reported back as errors.
+    pub fn load_script(&mut self, name: &'static str, js_code: String) -> Result<(), AnyError> {
+        self.runtime.execute_script(name, js_code)?;
+        Ok(())
+    }
 }
 
 fn v8_value_to_serde_json(